@@ -0,0 +1,22 @@
+#![no_main]
+
+use alloy_primitives::Address;
+use libfuzzer_sys::fuzz_target;
+use rusty_evm::block::state::State;
+use rusty_evm::evm::bytecode_parser::BytecodeParser;
+use rusty_evm::evm::evm::{Contract, ExecutionContext, VM};
+use std::sync::{Arc, Mutex};
+
+// Arbitrary bytecode must never panic the parser or the interpreter;
+// malformed programs have to surface as `ParserError`/`VMError` instead.
+fuzz_target!(|data: &[u8]| {
+    let mut parser = BytecodeParser::new(data.to_vec());
+    let _ = parser.compile();
+
+    let mut vm = VM::new(
+        Contract::new(data.to_vec()),
+        ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1_000_000),
+        Arc::new(Mutex::new(State::new())),
+    );
+    let _ = vm.execute_operations(data.to_vec());
+});
@@ -1,8 +1,12 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
 use rusty_evm::blockchain::{App, Blockchain};
+use rusty_evm::config::errors::ConfigError;
 use rusty_evm::config::loader::load_toml;
 use rusty_evm::config::models::Config;
+use rusty_evm::evm::runner::{self, DEFAULT_RUN_GAS};
+use rusty_evm::transaction::units::parse_ether;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -17,21 +21,68 @@ struct Args {
 
     #[arg(short, long, default_value_t = String::from("debug"))]
     log_level: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn load_config() -> Config {
-    let cli = Args::parse();
-    let config = load_toml(cli.config_path.as_str());
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Execute a single piece of bytecode in a one-shot VM and print the result
+    Run {
+        /// Hex encoded bytecode or a path to a `.evm` file
+        bytecode: String,
+
+        /// Hex encoded calldata
+        #[arg(short, long, default_value_t = String::new())]
+        data: String,
+
+        /// Call value, e.g. `1.5 ether` or `20 gwei`; plain numbers are in wei
+        #[arg(short, long, default_value_t = String::from("0"))]
+        value: String,
+
+        #[arg(short, long, default_value_t = DEFAULT_RUN_GAS)]
+        gas: u64,
+    },
+}
 
-    env::set_var("RUST_LOG", cli.log_level);
-    config
+fn run_bytecode(
+    bytecode: &str,
+    data: &str,
+    value: &str,
+    gas: u64,
+) -> color_eyre::eyre::Result<()> {
+    let code = runner::load_bytecode(bytecode)?;
+    let calldata = runner::decode_hex(data)?;
+    let value = parse_ether(value)?.try_into()?;
+    let report = runner::run(code, calldata, value, gas)?;
+    println!("{}", report);
+    Ok(())
+}
+
+fn load_config(cli: &Args) -> Result<Config, ConfigError> {
+    let config = load_toml(cli.config_path.as_str())?;
+
+    env::set_var("RUST_LOG", &cli.log_level);
+    Ok(config)
 }
 
 fn main() -> color_eyre::eyre::Result<()> {
     color_eyre::install()?;
     dotenv::dotenv().ok();
 
-    let config = load_config();
+    let cli = Args::parse();
+    if let Some(Command::Run {
+        bytecode,
+        data,
+        value,
+        gas,
+    }) = &cli.command
+    {
+        return run_bytecode(bytecode, data, value, *gas);
+    }
+
+    let config = load_config(&cli)?;
     env_logger::init();
 
     let app_name = env!("CARGO_PKG_NAME");
@@ -39,7 +90,14 @@ fn main() -> color_eyre::eyre::Result<()> {
     log::info!("Application '{}' started.", app_name);
     log::debug!("{:#?}", config);
 
-    let mut app = App::new();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut app = App::new()
+        .with_block_reward(config.general.block_reward)
+        .with_block_time(config.general.block_time_secs)
+        .with_mempool_capacity(config.general.mempool_capacity)
+        .with_state_history(config.general.state_history)
+        .with_auto_mine(config.general.auto_mine)
+        .with_genesis_timestamp(now);
     app.run();
 
     Ok(())
@@ -1,12 +1,18 @@
-use alloy_primitives::B256;
+use alloy_primitives::{b256, B256};
 
+/// Code hash of an account without code: `keccak256("")`.
+/// `B256::ZERO` is reserved for accounts that don't exist.
+pub const EMPTY_CODE_HASH: B256 =
+    b256!("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+
+#[derive(Clone)]
 pub struct Account {
     // count of number transactions made or number of contracts made
     // only one transaction can use same nonce, to protect against replay attacks
     pub nonce: u64,
     pub balance: u64,
     // Contracts: hash of the the code?
-    // EOA: hash of empty string
+    // EOA: hash of empty string, see `EMPTY_CODE_HASH`
     pub code_hash: B256,
     // hash of the merkle patricia trie of the storage
     pub storage_root: B256,
@@ -14,7 +20,7 @@ pub struct Account {
 
 impl Default for Account {
     fn default() -> Self {
-        Self::new(0, B256::ZERO, B256::ZERO)
+        Self::new(0, EMPTY_CODE_HASH, B256::ZERO)
     }
 }
 
@@ -27,4 +33,9 @@ impl Account {
             storage_root,
         }
     }
+
+    /// An account is empty if it has no nonce, no balance and no code (EIP-161).
+    pub fn is_empty(&self) -> bool {
+        self.nonce == 0 && self.balance == 0 && self.code_hash == EMPTY_CODE_HASH
+    }
 }
@@ -1,7 +1,10 @@
-use alloy_primitives::{Address, B256};
+use alloy_primitives::{Address, Keccak256, B256};
 
+use crate::block::errors::BlockError;
 use crate::transaction::transaction::Transaction;
 
+// consensus-layer data, modelled for completeness but not yet consumed
+#[allow(dead_code)]
 struct AttestationData {
     slot: u64,
     index: u64,
@@ -12,6 +15,7 @@ struct AttestationData {
     target: u64,
 }
 
+#[allow(dead_code)]
 struct Attestation {
     aggregation_bits: Vec<u64>,
     data: AttestationData,
@@ -19,39 +23,67 @@ struct Attestation {
 }
 
 pub struct Withdrawal {
-    address: Address,
-    amount: u64,
-    index: u64,
-    validator_index: u64,
+    pub address: Address,
+    // amount in gwei
+    pub amount: u64,
+    pub index: u64,
+    pub validator_index: u64,
+}
+
+impl Withdrawal {
+    pub fn new(index: u64, validator_index: u64, address: Address, amount: u64) -> Self {
+        Self {
+            address,
+            amount,
+            index,
+            validator_index,
+        }
+    }
 }
 
 struct ExecutionPayload {
+    #[allow(dead_code)]
     parent_hash: B256,
     fee_recipient: Address,
+    #[allow(dead_code)]
     state_root: B256,
+    #[allow(dead_code)]
     receipts_root: B256,
+    #[allow(dead_code)]
     logs_bloom: Vec<String>,
-    prev_randao: u64,
+    prev_randao: B256,
+    #[allow(dead_code)]
     block_number: u64,
     gas_limit: u64,
     gas_used: u64,
     timestamp: u64,
+    #[allow(dead_code)]
     extra_data: Vec<u8>,
     base_fee_per_gas: u64,
+    #[allow(dead_code)]
     block_hash: B256,
     transactions: Vec<Transaction>,
     withdrawals: Vec<Withdrawal>,
 }
 
 struct BlockBody {
+    #[allow(dead_code)]
     randao_reveal: u64,
+    #[allow(dead_code)]
     eth1_data: B256,
+    #[allow(dead_code)]
     graffiti: B256,
+    #[allow(dead_code)]
     proposer_slashings: Vec<u64>,
+    #[allow(dead_code)]
     attester_slashings: Vec<u64>,
+    #[allow(dead_code)]
     attestations: Vec<Attestation>,
+    #[allow(dead_code)]
     deposits: Vec<u64>,
+    #[allow(dead_code)]
     voluntary_exits: Vec<u64>,
+    #[allow(dead_code)]
     sync_aggregate: Vec<u64>,
     execution_payload: ExecutionPayload,
 }
@@ -92,6 +124,145 @@ impl Block {
     pub fn add_withdrawal(&mut self, withdrawal: Withdrawal) {
         self.body.execution_payload.withdrawals.push(withdrawal);
     }
+
+    /// Sets the address that receives the priority fees of the block.
+    pub fn set_fee_recipient(&mut self, fee_recipient: Address) {
+        self.body.execution_payload.fee_recipient = fee_recipient;
+    }
+
+    /// Returns the address that receives the priority fees of the block.
+    pub fn fee_recipient(&self) -> Address {
+        self.body.execution_payload.fee_recipient
+    }
+
+    /// Returns the withdrawals included in the block's execution payload.
+    pub fn withdrawals(&self) -> &[Withdrawal] {
+        &self.body.execution_payload.withdrawals
+    }
+
+    /// Sets the maximum amount of gas the block's transactions may consume.
+    pub fn set_gas_limit(&mut self, gas_limit: u64) {
+        self.body.execution_payload.gas_limit = gas_limit;
+    }
+
+    /// Returns the maximum amount of gas the block's transactions may consume.
+    pub fn gas_limit(&self) -> u64 {
+        self.body.execution_payload.gas_limit
+    }
+
+    /// Adds the gas consumed by an included transaction to the block total.
+    pub fn add_gas_used(&mut self, gas_used: u64) {
+        self.body.execution_payload.gas_used += gas_used;
+    }
+
+    /// Returns the gas consumed by the block's transactions.
+    pub fn gas_used(&self) -> u64 {
+        self.body.execution_payload.gas_used
+    }
+
+    /// Returns the gas still available to transactions added to the block.
+    pub fn remaining_gas(&self) -> u64 {
+        self.gas_limit().saturating_sub(self.gas_used())
+    }
+
+    /// Sets the base fee per gas paid by the block's transactions.
+    pub fn set_base_fee_per_gas(&mut self, base_fee_per_gas: u64) {
+        self.body.execution_payload.base_fee_per_gas = base_fee_per_gas;
+    }
+
+    /// Returns the base fee per gas paid by the block's transactions.
+    pub fn base_fee_per_gas(&self) -> u64 {
+        self.body.execution_payload.base_fee_per_gas
+    }
+
+    /// Sets the randomness mixed in by the beacon chain, exposed to contracts by PREVRANDAO.
+    pub fn set_prev_randao(&mut self, prev_randao: B256) {
+        self.body.execution_payload.prev_randao = prev_randao;
+    }
+
+    /// Returns the randomness mixed in by the beacon chain.
+    pub fn prev_randao(&self) -> B256 {
+        self.body.execution_payload.prev_randao
+    }
+
+    /// Sets the time the block was produced at, in seconds since the Unix epoch.
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.body.execution_payload.timestamp = timestamp;
+    }
+
+    /// Returns the time the block was produced at, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.body.execution_payload.timestamp
+    }
+
+    /// Returns the slot the block was proposed in.
+    pub fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    /// Sets the root of the state after executing the block.
+    pub fn set_state_root(&mut self, state_root: B256) {
+        self.state_root = state_root;
+    }
+
+    /// Returns the root of the state after executing the block.
+    pub fn state_root(&self) -> B256 {
+        self.state_root
+    }
+
+    /// Returns the root of the parent block.
+    pub fn parent_root(&self) -> B256 {
+        self.parent_root
+    }
+
+    /// Checks the block against its parent and the state computed by re-executing it.
+    pub fn verify(&self, parent_root: B256, computed_state_root: B256) -> Result<(), BlockError> {
+        if self.parent_root != parent_root {
+            return Err(BlockError::ParentRootMismatch);
+        }
+        if self.state_root != computed_state_root {
+            return Err(BlockError::StateRootMismatch);
+        }
+        if self.gas_used() > self.gas_limit() {
+            return Err(BlockError::GasLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Hash identifying the block: covers the header fields, those of the execution payload,
+    /// the included transactions and the withdrawals.
+    pub fn hash(&self) -> B256 {
+        let payload = &self.body.execution_payload;
+        let mut hasher = Keccak256::new();
+        hasher.update(self.slot.to_be_bytes());
+        hasher.update(self.proposer_index.to_be_bytes());
+        hasher.update(self.parent_root);
+        hasher.update(self.state_root);
+        hasher.update(payload.fee_recipient);
+        hasher.update(payload.prev_randao);
+        hasher.update(payload.gas_limit.to_be_bytes());
+        hasher.update(payload.gas_used.to_be_bytes());
+        hasher.update(payload.timestamp.to_be_bytes());
+        hasher.update(payload.base_fee_per_gas.to_be_bytes());
+        // the counts keep the boundary between the transactions and the withdrawals unambiguous
+        hasher.update((payload.transactions.len() as u64).to_be_bytes());
+        for transaction in self.transactions() {
+            hasher.update(transaction.hash());
+        }
+        hasher.update((payload.withdrawals.len() as u64).to_be_bytes());
+        for withdrawal in self.withdrawals() {
+            hasher.update(withdrawal.index.to_be_bytes());
+            hasher.update(withdrawal.validator_index.to_be_bytes());
+            hasher.update(withdrawal.address);
+            hasher.update(withdrawal.amount.to_be_bytes());
+        }
+        hasher.finalize()
+    }
+
+    /// Returns the transactions included in the block's execution payload.
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.body.execution_payload.transactions
+    }
 }
 
 impl BlockBody {
@@ -121,7 +292,7 @@ impl ExecutionPayload {
             state_root: B256::default(),
             receipts_root: B256::default(),
             logs_bloom: Vec::new(),
-            prev_randao: 0,
+            prev_randao: B256::default(),
             block_number: 0,
             gas_limit: 0,
             gas_used: 0,
@@ -134,3 +305,55 @@ impl ExecutionPayload {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let parent_root = B256::repeat_byte(1);
+        let state_root = B256::repeat_byte(2);
+        let mut block = Block::new(1, 0, parent_root, state_root);
+        block.set_gas_limit(30_000_000);
+        block.add_gas_used(21_000);
+
+        assert_eq!(block.verify(parent_root, state_root), Ok(()));
+        assert_eq!(
+            block.verify(parent_root, B256::ZERO),
+            Err(BlockError::StateRootMismatch)
+        );
+        assert_eq!(
+            block.verify(B256::ZERO, state_root),
+            Err(BlockError::ParentRootMismatch)
+        );
+
+        block.add_gas_used(30_000_000);
+        assert_eq!(
+            block.verify(parent_root, state_root),
+            Err(BlockError::GasLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn test_hash_covers_payload() {
+        let block = || Block::new(1, 0, B256::repeat_byte(1), B256::repeat_byte(2));
+        let hash = block().hash();
+        assert_eq!(block().hash(), hash);
+
+        let changes: Vec<fn(&mut Block)> = vec![
+            |block| block.set_fee_recipient(Address::repeat_byte(3)),
+            |block| block.set_prev_randao(B256::repeat_byte(4)),
+            |block| block.set_gas_limit(30_000_000),
+            |block| block.add_gas_used(21_000),
+            |block| block.set_timestamp(12),
+            |block| block.set_base_fee_per_gas(7),
+            |block| block.add_withdrawal(Withdrawal::new(0, 1, Address::repeat_byte(5), 32)),
+        ];
+        for change in changes {
+            let mut changed = block();
+            change(&mut changed);
+            assert_ne!(changed.hash(), hash);
+        }
+    }
+}
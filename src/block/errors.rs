@@ -0,0 +1,11 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BlockError {
+    #[error("state root doesn't match the post-execution state")]
+    StateRootMismatch,
+    #[error("parent root doesn't match the parent block")]
+    ParentRootMismatch,
+    #[error("gas used exceeds the block gas limit")]
+    GasLimitExceeded,
+}
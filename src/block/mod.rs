@@ -1,3 +1,6 @@
 pub mod account;
+#[allow(clippy::module_inception)]
 pub mod block;
-pub mod state;
\ No newline at end of file
+pub mod errors;
+pub mod state;
+pub mod state_diff;
\ No newline at end of file
@@ -1,9 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::block::account::Account;
+use crate::block::account::{Account, EMPTY_CODE_HASH};
 use crate::evm::evm::Contract;
-use alloy_primitives::{Address, B256};
+use crate::evm::precompiles;
+use alloy_primitives::{Address, Keccak256, B256};
 
+#[derive(Clone)]
 pub struct State {
     pub accounts: HashMap<Address, Account>,
     pub storage: HashMap<(Address, B256), B256>,
@@ -25,6 +27,17 @@ impl State {
         }
     }
 
+    /// Seeds an account without code at every precompile address, so they exist like on
+    /// mainnet. They are empty, but never considered dead, see `is_dead`.
+    pub fn with_precompiles(mut self) -> Self {
+        for address in precompiles::reserved_addresses() {
+            self.accounts
+                .entry(address)
+                .or_insert_with(|| Account::new(0, EMPTY_CODE_HASH, B256::ZERO));
+        }
+        self
+    }
+
     pub fn get_account(&mut self, address: &Address) -> Option<&mut Account> {
         self.accounts.get_mut(address)
     }
@@ -33,6 +46,33 @@ impl State {
         self.accounts.insert(address, account);
     }
 
+    /// Iterates over all the accounts in address order.
+    pub fn accounts_iter(&self) -> impl Iterator<Item = (&Address, &Account)> {
+        let accounts: BTreeMap<_, _> = self.accounts.iter().collect();
+        accounts.into_iter()
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Whether the account at `address` is missing, or empty and without contract code
+    /// (EIP-161). The precompiles always exist.
+    pub fn is_dead(&self, address: &Address) -> bool {
+        let empty = self
+            .accounts
+            .get(address)
+            .is_none_or(|account| account.is_empty());
+        empty && !self.contract.contains_key(address) && !precompiles::is_reserved(address)
+    }
+
+    /// Removes the account at `address` if it is dead (EIP-161).
+    pub fn remove_if_empty(&mut self, address: &Address) {
+        if self.is_dead(address) {
+            self.accounts.remove(address);
+        }
+    }
+
     pub fn get_storage(&self, address: &Address, key: &B256) -> B256 {
         self.storage
             .get(&(*address, *key))
@@ -40,8 +80,53 @@ impl State {
             .unwrap_or_default()
     }
 
+    /// Writes a storage slot of the contract at `address`. Writing zero clears the slot.
     pub fn set_storage(&mut self, address: Address, key: B256, value: B256) {
-        self.storage.insert((address, key), value);
+        if value.is_zero() {
+            self.storage.remove(&(address, key));
+        } else {
+            self.storage.insert((address, key), value);
+        }
+    }
+
+    /// Iterates over the non-zero storage slots of the contract at `address` in slot order.
+    pub fn iter_storage(&self, address: &Address) -> impl Iterator<Item = (B256, B256)> {
+        let slots: BTreeMap<_, _> = self
+            .storage
+            .iter()
+            .filter(|((owner, _), _)| owner == address)
+            .map(|((_, key), value)| (*key, *value))
+            .collect();
+        slots.into_iter()
+    }
+
+    /// Hash committing to the accounts, storage and contracts. Entries are hashed in address
+    /// and slot order so the root doesn't depend on `HashMap` iteration order.
+    pub fn root(&self) -> B256 {
+        let mut hasher = Keccak256::new();
+
+        for (address, account) in self.accounts_iter() {
+            hasher.update(address);
+            hasher.update(account.nonce.to_be_bytes());
+            hasher.update(account.balance.to_be_bytes());
+            hasher.update(account.code_hash);
+            hasher.update(account.storage_root);
+        }
+
+        let storage: BTreeMap<_, _> = self.storage.iter().collect();
+        for ((address, key), value) in storage {
+            hasher.update(address);
+            hasher.update(key);
+            hasher.update(value);
+        }
+
+        let contracts: BTreeMap<_, _> = self.contract.iter().collect();
+        for (address, contract) in contracts {
+            hasher.update(address);
+            hasher.update(&contract.code);
+        }
+
+        hasher.finalize()
     }
 }
 
@@ -49,9 +134,10 @@ impl State {
 mod tests {
     use std::sync::{Arc, Mutex};
     use super::*;
-    use crate::block::account::Account;
+    use crate::block::account::{Account, EMPTY_CODE_HASH};
     use crate::crypto::wallet::Wallet;
     use crate::transaction::transaction::{Transaction, ETH_TO_WEI, TRANSACTION_GAS_COST};
+    use crate::evm::evm::BlockEnv;
     use crate::evm::executor::Executor;
 
     #[test]
@@ -60,13 +146,12 @@ mod tests {
         let eth_wallet_receiver = Wallet::generate();
 
         let state_arc = Arc::new(Mutex::new(State::new()));
-        let mut state = state_arc.lock().unwrap();
 
         {
+            let mut state = state_arc.lock().unwrap();
             assert!(state.get_account(&eth_wallet_receiver.address).is_none());
             assert!(state.get_account(&eth_wallet_sender.address).is_none());
-        }
-        {
+
             state.set_account(eth_wallet_sender.address, Account::default());
             let sender = state.get_account(&eth_wallet_sender.address).unwrap();
 
@@ -85,8 +170,9 @@ mod tests {
         );
 
         let base_fee = 10;
-        Executor::process_transaction(&tx, base_fee, state_arc.clone()).unwrap();
+        Executor::process_transaction(&tx, &BlockEnv::new(0, base_fee), state_arc.clone()).unwrap();
 
+        let mut state = state_arc.lock().unwrap();
         let sender_balance = state
             .get_account(&eth_wallet_sender.address)
             .unwrap()
@@ -104,4 +190,79 @@ mod tests {
         );
         assert_eq!(recv_balance, ETH_TO_WEI);
     }
+
+    #[test]
+    fn test_accounts_iter_is_ordered_by_address() {
+        let mut state = State::new();
+        for byte in [0x30, 0x10, 0x20] {
+            state.set_account(
+                Address::repeat_byte(byte),
+                Account::new(byte as u64, EMPTY_CODE_HASH, B256::ZERO),
+            );
+        }
+
+        assert_eq!(state.account_count(), 3);
+        let accounts: Vec<(Address, u64)> = state
+            .accounts_iter()
+            .map(|(address, account)| (*address, account.balance))
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![
+                (Address::repeat_byte(0x10), 0x10),
+                (Address::repeat_byte(0x20), 0x20),
+                (Address::repeat_byte(0x30), 0x30),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_root_is_independent_of_insertion_order() {
+        let entries: Vec<(Address, Account)> = (0..32u64)
+            .map(|i| (Wallet::generate().address, Account::new(i, EMPTY_CODE_HASH, B256::ZERO)))
+            .collect();
+
+        let mut first = State::new();
+        for (address, account) in &entries {
+            first.set_account(*address, account.clone());
+            first.set_storage(*address, B256::with_last_byte(1), B256::with_last_byte(2));
+        }
+        let mut second = State::new();
+        for (address, account) in entries.iter().rev() {
+            second.set_account(*address, account.clone());
+            second.set_storage(*address, B256::with_last_byte(1), B256::with_last_byte(2));
+        }
+
+        assert_eq!(first.root(), first.root());
+        assert_eq!(first.root(), second.root());
+
+        second.set_storage(entries[0].0, B256::with_last_byte(1), B256::ZERO);
+        assert_ne!(first.root(), second.root());
+    }
+
+    #[test]
+    fn test_iter_storage_is_ordered_by_slot() {
+        let contract = Address::repeat_byte(0xcc);
+        let mut state = State::new();
+        state.set_storage(contract, B256::with_last_byte(3), B256::with_last_byte(30));
+        state.set_storage(contract, B256::with_last_byte(1), B256::with_last_byte(10));
+        state.set_storage(contract, B256::repeat_byte(0xff), B256::with_last_byte(1));
+        // cleared slots and the slots of other contracts are left out
+        state.set_storage(contract, B256::with_last_byte(2), B256::ZERO);
+        state.set_storage(
+            Address::repeat_byte(0xdd),
+            B256::with_last_byte(2),
+            B256::with_last_byte(20),
+        );
+
+        assert_eq!(
+            state.iter_storage(&contract).collect::<Vec<_>>(),
+            vec![
+                (B256::with_last_byte(1), B256::with_last_byte(10)),
+                (B256::with_last_byte(3), B256::with_last_byte(30)),
+                (B256::repeat_byte(0xff), B256::with_last_byte(1)),
+            ]
+        );
+        assert_eq!(state.iter_storage(&Address::ZERO).count(), 0);
+    }
 }
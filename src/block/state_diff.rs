@@ -0,0 +1,135 @@
+// Changes between two states of the chain, as reported to explorers and debuggers.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use alloy_primitives::{Address, B256};
+
+use crate::block::account::Account;
+use crate::block::state::State;
+
+/// A value before and after the change.
+pub type Change<T> = (T, T);
+
+/// Changes to a single account. A missing account reads as an empty one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountDiff {
+    pub balance: Option<Change<u64>>,
+    pub nonce: Option<Change<u64>>,
+    pub code_hash: Option<Change<B256>>,
+    // changed slots, a cleared slot reads as zero
+    pub storage: BTreeMap<B256, Change<B256>>,
+}
+
+impl AccountDiff {
+    /// Signed change of the balance, zero if it is unchanged.
+    pub fn balance_delta(&self) -> i128 {
+        self.balance
+            .map_or(0, |(before, after)| after as i128 - before as i128)
+    }
+
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Accounts whose balance, nonce, code or storage differ between two states.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateDiff {
+    pub accounts: BTreeMap<Address, AccountDiff>,
+}
+
+impl StateDiff {
+    pub fn between(before: &State, after: &State) -> Self {
+        let mut addresses: BTreeSet<Address> = BTreeSet::new();
+        // the storage is keyed by (address, slot), so group the slots by address in one pass
+        let mut storage_keys: BTreeMap<Address, BTreeSet<B256>> = BTreeMap::new();
+        for state in [before, after] {
+            addresses.extend(state.accounts.keys());
+            addresses.extend(state.contract.keys());
+            for (address, key) in state.storage.keys() {
+                storage_keys.entry(*address).or_default().insert(*key);
+            }
+        }
+        addresses.extend(storage_keys.keys());
+
+        let mut accounts = BTreeMap::new();
+        for address in addresses {
+            let (old, new) = (account(before, &address), account(after, &address));
+            let mut diff = AccountDiff {
+                balance: changed(old.balance, new.balance),
+                nonce: changed(old.nonce, new.nonce),
+                code_hash: changed(code_hash(before, &address), code_hash(after, &address)),
+                storage: BTreeMap::new(),
+            };
+
+            let keys = storage_keys.remove(&address).unwrap_or_default();
+            for key in keys {
+                let old = before.get_storage(&address, &key);
+                let new = after.get_storage(&address, &key);
+                if let Some(change) = changed(old, new) {
+                    diff.storage.insert(key, change);
+                }
+            }
+
+            if !diff.is_empty() {
+                accounts.insert(address, diff);
+            }
+        }
+        Self { accounts }
+    }
+}
+
+fn changed<T: PartialEq>(before: T, after: T) -> Option<Change<T>> {
+    (before != after).then_some((before, after))
+}
+
+fn account(state: &State, address: &Address) -> Account {
+    state.accounts.get(address).cloned().unwrap_or_default()
+}
+
+/// Hash of the code at `address`, taken from the contract for accounts holding one.
+fn code_hash(state: &State, address: &Address) -> B256 {
+    match state.contract.get(address) {
+        Some(contract) => contract.code_hash(),
+        None => account(state, address).code_hash,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::EMPTY_CODE_HASH;
+    use crate::evm::evm::Contract;
+
+    #[test]
+    fn test_diff_of_storage_and_code() {
+        let contract = Address::repeat_byte(0xcc);
+        let mut before = State::new();
+        before.set_storage(contract, B256::ZERO, B256::with_last_byte(1));
+        before.set_storage(contract, B256::with_last_byte(1), B256::with_last_byte(2));
+
+        let mut after = before.clone();
+        after.set_storage(contract, B256::ZERO, B256::ZERO);
+        after.set_storage(contract, B256::with_last_byte(2), B256::with_last_byte(3));
+        let code = Contract::new(vec![0x00]);
+        after.contract.insert(contract, code.clone());
+
+        let diff = StateDiff::between(&before, &after);
+        let account = &diff.accounts[&contract];
+        assert_eq!(account.balance, None);
+        assert_eq!(account.nonce, None);
+        assert_eq!(account.code_hash, Some((EMPTY_CODE_HASH, code.code_hash())));
+        assert_eq!(
+            account.storage,
+            BTreeMap::from([
+                (B256::ZERO, (B256::with_last_byte(1), B256::ZERO)),
+                (
+                    B256::with_last_byte(2),
+                    (B256::ZERO, B256::with_last_byte(3))
+                ),
+            ])
+        );
+
+        assert!(StateDiff::between(&after, &after).accounts.is_empty());
+    }
+}
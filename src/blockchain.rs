@@ -1,15 +1,71 @@
 use alloy_primitives::B256;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 
 use crate::block::block::Block;
 use crate::block::state::State;
+use crate::block::state_diff::StateDiff;
+use crate::crypto::hash::hash_slice_to_b256;
 use crate::crypto::wallet::Wallet;
+use crate::evm::evm::BlockEnv;
 use crate::evm::executor::Executor;
+use crate::mempool::Mempool;
+use crate::transaction::errors::TransactionError;
+use crate::transaction::receipt::Receipt;
 use crate::transaction::transaction::Transaction;
 
+/// Gas limit of a block, twice the gas target.
+pub const BLOCK_GAS_LIMIT: u64 = 30_000_000;
+/// Base fee of the first block.
+pub const INITIAL_BASE_FEE: u64 = 10;
+/// Seconds between two blocks, as on mainnet since the merge.
+pub const DEFAULT_BLOCK_TIME_SECS: u64 = 12;
+/// Number of recent blocks whose hash is available to BLOCKHASH.
+const BLOCK_HASH_HISTORY: usize = 256;
+/// Number of recent states kept for `App::state_diff` by default.
+pub const DEFAULT_STATE_HISTORY: usize = 128;
+/// Bounds the base fee change between two blocks to 1/8 (EIP-1559).
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+
+/// Computes the base fee of the block following a block with the given base fee and gas usage.
+///
+/// The base fee rises when the parent used more than its gas target (half the gas limit) and
+/// falls when it used less, by at most 1/8 per block (EIP-1559).
+pub fn next_base_fee(base_fee: u64, gas_used: u64, gas_limit: u64) -> u64 {
+    let gas_target = gas_limit / 2;
+    if gas_target == 0 || gas_used == gas_target {
+        return base_fee;
+    }
+
+    if gas_used > gas_target {
+        let delta = (base_fee as u128 * (gas_used - gas_target) as u128
+            / gas_target as u128
+            / BASE_FEE_MAX_CHANGE_DENOMINATOR as u128) as u64;
+        base_fee + delta.max(1)
+    } else {
+        let delta = (base_fee as u128 * (gas_target - gas_used) as u128
+            / gas_target as u128
+            / BASE_FEE_MAX_CHANGE_DENOMINATOR as u128) as u64;
+        base_fee - delta
+    }
+}
+
+/// Fees paid in a range of recent blocks, oldest first, as reported by `eth_feeHistory`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeHistory {
+    pub oldest_block: u64,
+    // one entry per block, followed by the base fee of the next block
+    pub base_fee_per_gas: Vec<u64>,
+    pub gas_used_ratio: Vec<f64>,
+    // for each block, the priority fee per gas at each of the requested percentiles
+    pub reward: Vec<Vec<u64>>,
+}
+
 pub trait Blockchain {
     fn run(&mut self);
-    fn execute_transactions(&mut self);
+    fn produce_block(&mut self);
+    fn execute_transactions(&mut self, block: &mut Block);
+    fn finalize_block(&mut self, block: &Block);
     fn get_next_block(&self) -> Block;
 }
 
@@ -17,11 +73,32 @@ pub struct App {
     state: Arc<Mutex<State>>,
     tx_send: std::sync::mpsc::Sender<Transaction>,
     tx_recv: std::sync::mpsc::Receiver<Transaction>,
+    mempool: Mempool,
     account: Wallet,
     running: bool,
     blocks: Vec<Block>,
-    slot: u64,
+    gas_limit: u64,
     base_fee: u64,
+    burnt_fees: u64,
+    // amount credited to the fee recipient of every block, zero since the merge
+    block_reward: u64,
+    issuance: u64,
+    // transaction hash -> (block height, index in block, receipt)
+    included_transactions: HashMap<B256, (usize, usize, Receipt)>,
+    // block hash -> block height
+    block_heights: HashMap<B256, usize>,
+    // the state at each of the most recent heights, i.e. after that many blocks, oldest first
+    state_history: VecDeque<State>,
+    // number of states kept in state_history
+    state_history_limit: usize,
+    // the PREVRANDAO of every block is derived from this seed and the block height
+    randao_seed: B256,
+    // each block is timestamped block_time seconds after its parent, the first one at
+    // genesis_timestamp
+    block_time: u64,
+    genesis_timestamp: u64,
+    // produce a block as soon as a raw transaction is sent, for development
+    auto_mine: bool,
 }
 
 impl Default for App {
@@ -35,16 +112,249 @@ impl App {
         let (tx_send, tx_recv) = std::sync::mpsc::channel();
 
         Self {
-            state: Arc::new(Mutex::new(State::new())),
+            state: Arc::new(Mutex::new(State::new().with_precompiles())),
             tx_send,
             tx_recv,
+            mempool: Mempool::new(),
             account: Wallet::generate(),
             running: true,
             blocks: vec![],
-            slot: 0,
-            base_fee: 10,
+            gas_limit: BLOCK_GAS_LIMIT,
+            base_fee: INITIAL_BASE_FEE,
+            burnt_fees: 0,
+            block_reward: 0,
+            issuance: 0,
+            included_transactions: HashMap::new(),
+            block_heights: HashMap::new(),
+            state_history: VecDeque::new(),
+            state_history_limit: DEFAULT_STATE_HISTORY,
+            randao_seed: B256::ZERO,
+            block_time: DEFAULT_BLOCK_TIME_SECS,
+            genesis_timestamp: 0,
+            auto_mine: false,
         }
     }
+
+    /// Sets the gas limit of the blocks produced by the node.
+    pub fn with_gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    /// Sets the reward credited to the fee recipient of every produced block, to simulate the
+    /// issuance of a chain before the merge.
+    pub fn with_block_reward(mut self, block_reward: u64) -> Self {
+        self.block_reward = block_reward;
+        self
+    }
+
+    /// Sets the number of recent states kept for `state_diff`, older ones are dropped as blocks
+    /// are produced. The current state is always kept.
+    pub fn with_state_history(mut self, limit: usize) -> Self {
+        self.state_history_limit = limit.max(1);
+        self
+    }
+
+    /// Sets the maximum number of transactions waiting in the mempool.
+    pub fn with_mempool_capacity(mut self, capacity: usize) -> Self {
+        self.mempool = self.mempool.with_capacity(capacity);
+        self
+    }
+
+    /// Sets the seed the PREVRANDAO of the produced blocks is derived from. The node has no
+    /// beacon chain, so the randomness is deterministic: `keccak256(seed ++ height)`.
+    pub fn with_randao_seed(mut self, randao_seed: B256) -> Self {
+        self.randao_seed = randao_seed;
+        self
+    }
+
+    /// Sets the number of seconds between two blocks, both in their timestamps and in the pace
+    /// at which `run` produces them.
+    pub fn with_block_time(mut self, block_time_secs: u64) -> Self {
+        self.block_time = block_time_secs;
+        self
+    }
+
+    /// Sets the timestamp of the first block. The timestamps of the following blocks don't
+    /// depend on the wall clock, so a chain produced from the same inputs is reproducible.
+    pub fn with_genesis_timestamp(mut self, genesis_timestamp: u64) -> Self {
+        self.genesis_timestamp = genesis_timestamp;
+        self
+    }
+
+    /// Produces a block including every transaction sent through `send_raw_transaction` as
+    /// soon as it is sent, rather than waiting for the next slot.
+    pub fn with_auto_mine(mut self, auto_mine: bool) -> Self {
+        self.auto_mine = auto_mine;
+        self
+    }
+
+    /// Last block of the chain, if any was produced.
+    pub fn head(&self) -> Option<&Block> {
+        self.blocks.last()
+    }
+
+    /// Number of blocks in the chain, which is also the slot of the next block.
+    pub fn height(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+
+    /// Looks up a block by its height.
+    pub fn get_block_by_number(&self, number: u64) -> Option<&Block> {
+        self.blocks.get(usize::try_from(number).ok()?)
+    }
+
+    /// Looks up a block by its hash.
+    pub fn get_block_by_hash(&self, hash: B256) -> Option<&Block> {
+        let height = self.block_heights.get(&hash)?;
+        Some(&self.blocks[*height])
+    }
+
+    /// Base fee per gas of the next block.
+    pub fn base_fee(&self) -> u64 {
+        self.base_fee
+    }
+
+    /// Returns a handle to the world state of the node.
+    pub fn state(&self) -> Arc<Mutex<State>> {
+        self.state.clone()
+    }
+
+    /// Total amount of base fees burned since the node started.
+    pub fn burnt_fees(&self) -> u64 {
+        self.burnt_fees
+    }
+
+    /// Total amount issued as block rewards since the node started.
+    pub fn issuance(&self) -> u64 {
+        self.issuance
+    }
+
+    /// Looks up an included transaction by its hash.
+    ///
+    /// Returns the height of the block that included it, the transaction and its receipt.
+    pub fn get_transaction(&self, hash: B256) -> Option<(u64, &Transaction, &Receipt)> {
+        let (height, index, receipt) = self.included_transactions.get(&hash)?;
+        let transaction = &self.blocks[*height].transactions()[*index];
+        Some((*height as u64, transaction, receipt))
+    }
+
+    /// Summarizes the fees paid in the last `block_count` blocks.
+    ///
+    /// The reward at percentile `p` is the priority fee per gas of the first transaction, by
+    /// ascending priority fee, at which the cumulative gas used reaches `p`% of the gas used by
+    /// the block. Empty blocks report a reward of zero.
+    pub fn fee_history(&self, block_count: u64, percentiles: &[f64]) -> FeeHistory {
+        let oldest_block = self.height().saturating_sub(block_count);
+        let blocks = &self.blocks[oldest_block as usize..];
+
+        let mut base_fee_per_gas: Vec<u64> = blocks.iter().map(Block::base_fee_per_gas).collect();
+        base_fee_per_gas.push(self.base_fee);
+
+        let gas_used_ratio = blocks
+            .iter()
+            .map(|block| match block.gas_limit() {
+                0 => 0.0,
+                gas_limit => block.gas_used() as f64 / gas_limit as f64,
+            })
+            .collect();
+
+        let reward = blocks
+            .iter()
+            .map(|block| {
+                let mut fees: Vec<(u64, u64)> = block
+                    .transactions()
+                    .iter()
+                    .filter_map(|tx| self.included_transactions.get(&tx.hash()))
+                    .map(|(_, _, receipt)| (receipt.priority_fee_per_gas, receipt.gas_used))
+                    .collect();
+                fees.sort_unstable();
+
+                let gas_used: u64 = fees.iter().map(|(_, gas_used)| gas_used).sum();
+                percentiles
+                    .iter()
+                    .map(|percentile| {
+                        let threshold = gas_used as f64 * percentile / 100.0;
+                        let mut cumulative_gas = 0;
+                        fees.iter()
+                            .find(|(_, gas_used)| {
+                                cumulative_gas += gas_used;
+                                cumulative_gas as f64 >= threshold
+                            })
+                            .map_or(0, |(priority_fee, _)| *priority_fee)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        FeeHistory {
+            oldest_block,
+            base_fee_per_gas,
+            gas_used_ratio,
+            reward,
+        }
+    }
+
+    /// Environment for executing the transactions of `block`, including the hashes of the
+    /// most recent blocks before it. Used both to produce a block and to replay it with
+    /// `Executor::execute_block`.
+    pub fn block_env(&self, block: &Block) -> BlockEnv {
+        let mut env = BlockEnv::new(block.slot(), block.base_fee_per_gas());
+        env.prev_randao = block.prev_randao();
+        env.timestamp = block.timestamp();
+        let parents = &self.blocks[..self.blocks.len().min(block.slot() as usize)];
+        let first = parents.len().saturating_sub(BLOCK_HASH_HISTORY);
+        for (number, parent) in parents.iter().enumerate().skip(first) {
+            env.block_hashes.insert(number as u64, parent.hash());
+        }
+        env
+    }
+
+    /// Changes to the state between two heights, `None` if either is above the current height
+    /// or older than the kept history, see `with_state_history`. The state at a height is the
+    /// state after that many blocks.
+    pub fn state_diff(&self, from_height: u64, to_height: u64) -> Option<StateDiff> {
+        let from = self.historical_state(from_height)?;
+        let to = self.historical_state(to_height)?;
+        Some(StateDiff::between(from, to))
+    }
+
+    fn historical_state(&self, height: u64) -> Option<&State> {
+        // the last kept state is the one at the current height
+        let oldest_height = (self.height() + 1).checked_sub(self.state_history.len() as u64)?;
+        let index = height.checked_sub(oldest_height)?;
+        self.state_history.get(usize::try_from(index).ok()?)
+    }
+
+    /// Returns a handle for submitting transactions to the node.
+    pub fn transaction_sender(&self) -> std::sync::mpsc::Sender<Transaction> {
+        self.tx_send.clone()
+    }
+
+    /// Submits a signed transaction in its network encoding, see `Transaction::to_rlp`.
+    ///
+    /// The transaction is rejected if it can't be decoded or its signature is invalid, the
+    /// remaining checks happen when it reaches the mempool. Returns the transaction hash.
+    pub fn submit_raw(&self, bytes: &[u8]) -> Result<B256, TransactionError> {
+        let transaction = Transaction::from_rlp(bytes)?;
+        transaction.recover_sender()?;
+
+        let hash = transaction.hash();
+        self.tx_send
+            .send(transaction)
+            .expect("the node owns the receiving end of the channel");
+        Ok(hash)
+    }
+
+    /// Submits a transaction like `submit_raw`. In auto-mine mode, a block including it is
+    /// produced before returning.
+    pub fn send_raw_transaction(&mut self, bytes: &[u8]) -> Result<B256, TransactionError> {
+        let hash = self.submit_raw(bytes)?;
+        if self.auto_mine {
+            self.produce_block();
+        }
+        Ok(hash)
+    }
 }
 
 impl Blockchain for App {
@@ -52,18 +362,12 @@ impl Blockchain for App {
         use std::thread;
         use std::time::{Duration, Instant};
 
-        let target_block_time = Duration::from_secs(12);
+        let target_block_time = Duration::from_secs(self.block_time);
 
         while self.running {
             let start_time = Instant::now();
 
-            self.execute_transactions();
-
-            // Generate and push the next block
-            let new_block = self.get_next_block();
-            self.blocks.push(new_block);
-            self.slot += 1;
-            log::info!("Block {} generated.", self.slot);
+            self.produce_block();
 
             // Sleep to maintain the target block time
             let elapsed_time = start_time.elapsed();
@@ -73,18 +377,819 @@ impl Blockchain for App {
         }
     }
 
-    fn execute_transactions(&mut self) {
-        if let Ok(tx) = self.tx_recv.try_recv() {
-            if Executor::process_transaction(&tx, self.base_fee, self.state.clone()).is_err() {
-                log::error!("Transaction failed.");
-            };
+    fn produce_block(&mut self) {
+        if self.state_history.is_empty() {
+            self.state_history
+                .push_back(self.state.lock().unwrap().clone());
         }
+
+        // Generate the next block, fill it and push it
+        let mut block = self.get_next_block();
+        self.execute_transactions(&mut block);
+        self.finalize_block(&block);
+        // the block commits to the state it leaves behind, which its hash covers
+        block.set_state_root(self.state.lock().unwrap().root());
+        self.block_heights.insert(block.hash(), self.blocks.len());
+        self.blocks.push(block);
+        self.state_history
+            .push_back(self.state.lock().unwrap().clone());
+        while self.state_history.len() > self.state_history_limit {
+            self.state_history.pop_front();
+        }
+        log::info!("Block {} generated.", self.height());
+    }
+
+    fn execute_transactions(&mut self, block: &mut Block) {
+        let mut priority_fees = 0;
+        let env = self.block_env(block);
+
+        let transactions: Vec<Transaction> = self.tx_recv.try_iter().collect();
+        let results = self
+            .mempool
+            .submit_batch(transactions, env.base_fee, |sender| {
+                self.state.lock().unwrap().accounts.get(sender).cloned()
+            });
+        for err in results.into_iter().filter_map(Result::err) {
+            log::error!("Transaction rejected: {}.", err);
+        }
+
+        // transactions that don't fit stay in the mempool for the next block
+        while let Some(tx) = self.mempool.peek_ready() {
+            if tx.gas_limit > block.remaining_gas() {
+                break;
+            }
+            let tx = self.mempool.pop_ready().unwrap();
+            match Executor::process_transaction(&tx, &env, self.state.clone()) {
+                Ok(receipt) => {
+                    priority_fees += receipt.priority_fee();
+                    self.burnt_fees += receipt.burned_fee();
+                    block.add_gas_used(receipt.gas_used);
+                    self.included_transactions.insert(
+                        tx.hash(),
+                        (self.blocks.len(), block.transactions().len(), receipt),
+                    );
+                    block.add_transaction(tx);
+                }
+                Err(err) => {
+                    log::error!("Transaction failed: {}.", err);
+                    // the failed nonce is still to be used, the mempool must expect it again
+                    if let Some(sender) = tx.get_sender_address() {
+                        let account_nonce = self
+                            .state
+                            .lock()
+                            .unwrap()
+                            .accounts
+                            .get(&sender)
+                            .map_or(0, |account| account.nonce);
+                        self.mempool.reset_nonce(sender, account_nonce);
+                    }
+                    self.mempool.forget(tx.hash());
+                }
+            }
+        }
+
+        self.issuance += self.block_reward;
+        Executor::credit_fee_recipient(
+            block.fee_recipient(),
+            priority_fees + self.block_reward,
+            self.state.clone(),
+        );
+    }
+
+    fn finalize_block(&mut self, block: &Block) {
+        Executor::process_withdrawals(block.withdrawals(), self.state.clone());
+        self.base_fee = next_base_fee(
+            block.base_fee_per_gas(),
+            block.gas_used(),
+            block.gas_limit(),
+        );
     }
 
     fn get_next_block(&self) -> Block {
         let proposer_index = 0;
-        let parent_root = B256::ZERO;
+        let parent_root = self.head().map_or(B256::ZERO, Block::hash);
+        // known once the block is executed
         let state_root = B256::ZERO;
-        Block::new(self.slot, proposer_index, parent_root, state_root)
+        let mut block = Block::new(self.height(), proposer_index, parent_root, state_root);
+        block.set_fee_recipient(self.account.address);
+        block.set_gas_limit(self.gas_limit);
+        block.set_base_fee_per_gas(self.base_fee);
+        let mut seed = self.randao_seed.to_vec();
+        seed.extend_from_slice(&self.height().to_be_bytes());
+        block.set_prev_randao(hash_slice_to_b256(&seed));
+        let timestamp = self.head().map_or(self.genesis_timestamp, |parent| {
+            parent.timestamp() + self.block_time
+        });
+        block.set_timestamp(timestamp);
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::{Account, EMPTY_CODE_HASH};
+    use crate::block::block::Withdrawal;
+    use crate::evm::evm::Contract;
+    use crate::evm::operation::Operation;
+    use crate::evm::program::Program;
+    use crate::transaction::builder::TransactionBuilder;
+    use crate::transaction::transaction::{ETH_TO_WEI, GWEI_TO_WEI, TRANSACTION_GAS_COST};
+    use alloy_primitives::{Address, U256};
+
+    #[test]
+    fn test_block_pays_tip_to_coinbase() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate();
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(2 * ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = Transaction::new(
+            receiver.address,
+            ETH_TO_WEI,
+            TRANSACTION_GAS_COST,
+            2 * GWEI_TO_WEI,  // 2 Gwei max tip
+            12 * GWEI_TO_WEI, // 12 Gwei max total (base + priority)
+            vec![],
+            Some(&sender.private_key),
+        );
+        app.transaction_sender().send(tx).unwrap();
+        app.produce_block();
+
+        let coinbase = app.account.address;
+        let mut state = app.state.lock().unwrap();
+        assert_eq!(
+            state.get_account(&coinbase).unwrap().balance,
+            TRANSACTION_GAS_COST * 2 * GWEI_TO_WEI
+        );
+        assert_eq!(app.burnt_fees(), TRANSACTION_GAS_COST * INITIAL_BASE_FEE);
+        assert_eq!(app.blocks[0].transactions().len(), 1);
+    }
+
+    #[test]
+    fn test_block_reward_is_credited_to_coinbase() {
+        let reward = 2 * ETH_TO_WEI;
+        let mut app = App::new().with_block_reward(reward);
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate().address;
+        let coinbase = app.account.address;
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tip = TRANSACTION_GAS_COST * GWEI_TO_WEI;
+        let mut expected_balance = 0;
+        for nonce in 0..2 {
+            let tx = TransactionBuilder::new()
+                .to(receiver)
+                .value(100)
+                .nonce(nonce)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_priority_fee_per_gas(GWEI_TO_WEI)
+                .max_fee_per_gas(12 * GWEI_TO_WEI)
+                .build_signed(&sender.private_key)
+                .unwrap();
+            app.transaction_sender().send(tx).unwrap();
+            app.produce_block();
+
+            expected_balance += reward + tip;
+            assert_eq!(
+                app.state
+                    .lock()
+                    .unwrap()
+                    .get_account(&coinbase)
+                    .unwrap()
+                    .balance,
+                expected_balance
+            );
+        }
+        assert_eq!(app.issuance(), 2 * reward);
+
+        // a block without transactions still pays the reward
+        app.produce_block();
+        assert_eq!(
+            app.state
+                .lock()
+                .unwrap()
+                .get_account(&coinbase)
+                .unwrap()
+                .balance,
+            expected_balance + reward
+        );
+        assert_eq!(app.issuance(), 3 * reward);
+    }
+
+    #[test]
+    fn test_replayed_block_matches_live_state() {
+        let reward = 2 * ETH_TO_WEI;
+        let mut app = App::new().with_block_reward(reward);
+        let sender = Wallet::generate();
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = TransactionBuilder::new()
+            .to(Wallet::generate().address)
+            .value(100)
+            .gas_limit(TRANSACTION_GAS_COST)
+            .max_priority_fee_per_gas(GWEI_TO_WEI)
+            .max_fee_per_gas(12 * GWEI_TO_WEI)
+            .build_signed(&sender.private_key)
+            .unwrap();
+        app.transaction_sender().send(tx).unwrap();
+        app.produce_block();
+
+        let parent_state = Arc::new(Mutex::new(app.state_history[0].clone()));
+        let env = app.block_env(&app.blocks[0]);
+        let result = Executor::execute_block(&app.blocks[0], &env, reward, parent_state).unwrap();
+        assert_eq!(result.gas_used, app.blocks[0].gas_used());
+        assert_eq!(result.state_root, app.state.lock().unwrap().root());
+        assert_eq!(result.state_root, app.blocks[0].state_root());
+    }
+
+    #[test]
+    fn test_replay_reads_block_hashes() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+        // stores the hash of the first block
+        let code = Program::new()
+            .op(Operation::Push0)
+            .op(Operation::BlockHash)
+            .op(Operation::Push0)
+            .op(Operation::SStore)
+            .build();
+        {
+            let mut state = app.state.lock().unwrap();
+            state.set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            state.contract.insert(contract_address, Contract::new(code));
+        }
+        app.produce_block();
+
+        let tx = TransactionBuilder::new()
+            .to(contract_address)
+            .gas_limit(100_000)
+            .max_fee_per_gas(12 * GWEI_TO_WEI)
+            .build_signed(&sender.private_key)
+            .unwrap();
+        app.transaction_sender().send(tx).unwrap();
+        app.produce_block();
+        assert_eq!(
+            app.state
+                .lock()
+                .unwrap()
+                .get_storage(&contract_address, &B256::ZERO),
+            app.blocks[0].hash()
+        );
+
+        let block = &app.blocks[1];
+        let replay = |env: &BlockEnv| {
+            let parent_state = Arc::new(Mutex::new(app.state_history[1].clone()));
+            Executor::execute_block(block, env, 0, parent_state)
+                .unwrap()
+                .state_root
+        };
+        assert_eq!(replay(&app.block_env(block)), block.state_root());
+        // without the hashes of its ancestors the block replays to another state
+        let without_hashes = BlockEnv {
+            block_hashes: HashMap::new(),
+            ..app.block_env(block)
+        };
+        assert_ne!(replay(&without_hashes), block.state_root());
+    }
+
+    #[test]
+    fn test_produced_block_commits_to_state() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+        app.produce_block();
+
+        let tx = TransactionBuilder::new()
+            .to(Wallet::generate().address)
+            .value(100)
+            .gas_limit(TRANSACTION_GAS_COST)
+            .max_priority_fee_per_gas(GWEI_TO_WEI)
+            .max_fee_per_gas(12 * GWEI_TO_WEI)
+            .build_signed(&sender.private_key)
+            .unwrap();
+        app.transaction_sender().send(tx).unwrap();
+        app.produce_block();
+
+        let state_root = app.state.lock().unwrap().root();
+        let (parent, head) = (&app.blocks[0], &app.blocks[1]);
+        assert_eq!(head.verify(parent.hash(), state_root), Ok(()));
+        assert_ne!(head.state_root(), parent.state_root());
+        assert_eq!(app.get_block_by_hash(head.hash()).unwrap().slot(), 1);
+    }
+
+    #[test]
+    fn test_withdrawals_credit_recipients() {
+        let mut app = App::new();
+        let first = Wallet::generate().address;
+        let second = Wallet::generate().address;
+
+        app.state.lock().unwrap().set_account(
+            first,
+            Account::new(GWEI_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let mut block = app.get_next_block();
+        block.add_withdrawal(Withdrawal::new(0, 1, first, 2));
+        block.add_withdrawal(Withdrawal::new(1, 2, second, 5));
+        app.finalize_block(&block);
+
+        let mut state = app.state.lock().unwrap();
+        assert_eq!(state.get_account(&first).unwrap().balance, 3 * GWEI_TO_WEI);
+        assert_eq!(state.get_account(&second).unwrap().balance, 5 * GWEI_TO_WEI);
+    }
+
+    #[test]
+    fn test_submit_raw_transaction() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate().address;
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = TransactionBuilder::new()
+            .to(receiver)
+            .value(1000)
+            .gas_limit(TRANSACTION_GAS_COST)
+            .max_fee_per_gas(20)
+            .build_signed(&sender.private_key)
+            .unwrap();
+        let hash = app.submit_raw(&tx.to_rlp()).unwrap();
+        assert_eq!(hash, tx.hash());
+        app.produce_block();
+
+        let (height, _, receipt) = app.get_transaction(hash).unwrap();
+        assert_eq!(height, 0);
+        assert!(receipt.success);
+        assert_eq!(
+            app.state
+                .lock()
+                .unwrap()
+                .get_account(&receiver)
+                .unwrap()
+                .balance,
+            1000
+        );
+
+        assert!(matches!(
+            app.submit_raw(&[0x02, 0xc1]),
+            Err(TransactionError::InvalidRlp(_))
+        ));
+        // a tampered signature recovers no valid sender
+        let mut tampered = tx.clone();
+        tampered.signature[..32].fill(0);
+        assert!(matches!(
+            app.submit_raw(&tampered.to_rlp()),
+            Err(TransactionError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_get_transaction_by_hash() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = Transaction::new(
+            Wallet::generate().address,
+            100,
+            TRANSACTION_GAS_COST,
+            GWEI_TO_WEI,
+            2 * GWEI_TO_WEI,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let hash = tx.hash();
+        app.transaction_sender().send(tx).unwrap();
+
+        assert!(app.get_transaction(hash).is_none());
+        app.produce_block();
+
+        let (height, transaction, receipt) = app.get_transaction(hash).unwrap();
+        assert_eq!(height, 0);
+        assert_eq!(transaction.hash(), hash);
+        assert!(receipt.success);
+        assert_eq!(receipt.gas_used, TRANSACTION_GAS_COST);
+    }
+
+    #[test]
+    fn test_block_gas_used_is_sum_of_receipts() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+
+        {
+            let mut state = app.state.lock().unwrap();
+            state.set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            // PUSH1 1 PUSH1 0 SSTORE
+            state.contract.insert(
+                contract_address,
+                Contract::new(vec![0x60, 0x01, 0x60, 0x00, 0x55]),
+            );
+        }
+
+        let transfer = TransactionBuilder::new()
+            .nonce(0)
+            .to(Wallet::generate().address)
+            .gas_limit(TRANSACTION_GAS_COST)
+            .max_fee_per_gas(1000)
+            .build_signed(&sender.private_key)
+            .unwrap();
+        let call = TransactionBuilder::new()
+            .nonce(1)
+            .to(contract_address)
+            .gas_limit(100_000)
+            .max_fee_per_gas(1000)
+            .build_signed(&sender.private_key)
+            .unwrap();
+        let hashes = [transfer.hash(), call.hash()];
+        app.transaction_sender().send(transfer).unwrap();
+        app.transaction_sender().send(call).unwrap();
+        app.produce_block();
+
+        let gas_used: Vec<u64> = hashes
+            .iter()
+            .map(|hash| app.get_transaction(*hash).unwrap().2.gas_used)
+            .collect();
+        assert_eq!(gas_used[0], TRANSACTION_GAS_COST);
+        assert!(gas_used[1] > TRANSACTION_GAS_COST);
+        assert_eq!(app.blocks[0].gas_used(), gas_used.iter().sum::<u64>());
+        assert_eq!(
+            app.base_fee(),
+            next_base_fee(INITIAL_BASE_FEE, app.blocks[0].gas_used(), BLOCK_GAS_LIMIT)
+        );
+    }
+
+    #[test]
+    fn test_base_fee_follows_gas_usage() {
+        // a block of two transfers is full, its gas target is a single transfer
+        let mut app = App::new().with_gas_limit(2 * TRANSACTION_GAS_COST);
+        let sender = Wallet::generate();
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let mut base_fees = vec![app.base_fee()];
+        for i in 0..3 {
+            for j in 0..2 {
+                let tx = TransactionBuilder::new()
+                    .nonce(i * 2 + j)
+                    .to(Wallet::generate().address)
+                    .gas_limit(TRANSACTION_GAS_COST)
+                    .max_priority_fee_per_gas(1)
+                    .max_fee_per_gas(1000)
+                    .build_signed(&sender.private_key)
+                    .unwrap();
+                app.transaction_sender().send(tx).unwrap();
+            }
+            app.produce_block();
+            assert_eq!(
+                app.blocks.last().unwrap().gas_used(),
+                2 * TRANSACTION_GAS_COST
+            );
+            base_fees.push(app.base_fee());
+        }
+        for _ in 0..3 {
+            app.produce_block();
+            base_fees.push(app.base_fee());
+        }
+
+        assert!(base_fees[..4].windows(2).all(|pair| pair[1] > pair[0]));
+        assert!(base_fees[3..].windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(next_base_fee(800, 15_000_000, BLOCK_GAS_LIMIT), 800);
+        assert_eq!(next_base_fee(800, BLOCK_GAS_LIMIT, BLOCK_GAS_LIMIT), 900);
+        assert_eq!(next_base_fee(800, 0, BLOCK_GAS_LIMIT), 700);
+    }
+
+    #[test]
+    fn test_fee_history() {
+        // a block of two transfers is full
+        let mut app = App::new().with_gas_limit(2 * TRANSACTION_GAS_COST);
+        let sender = Wallet::generate();
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let mut nonce = 0;
+        for priority_fees in [vec![3, 1], vec![2], vec![]] {
+            for priority_fee in priority_fees {
+                let tx = TransactionBuilder::new()
+                    .nonce(nonce)
+                    .to(Wallet::generate().address)
+                    .gas_limit(TRANSACTION_GAS_COST)
+                    .max_priority_fee_per_gas(priority_fee)
+                    .max_fee_per_gas(1000)
+                    .build_signed(&sender.private_key)
+                    .unwrap();
+                app.transaction_sender().send(tx).unwrap();
+                nonce += 1;
+            }
+            app.produce_block();
+        }
+
+        let history = app.fee_history(3, &[25.0, 75.0]);
+        assert_eq!(history.oldest_block, 0);
+        assert_eq!(history.base_fee_per_gas, vec![10, 11, 11, 10]);
+        assert_eq!(history.gas_used_ratio, vec![1.0, 0.5, 0.0]);
+        assert_eq!(history.reward, vec![vec![1, 3], vec![2, 2], vec![0, 0]]);
+
+        let history = app.fee_history(10, &[]);
+        assert_eq!(history.oldest_block, 0);
+        assert_eq!(history.gas_used_ratio.len(), 3);
+
+        let history = app.fee_history(1, &[50.0]);
+        assert_eq!(history.oldest_block, 2);
+        assert_eq!(history.base_fee_per_gas, vec![11, 10]);
+        assert_eq!(history.reward, vec![vec![0]]);
+    }
+
+    #[test]
+    fn test_nonce_gap_delays_inclusion() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account {
+                nonce: 4,
+                ..Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO)
+            },
+        );
+
+        let transaction = |nonce| {
+            TransactionBuilder::new()
+                .nonce(nonce)
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_fee_per_gas(1000)
+                .build_signed(&sender.private_key)
+                .unwrap()
+        };
+
+        app.transaction_sender().send(transaction(5)).unwrap();
+        app.produce_block();
+        assert!(app.blocks[0].transactions().is_empty());
+
+        app.transaction_sender().send(transaction(4)).unwrap();
+        app.produce_block();
+        let nonces: Vec<u64> = app.blocks[1]
+            .transactions()
+            .iter()
+            .map(|tx| tx.nonce)
+            .collect();
+        assert_eq!(nonces, vec![4, 5]);
+
+        let mut state = app.state.lock().unwrap();
+        assert_eq!(state.get_account(&sender.address).unwrap().nonce, 6);
+    }
+
+    #[test]
+    fn test_failed_transaction_nonce_can_be_resubmitted() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let fee = TRANSACTION_GAS_COST * INITIAL_BASE_FEE;
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(4 * fee, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let transaction = |nonce, value| {
+            TransactionBuilder::new()
+                .nonce(nonce)
+                .value(value)
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_fee_per_gas(INITIAL_BASE_FEE)
+                .build_signed(&sender.private_key)
+                .unwrap()
+        };
+
+        // each fits the balance on its own, but the second no longer does after the first
+        app.transaction_sender().send(transaction(0, fee)).unwrap();
+        app.transaction_sender()
+            .send(transaction(1, 2 * fee))
+            .unwrap();
+        app.transaction_sender().send(transaction(2, 0)).unwrap();
+        app.produce_block();
+        assert_eq!(app.blocks[0].transactions().len(), 1);
+        assert_eq!(app.mempool.queued_len(), 1);
+
+        app.transaction_sender().send(transaction(1, 0)).unwrap();
+        app.produce_block();
+        let nonces: Vec<u64> = app.blocks[1]
+            .transactions()
+            .iter()
+            .map(|tx| tx.nonce)
+            .collect();
+        assert_eq!(nonces, vec![1, 2]);
+
+        let mut state = app.state.lock().unwrap();
+        assert_eq!(state.get_account(&sender.address).unwrap().nonce, 3);
+    }
+
+    #[test]
+    fn test_block_gas_limit_defers_transactions() {
+        let gas_limit = 5 * TRANSACTION_GAS_COST / 2;
+        let mut app = App::new().with_gas_limit(gas_limit);
+        let sender = Wallet::generate();
+
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        for nonce in 0..3 {
+            let tx = TransactionBuilder::new()
+                .nonce(nonce)
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_fee_per_gas(1000)
+                .build_signed(&sender.private_key)
+                .unwrap();
+            app.transaction_sender().send(tx).unwrap();
+        }
+        app.produce_block();
+
+        let block = &app.blocks[0];
+        assert_eq!(block.transactions().len(), 2);
+        assert_eq!(block.remaining_gas(), gas_limit - 2 * TRANSACTION_GAS_COST);
+        assert_eq!(app.mempool.ready_len(), 1);
+
+        app.produce_block();
+        assert_eq!(app.blocks[1].transactions().len(), 1);
+    }
+
+    #[test]
+    fn test_prev_randao_is_deterministic() {
+        let seed = B256::repeat_byte(7);
+        let mut first = App::new().with_randao_seed(seed);
+        let mut second = App::new().with_randao_seed(seed);
+        for app in [&mut first, &mut second] {
+            app.produce_block();
+            app.produce_block();
+        }
+
+        assert_eq!(
+            first.blocks[1].prev_randao(),
+            second.blocks[1].prev_randao()
+        );
+        assert_ne!(first.blocks[0].prev_randao(), first.blocks[1].prev_randao());
+        assert_ne!(
+            App::new().get_next_block().prev_randao(),
+            first.blocks[0].prev_randao()
+        );
+        assert_eq!(
+            first.block_env(&first.blocks[1]).prev_randao,
+            first.blocks[1].prev_randao()
+        );
+    }
+
+    #[test]
+    fn test_block_timestamps_follow_block_time() {
+        let genesis_timestamp = 1_700_000_000;
+        let mut app = App::new()
+            .with_block_time(5)
+            .with_genesis_timestamp(genesis_timestamp);
+        let sender = Wallet::generate();
+        let contract = Address::repeat_byte(0xcc);
+        {
+            let mut state = app.state.lock().unwrap();
+            state.set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            // stores TIMESTAMP in slot 0
+            let code = Program::new()
+                .op(Operation::Timestamp)
+                .op(Operation::Push0)
+                .op(Operation::SStore)
+                .build();
+            state.contract.insert(contract, Contract::new(code));
+        }
+
+        for nonce in 0..3 {
+            let tx = TransactionBuilder::new()
+                .to(contract)
+                .nonce(nonce)
+                .gas_limit(100_000)
+                .max_priority_fee_per_gas(GWEI_TO_WEI)
+                .max_fee_per_gas(10 * GWEI_TO_WEI)
+                .build_signed(&sender.private_key)
+                .unwrap();
+            app.transaction_sender().send(tx).unwrap();
+            app.produce_block();
+
+            let timestamp = genesis_timestamp + 5 * nonce;
+            assert_eq!(app.head().unwrap().timestamp(), timestamp);
+            assert_eq!(
+                app.state
+                    .lock()
+                    .unwrap()
+                    .get_storage(&contract, &B256::ZERO),
+                B256::from(U256::from(timestamp))
+            );
+        }
+        assert_eq!(app.get_next_block().timestamp(), genesis_timestamp + 15);
+    }
+
+    #[test]
+    fn test_state_diff_of_transfer() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate().address;
+        app.state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+        assert!(app.state_diff(0, 0).is_none());
+
+        // without a tip the fee recipient is left untouched
+        let tx = TransactionBuilder::new()
+            .to(receiver)
+            .value(1000)
+            .gas_limit(TRANSACTION_GAS_COST)
+            .max_priority_fee_per_gas(0)
+            .max_fee_per_gas(INITIAL_BASE_FEE)
+            .build_signed(&sender.private_key)
+            .unwrap();
+        app.transaction_sender().send(tx).unwrap();
+        app.produce_block();
+        app.produce_block();
+
+        let diff = app.state_diff(0, 1).unwrap();
+        let mut changed = vec![sender.address, receiver];
+        changed.sort();
+        assert_eq!(diff.accounts.keys().copied().collect::<Vec<_>>(), changed);
+        let fee = (TRANSACTION_GAS_COST * INITIAL_BASE_FEE) as i128;
+        assert_eq!(diff.accounts[&sender.address].balance_delta(), -1000 - fee);
+        assert_eq!(diff.accounts[&sender.address].nonce, Some((0, 1)));
+        assert_eq!(diff.accounts[&receiver].balance, Some((0, 1000)));
+
+        // the empty block changed nothing
+        assert_eq!(app.state_diff(1, 2).unwrap(), StateDiff::default());
+        assert_eq!(app.state_diff(0, 2), Some(diff));
+        assert!(app.state_diff(0, 3).is_none());
+    }
+
+    #[test]
+    fn test_state_history_is_bounded() {
+        let mut app = App::new().with_state_history(2);
+        for _ in 0..3 {
+            app.produce_block();
+        }
+
+        assert_eq!(app.state_history.len(), 2);
+        assert_eq!(app.state_diff(2, 3), Some(StateDiff::default()));
+        assert!(app.state_diff(1, 3).is_none());
+        assert!(app.state_diff(0, 3).is_none());
+    }
+
+    #[test]
+    fn test_head_and_height() {
+        let mut app = App::new();
+        assert_eq!(app.height(), 0);
+        assert!(app.head().is_none());
+
+        for _ in 0..3 {
+            app.produce_block();
+        }
+
+        assert_eq!(app.height(), 3);
+        let head = app.head().unwrap();
+        assert_eq!(head.slot(), 2);
+        assert_eq!(head.hash(), app.blocks[2].hash());
+        assert_eq!(app.get_next_block().slot(), 3);
     }
 }
@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read configuration file {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    // covers syntax errors as well as missing and mistyped fields
+    #[error("invalid configuration file {path}: {source}")]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+}
@@ -1,9 +1,55 @@
 use std::fs;
 
+use super::errors::ConfigError;
 use super::models::Config;
 
-pub fn load_toml(path: &str) -> Config {
-    let contents = fs::read_to_string(path).unwrap();
+pub fn load_toml(path: &str) -> Result<Config, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_string(),
+        source,
+    })?;
+    let config = toml::from_str(&contents).map_err(|source| ConfigError::Parse {
+        path: path.to_string(),
+        source,
+    })?;
     log::info!("Loaded configuration file: {}.", path);
-    toml::from_str(&contents).unwrap()
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, contents: &str) -> Result<Config, ConfigError> {
+        let path = std::env::temp_dir().join(format!("{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let config = load_toml(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_load_toml_errors() {
+        let config = load(
+            "valid",
+            "[general]\nblock_time_secs = 12\nkeys_path = \"\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.general.block_time_secs, 12);
+        assert_eq!(config.general.block_reward, 0);
+
+        let err = load_toml("/nonexistent/config.toml").unwrap_err();
+        assert!(matches!(err, ConfigError::Read { .. }));
+        assert!(err
+            .to_string()
+            .starts_with("failed to read configuration file /nonexistent/config.toml"));
+
+        let err = load("malformed", "[general\nblock_time_secs = 12\n").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        assert!(err.to_string().contains("malformed"));
+
+        let err = load("incomplete", "[general]\nblock_time_secs = 12\n").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        assert!(err.to_string().contains("missing field `keys_path`"));
+    }
 }
@@ -1,2 +1,3 @@
+pub mod errors;
 pub mod loader;
 pub mod models;
\ No newline at end of file
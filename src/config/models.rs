@@ -1,9 +1,33 @@
 use serde::Deserialize;
 
+use crate::blockchain::DEFAULT_STATE_HISTORY;
+use crate::mempool::DEFAULT_MEMPOOL_CAPACITY;
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct General {
-    pub block_time_secs: usize,
+    pub block_time_secs: u64,
     pub keys_path: String,
+    /// Reward credited to the fee recipient of every block, in wei. Zero, as since the merge,
+    /// unless set.
+    #[serde(default)]
+    pub block_reward: u64,
+    /// Maximum number of pending transactions in the mempool.
+    #[serde(default = "default_mempool_capacity")]
+    pub mempool_capacity: usize,
+    /// Number of recent states kept to report the changes between them.
+    #[serde(default = "default_state_history")]
+    pub state_history: usize,
+    /// Produce a block as soon as a transaction is sent, for development.
+    #[serde(default)]
+    pub auto_mine: bool,
+}
+
+fn default_mempool_capacity() -> usize {
+    DEFAULT_MEMPOOL_CAPACITY
+}
+
+fn default_state_history() -> usize {
+    DEFAULT_STATE_HISTORY
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -1,13 +1,95 @@
 use alloy_primitives::{Keccak256, B256, U256};
 
 pub fn hash_string_to_u256(text: &str) -> U256 {
-    let mut hasher = Keccak256::new();
-    hasher.update(text);
-    U256::from_be_slice(&hasher.finalize().as_slice())
+    hash_slice_to_u256(text.as_bytes())
 }
 
 pub fn hash_slice_to_b256(buffer: &[u8]) -> B256 {
     let mut hasher = Keccak256::new();
     hasher.update(buffer);
-    B256::from_slice(&hasher.finalize().as_slice())
+    B256::from_slice(hasher.finalize().as_slice())
+}
+
+/// Keccak256 hash of `buffer` as a big-endian word, as pushed on the stack by KECCAK256.
+pub fn hash_slice_to_u256(buffer: &[u8]) -> U256 {
+    hash_slice_to_b256(buffer).into()
+}
+
+/// 4-byte selector of a function, the first bytes of the keccak256 hash of its canonical
+/// signature, e.g. `transfer(address,uint256)`.
+pub fn function_selector(signature: &str) -> [u8; 4] {
+    let hash = hash_slice_to_b256(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Storage slot of the value stored under `key` in a Solidity mapping at `base_slot`:
+/// `keccak256(key ++ base_slot)`.
+///
+/// Value-type keys must be passed as their 32-byte ABI encoding, `string` and `bytes` keys as
+/// their raw bytes.
+pub fn mapping_slot(base_slot: U256, key: &[u8]) -> U256 {
+    let mut hasher = Keccak256::new();
+    hasher.update(key);
+    hasher.update(base_slot.to_be_bytes::<32>());
+    U256::from_be_slice(hasher.finalize().as_slice())
+}
+
+/// Storage slot of the element at `index` of a Solidity dynamic array at `base_slot`:
+/// `keccak256(base_slot) + index`. Each element is assumed to occupy a full slot.
+pub fn array_element_slot(base_slot: U256, index: U256) -> U256 {
+    hash_slice_to_u256(&base_slot.to_be_bytes::<32>()).wrapping_add(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::EMPTY_CODE_HASH;
+    use alloy_primitives::{hex, uint};
+
+    #[test]
+    fn test_hash_slice_to_u256() {
+        assert_eq!(
+            hash_slice_to_u256(b""),
+            U256::from_be_bytes(EMPTY_CODE_HASH.0)
+        );
+        assert_eq!(
+            hash_slice_to_u256(b"inc()"),
+            U256::from_be_bytes(hash_slice_to_b256(b"inc()").0)
+        );
+    }
+
+    #[test]
+    fn test_function_selector() {
+        assert_eq!(
+            function_selector("transfer(address,uint256)"),
+            hex!("a9059cbb")
+        );
+        assert_eq!(
+            function_selector("inc()"),
+            hash_string_to_u256("inc()").to_be_bytes::<32>()[..4]
+        );
+    }
+
+    #[test]
+    fn test_storage_slots() {
+        // mapping(uint256 => uint256) at slot 0, key 0
+        assert_eq!(
+            mapping_slot(U256::ZERO, &U256::ZERO.to_be_bytes::<32>()),
+            uint!(0xad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5_U256)
+        );
+
+        // mapping(uint256 => uint256) at slot 0, key 1
+        assert_eq!(
+            mapping_slot(U256::ZERO, &U256::from(1).to_be_bytes::<32>()),
+            uint!(0xada5013122d395ba3c54772283fb069b10426056ef8ca54750cb9bb552a59e7d_U256)
+        );
+
+        // uint256[] at slot 0
+        let first = uint!(0x290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563_U256);
+        assert_eq!(array_element_slot(U256::ZERO, U256::ZERO), first);
+        assert_eq!(
+            array_element_slot(U256::ZERO, U256::from(2)),
+            first + U256::from(2)
+        );
+    }
 }
@@ -64,4 +64,38 @@ impl Wallet {
     pub fn get_address(&self) -> String {
         self.address.to_string()
     }
+
+    /// Retrieve the Ethereum address in its EIP-55 mixed-case checksum form
+    pub fn get_address_checksummed(&self) -> String {
+        self.address.to_checksum(None)
+    }
+}
+
+/// Whether `address` is a `0x` prefixed hexadecimal address whose letter casing matches its
+/// EIP-55 checksum. An all lowercase or all uppercase address carries no checksum and fails.
+pub fn is_valid_checksum(address: &str) -> bool {
+    Address::parse_checksummed(address, None).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+
+    #[test]
+    fn test_checksummed_address() {
+        let mut wallet = Wallet::generate();
+        wallet.address = address!("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed");
+        let checksummed = wallet.get_address_checksummed();
+        assert_eq!(checksummed, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+        assert!(is_valid_checksum(&checksummed));
+
+        // flip the case of a single letter
+        let flipped = checksummed.replacen("aA", "AA", 1);
+        assert!(!is_valid_checksum(&flipped));
+        assert!(!is_valid_checksum(&checksummed.to_lowercase()));
+        assert!(!is_valid_checksum(
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA"
+        ));
+    }
 }
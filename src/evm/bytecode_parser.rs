@@ -1,17 +1,38 @@
+use crate::evm::evm::VM;
 use crate::evm::operation::{Operation, OperationError};
 use alloy_primitives::{hex, U256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
 use std::fs;
 
-#[derive(Debug)]
+/// Maximum size of deployed contract code (EIP-170).
+pub const MAX_CODE_SIZE: usize = 0x6000;
+/// Maximum size of init code (EIP-3860), twice the deployed code limit.
+pub const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE;
+
+/// Errors raised while decoding bytecode, with the offset of the offending opcode.
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParserError {
-    IncompletePush,
-    InvalidOpcode,
+    IncompletePush {
+        offset: usize,
+    },
+    InvalidOpcode {
+        offset: usize,
+        error: OperationError,
+    },
+    CodeTooLarge {
+        size: usize,
+        limit: usize,
+    },
 }
 
-impl From<OperationError> for ParserError {
-    fn from(err: OperationError) -> Self {
-        match err {
-            _ => ParserError::InvalidOpcode,
+impl ParserError {
+    pub fn offset(&self) -> usize {
+        match self {
+            ParserError::IncompletePush { offset } | ParserError::InvalidOpcode { offset, .. } => {
+                *offset
+            }
+            ParserError::CodeTooLarge { limit, .. } => *limit,
         }
     }
 }
@@ -20,6 +41,7 @@ impl From<OperationError> for ParserError {
 pub struct BytecodeParser {
     pub bytecode: Vec<u8>,
     pub pc: usize,
+    max_code_size: usize,
 }
 
 impl Iterator for BytecodeParser {
@@ -32,17 +54,34 @@ impl Iterator for BytecodeParser {
 
 impl BytecodeParser {
     pub fn new(bytecode: Vec<u8>) -> Self {
-        Self { bytecode, pc: 0 }
+        Self {
+            bytecode,
+            pc: 0,
+            max_code_size: MAX_INITCODE_SIZE,
+        }
+    }
+
+    /// Overrides the largest bytecode `compile` accepts, which defaults to the init code limit.
+    pub fn with_max_code_size(mut self, max_code_size: usize) -> Self {
+        self.max_code_size = max_code_size;
+        self
     }
 
     pub fn from(filepath: &str) -> Result<Self, std::io::Error> {
         let content = fs::read_to_string(filepath)?;
         let bytecode = hex::decode(content.trim())
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        Ok(Self { bytecode, pc: 0 })
+        Ok(Self::new(bytecode))
     }
 
     pub fn compile(&mut self) -> Result<Vec<Operation>, ParserError> {
+        if self.bytecode.len() > self.max_code_size {
+            return Err(ParserError::CodeTooLarge {
+                size: self.bytecode.len(),
+                limit: self.max_code_size,
+            });
+        }
+
         let mut operations = Vec::new();
         while let Some(operation) = self.next_operation()? {
             operations.push(operation);
@@ -50,12 +89,99 @@ impl BytecodeParser {
         Ok(operations)
     }
 
-    fn next_operation(&mut self) -> Result<Option<Operation>, ParserError> {
+    /// Renders operations as one instruction per line, prefixed with its program counter.
+    ///
+    /// PUSH immediates are printed at the full width of the instruction, so
+    /// `PUSH2 0x0001` and `PUSH1 0x01` stay distinguishable.
+    pub fn disassemble(operations: &[Operation]) -> String {
+        let mut output = String::new();
+        let mut pc = 0;
+        for operation in operations {
+            let bytes = operation.to_bytes();
+            let name = operation.mnemonic();
+            if bytes.len() > 1 {
+                writeln!(
+                    output,
+                    "{:#06x} {} {}",
+                    pc,
+                    name,
+                    hex::encode_prefixed(&bytes[1..])
+                )
+            } else {
+                writeln!(output, "{:#06x} {}", pc, name)
+            }
+            .expect("writing to a String cannot fail");
+            pc += bytes.len();
+        }
+        output
+    }
+
+    /// Collects the offsets of every `JUMPDEST` that is an actual instruction.
+    ///
+    /// A `0x5b` byte that is part of a PUSH immediate is data, not an
+    /// instruction, and therefore not a valid jump destination.
+    pub fn jump_destinations(&self) -> HashSet<usize> {
+        let mut destinations = HashSet::new();
+        let mut pc = 0;
+        while pc < self.bytecode.len() {
+            match self.bytecode[pc] {
+                0x5b => {
+                    destinations.insert(pc);
+                }
+                n @ 0x60..=0x7f => pc += (n - 0x60 + 1) as usize,
+                _ => {}
+            }
+            pc += 1;
+        }
+        destinations
+    }
+
+    /// Counts how often each opcode occurs in the bytecode, keyed by its mnemonic.
+    ///
+    /// Unlike `compile`, this sweeps the entire bytecode instead of stopping at the first
+    /// `INVALID` (0xfe), so the runtime code appended to init code is included as well.
+    /// Bytes that do not decode to an opcode are skipped.
+    pub fn opcode_histogram(&self) -> HashMap<&'static str, usize> {
+        let mut histogram = HashMap::new();
+        for operation in self.sweep() {
+            *histogram.entry(operation.mnemonic()).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Lists the opcodes used by the bytecode that the VM does not implement yet.
+    pub fn unsupported_opcodes(&self) -> BTreeSet<&'static str> {
+        self.sweep()
+            .into_iter()
+            .filter(|operation| !VM::supports(operation))
+            .map(|operation| operation.mnemonic())
+            .collect()
+    }
+
+    fn sweep(&self) -> Vec<Operation> {
+        let mut operations = Vec::new();
+        let mut pc = 0;
+        while pc < self.bytecode.len() {
+            let opcode = self.bytecode[pc];
+            let data = matches!(opcode, 0x60..=0x7f).then_some(U256::ZERO);
+            if let Ok(operation) = Operation::from_byte(opcode, data) {
+                pc += operation.additional_bytes();
+                operations.push(operation);
+            }
+            pc += 1;
+        }
+        operations
+    }
+
+    /// Decodes the operation at `pc` and moves past it. Returns `None` at the end of the code
+    /// and at INVALID (0xfe), which separates the init code from the data appended to it.
+    pub fn next_operation(&mut self) -> Result<Option<Operation>, ParserError> {
         if self.pc >= self.bytecode.len() {
             return Ok(None);
         }
 
-        let opcode = self.bytecode[self.pc];
+        let offset = self.pc;
+        let opcode = self.bytecode[offset];
         if opcode == 0xfe {
             return Ok(None);
         }
@@ -65,25 +191,26 @@ impl BytecodeParser {
             n @ 0x60..=0x7f => {
                 let bytes_to_read = (n - 0x60 + 1) as usize;
                 if self.pc + bytes_to_read >= self.bytecode.len() {
-                    return Err(ParserError::IncompletePush);
+                    return Err(ParserError::IncompletePush { offset });
                 }
 
                 // Read the specified number of bytes
                 let mut value = U256::from(0);
                 for i in 0..bytes_to_read {
-                    value = value << 8;
-                    value = value + U256::from(self.bytecode[self.pc + 1 + i]);
+                    value <<= 8;
+                    value += U256::from(self.bytecode[self.pc + 1 + i]);
                 }
 
                 self.pc += bytes_to_read + 1;
-                Ok::<Operation, OperationError>(Operation::from_byte(opcode, Some(value))?)
+                Operation::from_byte(opcode, Some(value))
             }
             // Handle all other operations
             _ => {
                 self.pc += 1;
-                Ok::<Operation, OperationError>(Operation::from_byte(opcode, None)?)
+                Operation::from_byte(opcode, None)
             }
-        }?;
+        }
+        .map_err(|error| ParserError::InvalidOpcode { offset, error })?;
 
         Ok(Some(operation))
     }
@@ -110,4 +237,84 @@ mod tests {
             "The operations vector should not be empty"
         );
     }
+
+    #[test]
+    fn test_opcode_histogram() {
+        let parser = BytecodeParser::from("./test/Counter.evm").unwrap();
+
+        let histogram = parser.opcode_histogram();
+        assert!(histogram.contains_key("SSTORE"));
+        assert!(histogram["PUSH1"] > 0);
+        assert!(histogram.contains_key("CODECOPY"));
+        assert!(histogram.contains_key("RETURN"));
+        // each DUP is counted on its own
+        assert_eq!(histogram["DUP1"], 10);
+        assert_eq!(histogram["DUP2"], 7);
+        assert!(!histogram.contains_key("Dup"));
+
+        let unsupported = parser.unsupported_opcodes();
+        assert!(unsupported.contains("SHR"));
+        assert!(unsupported.contains("EQ"));
+        assert!(unsupported.contains("SWAP1"));
+        assert!(!unsupported.contains("SSTORE"));
+    }
+
+    #[test]
+    fn test_push_width_round_trip() {
+        let mut bytecode = vec![0x7f];
+        bytecode.extend_from_slice(&[0u8; 31]);
+        bytecode.push(0x01);
+
+        let operations = BytecodeParser::new(bytecode.clone()).compile().unwrap();
+        let encoded: Vec<u8> = operations.iter().flat_map(Operation::to_bytes).collect();
+        assert_eq!(encoded, bytecode);
+
+        let listing = BytecodeParser::disassemble(&operations);
+        assert_eq!(listing, format!("0x0000 PUSH32 0x{}01\n", "00".repeat(31)));
+    }
+
+    #[test]
+    fn test_parser_error_reports_offset() {
+        // PUSH1 0x01 followed by the unassigned opcode 0x0c
+        let mut parser = BytecodeParser::new(vec![0x60, 0x01, 0x0c]);
+        let err = parser.compile().unwrap_err();
+        assert_eq!(
+            err,
+            ParserError::InvalidOpcode {
+                offset: 2,
+                error: OperationError::UnknownOpcode
+            }
+        );
+
+        // ADD followed by a PUSH2 with a single byte of data
+        let mut parser = BytecodeParser::new(vec![0x01, 0x61, 0x00]);
+        let err = parser.compile().unwrap_err();
+        assert_eq!(err, ParserError::IncompletePush { offset: 1 });
+        assert_eq!(err.offset(), 1);
+    }
+
+    #[test]
+    fn test_max_code_size() {
+        let mut parser = BytecodeParser::new(vec![0x5b; MAX_INITCODE_SIZE]);
+        assert_eq!(parser.compile().unwrap().len(), MAX_INITCODE_SIZE);
+
+        let mut parser = BytecodeParser::new(vec![0x5b; MAX_INITCODE_SIZE + 1]);
+        assert_eq!(
+            parser.compile().unwrap_err(),
+            ParserError::CodeTooLarge {
+                size: MAX_INITCODE_SIZE + 1,
+                limit: MAX_INITCODE_SIZE
+            }
+        );
+
+        let mut parser =
+            BytecodeParser::new(vec![0x5b; MAX_CODE_SIZE]).with_max_code_size(MAX_CODE_SIZE);
+        assert!(parser.compile().is_ok());
+
+        let mut parser =
+            BytecodeParser::new(vec![0x5b; MAX_CODE_SIZE + 1]).with_max_code_size(MAX_CODE_SIZE);
+        let err = parser.compile().unwrap_err();
+        assert!(matches!(err, ParserError::CodeTooLarge { .. }));
+        assert_eq!(err.offset(), MAX_CODE_SIZE);
+    }
 }
@@ -1,24 +1,48 @@
-use crate::block::account::Account;
+use crate::block::account::{Account, EMPTY_CODE_HASH};
 use crate::block::state::State;
 use crate::evm::bytecode_parser::{BytecodeParser, ParserError};
 use crate::evm::evm::VMError::{NoItemsOnStack, NotEnoughItemsOnStack, StackFull};
-use crate::evm::operation::Operation;
-use crate::transaction::transaction::Transaction;
+use crate::evm::operation::{GasCost, GasSchedule, Operation};
+use crate::evm::precompiles;
+use crate::evm::profile::GasProfile;
+use crate::evm::signed;
+use crate::evm::spec::Spec;
+use crate::transaction::transaction::{Transaction, TRANSACTION_GAS_COST};
 use alloy_rlp::{Encodable, RlpDecodable, RlpEncodable};
 
-use crate::crypto::hash::hash_slice_to_b256;
-use alloy_primitives::{keccak256, Address, B256, U256};
-use std::collections::HashMap;
+use crate::crypto::hash::{hash_slice_to_b256, hash_slice_to_u256};
+use alloy_primitives::{hex, keccak256, Address, B256, U256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::{Arc, Mutex};
+use thiserror::Error;
 
 const MAX_STACK_SIZE: u32 = 1024;
-
-#[derive(Clone)]
+/// Refund for clearing a storage slot (EIP-3529).
+pub const SSTORE_CLEARS_SCHEDULE: u64 = 4800;
+/// Cost of the first write of a transaction to a slot that was zero (EIP-2200).
+pub const SSTORE_SET_GAS: u64 = 20000;
+/// Cost of the first write of a transaction to a slot that was not zero (EIP-2200).
+pub const SSTORE_RESET_GAS: u64 = 5000;
+/// Selector of `Error(string)`, the encoding of Solidity revert reasons.
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Cost of the first access to an account in a transaction (EIP-2929).
+pub const COLD_ACCOUNT_ACCESS_COST: u64 = 2600;
+/// Cost of accessing an account that was already accessed in the transaction (EIP-2929).
+pub const WARM_STORAGE_READ_COST: u64 = 100;
+/// Extra cost of a call transferring value.
+pub const CALL_VALUE_TRANSFER_COST: u64 = 9000;
+/// Extra cost of a call transferring value to an account that doesn't exist yet.
+pub const CALL_NEW_ACCOUNT_COST: u64 = 25000;
+/// Gas granted to the callee of a call transferring value on top of the forwarded gas.
+pub const CALL_STIPEND: u64 = 2300;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum ExecutionResult {
     Success {
         return_data: Option<Vec<u8>>,
         gas_used: u64,
-        jump_dest: usize,
+        storage_writes: Vec<StorageWrite>,
     },
     Revert {
         reason: Vec<u8>,
@@ -26,51 +50,178 @@ pub enum ExecutionResult {
     },
 }
 
+impl ExecutionResult {
+    /// Decodes the message of a revert reason encoded as `Error(string)`, as emitted by
+    /// Solidity's `require` and `revert`.
+    pub fn revert_message(&self) -> Option<String> {
+        let ExecutionResult::Revert { reason, .. } = self else {
+            return None;
+        };
+        let data = reason.strip_prefix(&ERROR_STRING_SELECTOR)?;
+        let word = |index: usize| -> Option<usize> {
+            let word = data.get(index..index.checked_add(32)?)?;
+            U256::from_be_slice(word).try_into().ok()
+        };
+
+        let offset = word(0)?;
+        let length = word(offset)?;
+        let start = offset.checked_add(32)?;
+        let message = data.get(start..start.checked_add(length)?)?;
+        String::from_utf8(message.to_vec()).ok()
+    }
+}
+
+impl fmt::Display for ExecutionResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionResult::Success {
+                return_data,
+                gas_used,
+                ..
+            } => write!(
+                f,
+                "success (gas used: {}, return data: {})",
+                gas_used,
+                hex::encode_prefixed(return_data.as_deref().unwrap_or_default())
+            ),
+            ExecutionResult::Revert { reason, gas_used } => match self.revert_message() {
+                Some(message) => {
+                    write!(f, "revert (gas used: {}, reason: {:?})", gas_used, message)
+                }
+                None => write!(
+                    f,
+                    "revert (gas used: {}, reason: {})",
+                    gas_used,
+                    hex::encode_prefixed(reason)
+                ),
+            },
+        }
+    }
+}
+
+/// An SSTORE recorded by the storage trace of the VM. Only executions that succeed report
+/// their writes, as a revert discards them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageWrite {
+    pub address: Address,
+    pub slot: U256,
+    pub old_value: U256,
+    pub new_value: U256,
+    pub pc: usize,
+}
+
+/// Effect of a single operation on the control flow.
+enum Step {
+    /// Continues with the next operation, or at `jump_dest` if set.
+    Continue {
+        gas_used: u64,
+        jump_dest: Option<usize>,
+    },
+    /// Stops the execution with the given result.
+    Halt(ExecutionResult),
+}
+
+impl Step {
+    /// Gas charged for the operation.
+    fn gas_used(&self) -> u64 {
+        match self {
+            Step::Continue { gas_used, .. }
+            | Step::Halt(ExecutionResult::Success { gas_used, .. })
+            | Step::Halt(ExecutionResult::Revert { gas_used, .. }) => *gas_used,
+        }
+    }
+}
+
 #[derive(Debug, RlpEncodable, RlpDecodable, PartialEq)]
 pub struct AddressNonce {
-    pub address: Vec<u8>,
+    // encoded as a byte string, a `Vec<u8>` would be encoded as a list of bytes
+    pub address: [u8; 20],
     pub nonce: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Error, Debug, Clone)]
 pub enum VMError {
+    #[error("stack limit of {MAX_STACK_SIZE} items reached")]
     StackFull,
+    #[error("not enough items on the stack for {0}")]
     NotEnoughItemsOnStack(String),
+    #[error("pop from an empty stack")]
     NoItemsOnStack,
-    NotImplemented,
-    ContractNotFound,
+    #[error("{0} is not implemented")]
+    NotImplemented(&'static str),
+    #[error("no contract at {0}")]
+    ContractNotFound(Address),
+    #[error("invalid transaction")]
     InvalidTransaction,
+    #[error("invalid bytecode")]
     InvalidBytecode,
+    #[error("contract creation returned no code")]
     InvalidContractCreationResponse,
-    OutOfGas,
+    #[error("out of gas: {needed} needed, {available} available")]
+    OutOfGas { needed: u64, available: u64 },
+    #[error("stack underflow")]
     StackUnderflow,
+    #[error("no operation executed")]
     NoOperationExecuted,
-    InvalidJumpDest,
+    #[error("invalid jump destination {0:#x}")]
+    InvalidJump(usize),
+    #[error("insufficient balance: {needed} needed, {available} available")]
+    InsufficientBalance { needed: u64, available: u64 },
+    #[error("an account already exists at {0}")]
+    ContractAddressCollision(Address),
+    #[error("{0} is not valid in the active hardfork")]
+    InvalidOpcode(&'static str),
+    #[error("{0} modifies the state inside a static call")]
+    StateChangeInStaticCall(&'static str),
+    #[error("step limit of {0} operations exceeded")]
+    StepLimitExceeded(u64),
+    #[error("nonce of {0} is at its maximum")]
+    NonceOverflow(Address),
+    #[error("read past the end of the return data")]
+    ReturnDataOutOfBounds,
+    #[error("invalid precompile input: {0}")]
+    InvalidPrecompileInput(&'static str),
 }
 impl From<ParserError> for VMError {
-    fn from(value: ParserError) -> Self {
-        match value {
-            ParserError::IncompletePush => VMError::InvalidBytecode,
-            _ => VMError::InvalidTransaction,
-        }
+    fn from(_: ParserError) -> Self {
+        // an undefined opcode, a truncated PUSH or oversized code can't be executed
+        VMError::InvalidBytecode
     }
 }
 
+/// Code of a contract. Its storage lives in the `State`, keyed by the contract's address.
 #[derive(Clone)]
 pub struct Contract {
     pub code: Vec<u8>,
-    pub storage: HashMap<U256, U256>,
 }
 
 impl Contract {
     pub fn new(code: Vec<u8>) -> Self {
-        Self {
-            code,
-            storage: HashMap::new(),
+        Self { code }
+    }
+
+    /// Size of the code in bytes.
+    pub fn size(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Hash of the code as stored in the account of the contract, `EMPTY_CODE_HASH` for a
+    /// contract without code.
+    pub fn code_hash(&self) -> B256 {
+        if self.code.is_empty() {
+            EMPTY_CODE_HASH
+        } else {
+            hash_slice_to_b256(&self.code)
         }
     }
+
+    /// Creates a contract from hex encoded code, with or without the `0x` prefix.
+    pub fn from_hex(code: &str) -> Result<Self, hex::FromHexError> {
+        Ok(Self::new(hex::decode(code.trim())?))
+    }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct ExecutionContext {
     caller: Address,
     address: Address,
@@ -103,9 +254,43 @@ impl ExecutionContext {
     }
 }
 
-enum StorageChangeType {
-    Set,
-    Delete,
+#[derive(Clone, Copy, PartialEq)]
+enum CallKind {
+    Call,
+    CallCode,
+    DelegateCall,
+}
+
+/// Environment of the block the code is executed in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockEnv {
+    pub number: u64,
+    pub base_fee: u64,
+    // hashes of the most recent blocks by number, see BLOCKHASH
+    pub block_hashes: HashMap<u64, B256>,
+    // randomness of the beacon chain, read by PREVRANDAO (formerly DIFFICULTY)
+    pub prev_randao: B256,
+    // seconds since the Unix epoch, read by TIMESTAMP
+    pub timestamp: u64,
+}
+
+impl BlockEnv {
+    pub fn new(number: u64, base_fee: u64) -> Self {
+        Self {
+            number,
+            base_fee,
+            block_hashes: HashMap::new(),
+            prev_randao: B256::ZERO,
+            timestamp: 0,
+        }
+    }
+}
+
+/// Outcome of a message call into another account.
+struct CallOutcome {
+    success: bool,
+    gas_used: u64,
+    output: Vec<u8>,
 }
 
 pub struct VM {
@@ -114,50 +299,146 @@ pub struct VM {
     contract: Contract,
     gas_available: u64,
     context: ExecutionContext,
-    creation_offset: usize,
+    block: BlockEnv,
     state: Arc<Mutex<State>>,
-    storage_revert: HashMap<U256, (StorageChangeType, U256)>,
+    // value of each storage slot when this execution first touched it, read or written,
+    // including the slots of nested calls that succeeded
+    storage_revert: HashMap<(Address, U256), U256>,
+    // balance of each account when this execution first changed it, `None` if the account
+    // didn't exist yet, including the accounts changed by nested calls that succeeded
+    balance_revert: HashMap<Address, Option<u64>>,
+    jump_destinations: HashSet<usize>,
+    // may go negative in a nested call undoing a refund of its caller
+    gas_refund: i64,
+    // offset of the operation being executed
+    pc: usize,
+    // recorded storage writes, if tracing is enabled
+    storage_trace: Option<Vec<StorageWrite>>,
+    gas_schedule: GasSchedule,
+    spec: Spec,
+    // addresses accessed so far in the transaction (EIP-2929)
+    accessed_addresses: HashSet<Address>,
+    gas_profile: Option<GasProfile>,
+    // addresses of the contracts that called into this execution, outermost first
+    call_stack: Vec<Address>,
+    // set inside STATICCALL, where the state must not be modified (EIP-214)
+    is_static: bool,
+    // output of the last call made by this execution (EIP-211)
+    return_data: Vec<u8>,
+    // maximum number of operations to execute, including those of nested calls
+    step_limit: Option<u64>,
+    // operations executed so far, including those of nested calls
+    steps: u64,
 }
 
 impl VM {
     pub fn new(contract: Contract, context: ExecutionContext, state: Arc<Mutex<State>>) -> Self {
-        // Find the runtime code start (look for 0xf3 0xfe sequence)
-        let creation_offset = contract
-            .code
-            .windows(2)
-            .position(|window| window == [0xf3, 0xfe])
-            .map(|pos| pos + 2) // Skip past the f3 fe
-            .unwrap_or(0); // If not found, assume it's all runtime code
-
         Self {
             stack: Vec::new(),
             memory: vec![],
             contract,
             gas_available: context.gas,
             context,
-            creation_offset,
+            block: BlockEnv::default(),
             state,
             storage_revert: HashMap::new(),
+            balance_revert: HashMap::new(),
+            jump_destinations: HashSet::new(),
+            gas_refund: 0,
+            pc: 0,
+            storage_trace: None,
+            gas_schedule: GasSchedule::default(),
+            spec: Spec::default(),
+            accessed_addresses: HashSet::new(),
+            gas_profile: None,
+            call_stack: vec![],
+            is_static: false,
+            return_data: vec![],
+            step_limit: None,
+            steps: 0,
         }
     }
 
-    fn load_into_memory(&mut self, offset: usize, value: U256) -> Result<(), VMError> {
-        let bytes = value.to_be_bytes::<32>();
-        self.expand_memory(offset, 32)?;
-        self.memory[offset..offset + 32].copy_from_slice(&bytes);
-        Ok(())
+    /// Creates a VM executing the runtime `code` of the contract at the address of `context`,
+    /// outside of any transaction, e.g. to read the state of a contract like `eth_call`.
+    pub fn for_call(code: Vec<u8>, context: ExecutionContext, state: Arc<Mutex<State>>) -> Self {
+        Self::new(Contract::new(code), context, state)
     }
 
-    fn expand_memory(&mut self, offset: usize, required_size: usize) -> Result<(), VMError> {
-        let new_size = offset + required_size;
-        if self.memory.len() < new_size {
-            if Self::calc_memory_expansion_gas(offset + 32) < self.gas_available {
-                self.memory.resize(new_size, 0);
-            } else {
-                return Err(VMError::OutOfGas);
-            }
+    /// Sets the block the code is executed in.
+    pub fn with_block_env(mut self, block: BlockEnv) -> Self {
+        self.block = block;
+        self
+    }
+
+    /// Follows the rules of the `spec` hardfork, including in nested calls.
+    pub fn with_spec(mut self, spec: Spec) -> Self {
+        self.spec = spec;
+        self
+    }
+
+    /// Executes in a static context, which rejects every operation modifying the state,
+    /// including in nested calls.
+    pub fn with_static(mut self) -> Self {
+        self.is_static = true;
+        self
+    }
+
+    /// Stops the execution with `StepLimitExceeded` once `step_limit` operations, including
+    /// those of nested calls, were executed, whatever gas is left.
+    pub fn with_step_limit(mut self, step_limit: u64) -> Self {
+        self.step_limit = Some(step_limit);
+        self
+    }
+
+    /// Charges the base costs of the operations from `gas_schedule`, including in nested calls.
+    pub fn with_gas_schedule(mut self, gas_schedule: GasSchedule) -> Self {
+        self.gas_schedule = gas_schedule;
+        self
+    }
+
+    /// Attributes the gas of every operation, including those of nested calls, to the
+    /// operation and the contracts it ran in.
+    pub fn with_gas_profile(mut self) -> Self {
+        self.gas_profile = Some(GasProfile::default());
+        self
+    }
+
+    /// Gas attributed to each operation, if profiling is enabled.
+    pub fn gas_profile(&self) -> Option<&GasProfile> {
+        self.gas_profile.as_ref()
+    }
+
+    /// Records every SSTORE, including those of nested calls, into the `storage_writes` of
+    /// the execution result.
+    pub fn with_storage_trace(mut self) -> Self {
+        self.storage_trace = Some(vec![]);
+        self
+    }
+
+    /// Grows the memory so that `size` bytes at `offset` are addressable, returning the gas
+    /// charged for the growth on top of `base_gas`.
+    fn expand_memory(&mut self, offset: usize, size: usize, base_gas: u64) -> Result<u64, VMError> {
+        let cost = self.memory_expansion_cost(offset, size)?;
+        if self.gas_available < base_gas + cost {
+            return Err(self.out_of_gas(base_gas + cost));
+        }
+        if size > 0 {
+            self.resize_memory(offset + size);
+        }
+        Ok(cost)
+    }
+
+    /// Grows the memory to hold `size` bytes. The memory grows by whole words, as it is billed,
+    /// so MSIZE is always a multiple of 32.
+    ///
+    /// This is the only way the memory grows, and it never shrinks during an execution, so
+    /// memory that was never written always reads as zero.
+    fn resize_memory(&mut self, size: usize) {
+        let size = size.div_ceil(32) * 32;
+        if self.memory.len() < size {
+            self.memory.resize(size, 0);
         }
-        Ok(())
     }
 
     /// Calculates the gas cost for expanding the memory to the given size.
@@ -176,96 +457,329 @@ impl VM {
     ///   - Quadratic term: `(memory_size_word^2) / 512`
     ///   - Linear term: `3 * memory_size_word`
     fn calc_memory_expansion_gas(memory_byte_size: usize) -> u64 {
-        let memory_size_word = (memory_byte_size + 31) / 32;
+        let memory_size_word = memory_byte_size.div_ceil(32);
         let memory_cost = (memory_size_word * memory_size_word / 512) + (3 * memory_size_word);
         memory_cost as u64
     }
 
-    fn read_from_memory(&mut self, offset: usize, length: usize) -> &[u8] {
-        if self.memory.len() < offset + length {
-            self.memory.resize(offset + length, 0);
+    /// Gas charged for growing the memory so that `size` bytes at `offset` are addressable.
+    ///
+    /// Only the growth beyond the current memory size is billed; touching zero bytes never
+    /// expands the memory.
+    fn memory_expansion_cost(&self, offset: usize, size: usize) -> Result<u64, VMError> {
+        if size == 0 {
+            return Ok(0);
+        }
+        let new_size = offset.checked_add(size).ok_or(self.out_of_gas(u64::MAX))?;
+        if new_size <= self.memory.len() {
+            return Ok(0);
+        }
+        // memory this large can never be paid for
+        if new_size > u32::MAX as usize {
+            return Err(self.out_of_gas(u64::MAX));
+        }
+        Ok(Self::calc_memory_expansion_gas(new_size)
+            - Self::calc_memory_expansion_gas(self.memory.len()))
+    }
+
+    /// Change of the gas refund for an SSTORE writing `new` over `current`, to a slot holding
+    /// `original` at the start of the transaction. `dirty_cost` is what the write costs when
+    /// the slot was already written (EIP-2200, with the refund for clearing of EIP-3529).
+    fn sstore_refund(original: U256, current: U256, new: U256, dirty_cost: u64) -> i64 {
+        if current == new {
+            return 0;
+        }
+        let clears = SSTORE_CLEARS_SCHEDULE as i64;
+        if original == current {
+            // only clearing a slot that held a value at the start is refunded
+            return if !original.is_zero() && new.is_zero() {
+                clears
+            } else {
+                0
+            };
+        }
+
+        let mut refund = 0;
+        if !original.is_zero() {
+            if current.is_zero() {
+                // the slot is set again, the refund for clearing it no longer applies
+                refund -= clears;
+            } else if new.is_zero() {
+                refund += clears;
+            }
+        }
+        // restoring the original value makes the first write a no-op in hindsight
+        if original == new {
+            let first_write = if original.is_zero() {
+                SSTORE_SET_GAS
+            } else {
+                SSTORE_RESET_GAS
+            };
+            refund += (first_write - dirty_cost) as i64;
+        }
+        refund
+    }
+
+    /// Rejects an operation modifying the state when executing in a static context.
+    fn ensure_not_static(&self, operation: &Operation) -> Result<(), VMError> {
+        if self.is_static {
+            return Err(VMError::StateChangeInStaticCall(operation.mnemonic()));
+        }
+        Ok(())
+    }
+
+    /// Extra gas charged for a call transferring `value` to `address`, and the stipend granted
+    /// to the callee. A `CALLCODE` transfers the value to the current contract, which always
+    /// exists.
+    fn transfer_value_and_stipend(
+        &self,
+        kind: CallKind,
+        address: &Address,
+        value: u64,
+    ) -> (u64, u64) {
+        if value == 0 {
+            return (0, 0);
+        }
+
+        let mut cost = CALL_VALUE_TRANSFER_COST;
+        if kind == CallKind::Call && self.state.lock().unwrap().is_dead(address) {
+            cost += CALL_NEW_ACCOUNT_COST;
+        }
+        (cost, CALL_STIPEND)
+    }
+
+    /// Marks `address` as accessed in the transaction, returning the extra cost of a first,
+    /// cold access on top of the warm cost `base_gas` (EIP-2929).
+    fn access_account(&mut self, address: Address, base_gas: u64) -> Result<u64, VMError> {
+        let dynamic_gas = if self.accessed_addresses.insert(address) {
+            COLD_ACCOUNT_ACCESS_COST - WARM_STORAGE_READ_COST
+        } else {
+            0
+        };
+        if self.gas_available < base_gas + dynamic_gas {
+            return Err(self.out_of_gas(base_gas + dynamic_gas));
+        }
+        Ok(dynamic_gas)
+    }
+
+    /// Error for an operation needing `needed` gas, more than is left.
+    fn out_of_gas(&self, needed: u64) -> VMError {
+        VMError::OutOfGas {
+            needed,
+            available: self.gas_available,
+        }
+    }
+
+    /// Reads `length` bytes of memory at `offset`, which `expand_memory` made addressable.
+    fn read_from_memory(&self, offset: usize, length: usize) -> &[u8] {
+        if length == 0 {
+            return &[];
         }
         &self.memory[offset..offset + length]
     }
 
-    fn revert_storage(&mut self) {
-        // Revert all changes made to the storage by replacing current values
-        // with the appropriate actions from the storage_revert map.
-        for (key, (change_type, old_value)) in &self.storage_revert {
-            match change_type {
-                StorageChangeType::Set => {
-                    if let Some(storage) = self.contract.storage.get_mut(key) {
-                        *storage = *old_value;
-                    }
-                }
-                StorageChangeType::Delete => {
-                    self.contract.storage.remove(key);
+    fn sload(&self, address: &Address, key: U256) -> U256 {
+        self.state
+            .lock()
+            .unwrap()
+            .get_storage(address, &key.into())
+            .into()
+    }
+
+    fn revert_state(&mut self) {
+        // Restore every slot and balance touched by this execution to its value before the
+        // first touch.
+        let mut state = self.state.lock().unwrap();
+        for ((address, key), old_value) in self.storage_revert.drain() {
+            state.set_storage(address, key.into(), old_value.into());
+        }
+        Self::restore_balances(&mut state, self.balance_revert.drain());
+    }
+
+    fn restore_balances(
+        state: &mut State,
+        balances: impl IntoIterator<Item = (Address, Option<u64>)>,
+    ) {
+        for (address, balance) in balances {
+            match balance {
+                Some(balance) => state.accounts.entry(address).or_default().balance = balance,
+                None => {
+                    state.accounts.remove(&address);
                 }
             }
         }
-        // Clear the storage_revert map after reverting changes.
-        self.storage_revert.clear();
+    }
+
+    /// Executes a single operation, counting it against the step limit.
+    fn step(&mut self, operation: &Operation) -> Result<Step, VMError> {
+        if let Some(step_limit) = self.step_limit {
+            if self.steps >= step_limit {
+                return Err(VMError::StepLimitExceeded(step_limit));
+            }
+        }
+        self.steps += 1;
+        self.process_operation(operation)
     }
 
     pub fn execute_operations(&mut self, code: Vec<u8>) -> Result<ExecutionResult, VMError> {
         let mut parser = BytecodeParser::new(code);
+        self.jump_destinations = parser.jump_destinations();
 
-        let mut execution_result = ExecutionResult::Revert {
-            reason: vec![],
+        // running off the end of the code stops the execution like STOP
+        let mut execution_result = ExecutionResult::Success {
+            return_data: None,
             gas_used: 0,
+            storage_writes: vec![],
         };
 
-        while let Some(operation) = parser.next() {
-            execution_result = self.process_operation(&operation)?;
+        loop {
+            self.pc = parser.pc;
+            let operation = match parser.next_operation() {
+                Ok(Some(operation)) => Ok(operation),
+                Ok(None) if parser.pc >= parser.bytecode.len() => break,
+                // the parser stops at INVALID without consuming it
+                Ok(None) => Err(VMError::InvalidOpcode(Operation::Invalid.mnemonic())),
+                Err(err) => Err(err.into()),
+            };
+            let result =
+                operation.and_then(|operation| self.step(&operation).map(|step| (operation, step)));
+            let (operation, step) = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    // an exceptional halt discards the state changes like a revert and
+                    // consumes all the gas. The step limit is a guard of the node rather than
+                    // of the EVM, so the gas spent up to it is kept.
+                    self.revert_state();
+                    if !matches!(err, VMError::StepLimitExceeded(_)) {
+                        self.gas_available = 0;
+                    }
+                    return Err(err);
+                }
+            };
+            if let Some(profile) = &mut self.gas_profile {
+                let mut call_stack = self.call_stack.clone();
+                call_stack.push(self.context.address);
+                profile.record(&call_stack, (&operation).into(), step.gas_used());
+            }
 
-            self.gas_available -= match execution_result {
-                ExecutionResult::Success {
+            match step {
+                Step::Continue {
                     gas_used,
                     jump_dest,
-                    ..
                 } => {
-                    if jump_dest != 0 {
+                    self.gas_available -= gas_used;
+                    if let Some(jump_dest) = jump_dest {
                         parser.pc = jump_dest;
                     }
-                    gas_used
                 }
-                ExecutionResult::Revert { gas_used, .. } => gas_used,
-            };
+                Step::Halt(result) => {
+                    self.gas_available -= match &result {
+                        ExecutionResult::Success { gas_used, .. }
+                        | ExecutionResult::Revert { gas_used, .. } => *gas_used,
+                    };
+                    execution_result = result;
+                    break;
+                }
+            }
         }
 
+        // report the gas consumed by the whole execution, not just by the last operation
+        let total_gas_used = self.gas_used();
+        let storage_writes = self
+            .storage_trace
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        match &mut execution_result {
+            ExecutionResult::Success {
+                gas_used,
+                storage_writes: writes,
+                ..
+            } => {
+                *gas_used = total_gas_used;
+                *writes = storage_writes;
+            }
+            ExecutionResult::Revert { gas_used, .. } => *gas_used = total_gas_used,
+        }
         Ok(execution_result)
     }
 
+    /// Gas refund accrued by the execution, before the cap applied to the transaction.
+    pub fn gas_refund(&self) -> u64 {
+        self.gas_refund.max(0) as u64
+    }
+
+    /// Gas consumed so far out of the gas given to the execution context.
+    pub fn gas_used(&self) -> u64 {
+        self.context.gas - self.gas_available
+    }
+
     pub fn execute_transaction(
         &mut self,
         transaction: Transaction,
+    ) -> Result<ExecutionResult, VMError> {
+        let sender = transaction
+            .get_sender_address()
+            .ok_or(VMError::InvalidTransaction)?;
+        self.execute_unsigned_transaction(transaction, sender)
+    }
+
+    /// Executes a transaction sent by `sender` without recovering it from the signature.
+    pub fn execute_unsigned_transaction(
+        &mut self,
+        transaction: Transaction,
+        sender: Address,
     ) -> Result<ExecutionResult, VMError> {
         self.stack.clear();
         self.memory.clear();
 
         self.context = ExecutionContext::new(
-            transaction
-                .get_sender_address()
-                .ok_or(VMError::InvalidTransaction)?,
+            sender,
             transaction.to,
             transaction.value,
             transaction.input_data.clone(),
-            transaction.gas_limit,
+            // the intrinsic cost of the transaction is paid before execution starts
+            transaction.gas_limit.saturating_sub(TRANSACTION_GAS_COST),
         );
         self.gas_available = self.context.gas;
 
+        // the sender, the recipient and the precompiles start out warm (EIP-2929)
+        self.accessed_addresses.clear();
+        self.accessed_addresses.insert(self.context.caller);
+        self.accessed_addresses.insert(transaction.to);
+        self.accessed_addresses.extend(precompiles::ADDRESSES);
+
         // differentiate contract creation
-        if transaction.to.is_zero() {
+        if transaction.is_creation() {
             self.call_contract_create(transaction)
         } else {
             self.call_contract(transaction)
         }
     }
 
-    fn generate_contract_address(&self, address: Address, nonce: u64) -> Address {
+    /// Executes the runtime code against `calldata` with the gas of the execution context. No
+    /// transaction is involved: nothing is charged or validated besides the execution itself.
+    pub fn run(&mut self, calldata: Vec<u8>) -> Result<ExecutionResult, VMError> {
+        self.stack.clear();
+        self.memory.clear();
+        self.return_data.clear();
+        self.context.data = calldata;
+        self.gas_available = self.context.gas;
+
+        self.accessed_addresses.clear();
+        self.accessed_addresses.insert(self.context.caller);
+        self.accessed_addresses.insert(self.context.address);
+        self.accessed_addresses.extend(precompiles::ADDRESSES);
+
+        self.execute_operations(self.contract.code.clone())
+    }
+
+    /// Address of the contract created by `address` at `nonce`, the last 20 bytes of
+    /// `keccak256(rlp([address, nonce]))`.
+    fn generate_contract_address(address: Address, nonce: u64) -> Address {
         let mut buffer = Vec::<u8>::new();
         AddressNonce {
-            address: address.0.as_slice().to_vec(),
+            address: address.into_array(),
             nonce,
         }
         .encode(&mut buffer);
@@ -277,27 +791,79 @@ impl VM {
         &mut self,
         transaction: Transaction,
     ) -> Result<ExecutionResult, VMError> {
-        let sender = transaction
-            .get_sender_address()
-            .ok_or(VMError::InvalidTransaction)?;
+        let sender = self.context.caller;
 
-        let contract_address = self.generate_contract_address(sender, transaction.nonce);
+        // EIP-2681: a creation at the maximum nonce would wrap it and reuse addresses
+        if transaction.nonce == u64::MAX {
+            return Err(VMError::NonceOverflow(sender));
+        }
+        let contract_address = Self::generate_contract_address(sender, transaction.nonce);
         self.context.address = contract_address;
-
-        self.state.lock().unwrap().accounts.insert(
-            contract_address,
-            Account::new(
-                transaction.value,
-                hash_slice_to_b256(transaction.input_data.as_slice()),
-                B256::ZERO, // TODO: storage root hash?
-            ),
-        );
+        self.accessed_addresses.insert(contract_address);
+
+        {
+            // the endowment moves from the sender to the new contract
+            let mut state = self.state.lock().unwrap();
+            // EIP-684: never deploy over an account that has code or has sent transactions
+            let collision = state.contract.contains_key(&contract_address)
+                || state
+                    .accounts
+                    .get(&contract_address)
+                    .is_some_and(|account| {
+                        account.nonce != 0 || account.code_hash != EMPTY_CODE_HASH
+                    });
+            if collision {
+                return Err(VMError::ContractAddressCollision(contract_address));
+            }
+            // journaled like any other balance change, so a failed creation undoes them
+            let contract_balance = state
+                .accounts
+                .get(&contract_address)
+                .map(|account| account.balance);
+            self.balance_revert
+                .entry(contract_address)
+                .or_insert(contract_balance);
+            if transaction.value > 0 {
+                let sender = state.accounts.entry(sender).or_default();
+                if sender.balance < transaction.value {
+                    return Err(VMError::InsufficientBalance {
+                        needed: transaction.value,
+                        available: sender.balance,
+                    });
+                }
+                self.balance_revert
+                    .entry(self.context.caller)
+                    .or_insert(Some(sender.balance));
+                sender.balance -= transaction.value;
+            }
+            state.accounts.insert(
+                contract_address,
+                Account::new(
+                    transaction.value,
+                    // the account has no code until the init code returns it
+                    EMPTY_CODE_HASH,
+                    B256::ZERO, // TODO: storage root hash?
+                ),
+            );
+        }
 
         match self.execute_operations(transaction.input_data.clone()) {
             Ok(result) => {
                 if let ExecutionResult::Success { return_data, .. } = result.clone() {
-                    self.contract.code =
-                        return_data.ok_or(VMError::InvalidContractCreationResponse)?;
+                    let Some(code) = return_data else {
+                        self.revert_state();
+                        return Err(VMError::InvalidContractCreationResponse);
+                    };
+                    self.contract.code = code;
+                    if let Some(account) = self
+                        .state
+                        .lock()
+                        .unwrap()
+                        .accounts
+                        .get_mut(&contract_address)
+                    {
+                        account.code_hash = self.contract.code_hash();
+                    }
                 }
                 Ok(result)
             }
@@ -306,14 +872,256 @@ impl VM {
     }
 
     pub fn call_contract(&mut self, transaction: Transaction) -> Result<ExecutionResult, VMError> {
-        // extract function selector
-        let selector = &transaction.input_data[0..4];
+        // the runtime code dispatches on the function selector in the calldata itself
+        self.context.data = transaction.input_data;
+        self.execute_operations(self.contract.code.clone())
+    }
 
-        Ok(ExecutionResult::Success {
-            return_data: None,
-            gas_used: 0,
-            jump_dest: 0,
-        })
+    pub fn contract(&self) -> &Contract {
+        &self.contract
+    }
+
+    /// Address of the account whose code is executed.
+    pub fn address(&self) -> Address {
+        self.context.address
+    }
+
+    /// Value of a storage slot of the account whose code is executed.
+    pub fn storage(&self, key: U256) -> U256 {
+        self.sload(&self.context.address, key)
+    }
+
+    pub fn stack(&self) -> &[U256] {
+        &self.stack
+    }
+
+    /// Copy of the stack, bottom first.
+    pub fn dump_stack(&self) -> Vec<U256> {
+        self.stack.clone()
+    }
+
+    /// Renders the memory as a hexdump of 32-byte rows prefixed with their offset.
+    pub fn dump_memory(&self) -> String {
+        self.memory
+            .chunks(32)
+            .enumerate()
+            .map(|(row, bytes)| format!("{:#06x}: {}", row * 32, hex::encode(bytes)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Executes a message call into `address`.
+    ///
+    /// `CALL` runs the callee's code against its own storage and transfers `value` to it.
+    /// `CALLCODE` and `DELEGATECALL` run the callee's code against the current contract's
+    /// storage; `CALLCODE` sets `CALLER`/`CALLVALUE` to the call's own parameters, while
+    /// `DELEGATECALL` keeps those of the current context.
+    ///
+    /// The balances changed by the value transfer are journaled, as the callee journals its own
+    /// changes; if the callee reverts or fails, both the value transfer and every state change
+    /// made by the callee are rolled back.
+    fn message_call(
+        &mut self,
+        kind: CallKind,
+        address: Address,
+        value: u64,
+        data: Vec<u8>,
+        gas: u64,
+    ) -> Result<CallOutcome, VMError> {
+        // balances before the value transfer, restored if the call fails
+        let mut transfer_revert = HashMap::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            let caller_balance = state
+                .accounts
+                .get(&self.context.address)
+                .map_or(0, |account| account.balance);
+            if kind != CallKind::DelegateCall && caller_balance < value {
+                return Ok(CallOutcome {
+                    success: false,
+                    gas_used: 0,
+                    output: vec![],
+                });
+            }
+
+            // CALLCODE transfers the value to the current contract itself
+            if kind == CallKind::Call && value > 0 {
+                for account in [self.context.address, address] {
+                    transfer_revert
+                        .entry(account)
+                        .or_insert_with(|| state.accounts.get(&account).map(|a| a.balance));
+                }
+                state
+                    .accounts
+                    .entry(self.context.address)
+                    .or_default()
+                    .balance -= value;
+                state.accounts.entry(address).or_default().balance += value;
+            }
+        }
+
+        // precompiles have no code, they run once the value is transferred
+        if let Some(precompile) = precompiles::get(&address) {
+            let outcome = match precompile(&data, gas) {
+                Ok((gas_used, output)) => CallOutcome {
+                    success: true,
+                    gas_used,
+                    output,
+                },
+                Err(_) => CallOutcome {
+                    success: false,
+                    gas_used: gas,
+                    output: vec![],
+                },
+            };
+            self.commit_transfer(&outcome, transfer_revert);
+            return Ok(outcome);
+        }
+
+        // a call to an account without code only transfers the value
+        let contract = self.state.lock().unwrap().contract.get(&address).cloned();
+        let Some(contract) = contract else {
+            let outcome = CallOutcome {
+                success: true,
+                gas_used: 0,
+                output: vec![],
+            };
+            self.commit_transfer(&outcome, transfer_revert);
+            return Ok(outcome);
+        };
+
+        let code = contract.code.clone();
+        // the storage the callee runs against is the one of the context's address
+        let context = match kind {
+            CallKind::Call => {
+                ExecutionContext::new(self.context.address, address, value, data, gas)
+            }
+            CallKind::CallCode => {
+                ExecutionContext::new(self.context.address, self.context.address, value, data, gas)
+            }
+            CallKind::DelegateCall => ExecutionContext::new(
+                self.context.caller,
+                self.context.address,
+                self.context.value,
+                data,
+                gas,
+            ),
+        };
+        // the callee gets a fresh memory and stack, only the output reaches the caller's memory
+        let mut callee = VM::new(contract, context, self.state.clone())
+            .with_block_env(self.block.clone())
+            .with_gas_schedule(self.gas_schedule.clone())
+            .with_spec(self.spec);
+        callee.is_static = self.is_static;
+        callee.step_limit = self.step_limit;
+        callee.steps = self.steps;
+        if self.storage_trace.is_some() {
+            callee = callee.with_storage_trace();
+        }
+        callee.accessed_addresses = self.accessed_addresses.clone();
+        callee.accessed_addresses.insert(address);
+        if self.gas_profile.is_some() {
+            callee = callee.with_gas_profile();
+            callee.call_stack = self.call_stack.clone();
+            callee.call_stack.push(self.context.address);
+        }
+
+        let result = callee.execute_operations(code);
+        self.steps = callee.steps;
+        if let (Some(profile), Some(callee_profile)) =
+            (&mut self.gas_profile, callee.gas_profile.take())
+        {
+            profile.merge_call(callee_profile);
+        }
+
+        let outcome = match result {
+            Ok(ExecutionResult::Success {
+                return_data,
+                storage_writes,
+                ..
+            }) => {
+                if let Some(trace) = &mut self.storage_trace {
+                    trace.extend(storage_writes);
+                }
+                // a revert of this execution also undoes the writes of the callee
+                for (slot, old_value) in callee.storage_revert.drain() {
+                    self.storage_revert.entry(slot).or_insert(old_value);
+                }
+                self.gas_refund += callee.gas_refund;
+                self.accessed_addresses = std::mem::take(&mut callee.accessed_addresses);
+                CallOutcome {
+                    success: true,
+                    gas_used: gas - callee.gas_available,
+                    output: return_data.unwrap_or_default(),
+                }
+            }
+            Ok(ExecutionResult::Revert { reason, .. }) => CallOutcome {
+                success: false,
+                gas_used: gas - callee.gas_available,
+                output: reason,
+            },
+            // an exceptional halt consumes all the forwarded gas
+            Err(_) => CallOutcome {
+                success: false,
+                gas_used: gas,
+                output: vec![],
+            },
+        };
+
+        // a failed callee already undid its own changes, leaving its journal empty, so only
+        // the transfer is left to undo. The balances from before the transfer come first.
+        self.commit_transfer(&outcome, transfer_revert);
+        for (account, balance) in callee.balance_revert.drain() {
+            self.balance_revert.entry(account).or_insert(balance);
+        }
+        Ok(outcome)
+    }
+
+    /// Keeps the value transfer of a call in the journal if the call succeeded, undoes it
+    /// otherwise.
+    fn commit_transfer(
+        &mut self,
+        outcome: &CallOutcome,
+        transfer_revert: HashMap<Address, Option<u64>>,
+    ) {
+        if outcome.success {
+            for (account, balance) in transfer_revert {
+                self.balance_revert.entry(account).or_insert(balance);
+            }
+        } else {
+            Self::restore_balances(&mut self.state.lock().unwrap(), transfer_revert);
+        }
+    }
+
+    /// Copies `size` bytes of `code` from `offset` into memory at `dest_offset`, padding with
+    /// zeros past the end of the code. Returns the gas charged on top of `base_gas`.
+    fn copy_code(
+        &mut self,
+        code: &[u8],
+        dest_offset: usize,
+        offset: usize,
+        size: usize,
+        base_gas: u64,
+    ) -> Result<u64, VMError> {
+        let minimum_word_size = (size as u64).div_ceil(32);
+        let dynamic_gas = 3 * minimum_word_size + self.memory_expansion_cost(dest_offset, size)?;
+
+        if self.gas_available < base_gas + dynamic_gas {
+            return Err(self.out_of_gas(base_gas + dynamic_gas));
+        }
+
+        if size > 0 {
+            self.resize_memory(dest_offset + size);
+        }
+
+        for i in 0..size {
+            self.memory[dest_offset + i] = offset
+                .checked_add(i)
+                .and_then(|index| code.get(index))
+                .copied()
+                .unwrap_or(0);
+        }
+        Ok(dynamic_gas)
     }
 
     fn stack_size(&self) -> u32 {
@@ -332,77 +1140,195 @@ impl VM {
         self.stack.pop().ok_or(NoItemsOnStack)
     }
 
+    /// Pops a stack item used as an offset or size. Values beyond `usize` saturate,
+    /// so they fail the subsequent bounds or gas checks instead of panicking.
+    fn pop_usize(&mut self) -> Result<usize, VMError> {
+        Ok(self.pop()?.saturating_to::<usize>())
+    }
+
     fn add(&mut self) -> Result<(), VMError> {
         let a = self.pop()?;
         let b = self.pop()?;
         self.push(a + b)
     }
 
-    fn process_operation(&mut self, operation: &Operation) -> Result<ExecutionResult, VMError> {
-        let stack_req = operation.stack_req();
-        let operation_name = format!("{:?}", operation);
+    /// Whether `process_operation` implements the given operation, found out by running it on
+    /// a scratch VM with enough stack items and gas that nothing else stops it first.
+    pub fn supports(operation: &Operation) -> bool {
+        let mut vm = VM::new(
+            Contract::new(vec![]),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1_000_000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.stack = vec![U256::ZERO; operation.stack_req().min_stack_height as usize];
+        !matches!(
+            vm.process_operation(operation),
+            Err(VMError::NotImplemented(_))
+        )
+    }
 
+    fn process_operation(&mut self, operation: &Operation) -> Result<Step, VMError> {
+        let stack_req = operation.stack_req();
         if self.stack_size() < stack_req.min_stack_height {
-            return Err(NotEnoughItemsOnStack(operation_name));
+            return Err(NotEnoughItemsOnStack(operation.mnemonic().to_string()));
         }
 
-        let gas_cost = operation.gas_cost();
+        let gas_cost = GasCost {
+            base: self.gas_schedule.base_cost(operation),
+            ..operation.gas_cost()
+        };
         if self.gas_available < gas_cost.base {
-            return Err(VMError::OutOfGas);
+            return Err(self.out_of_gas(gas_cost.base));
         }
 
-        let not_impl_error = format!("Operation {:?} is not implemented", operation_name);
-
         match operation {
-            Operation::Stop => panic!("{}", not_impl_error),
+            Operation::Stop => {
+                return Ok(Step::Halt(ExecutionResult::Success {
+                    return_data: None,
+                    gas_used: gas_cost.base,
+                    storage_writes: vec![],
+                }));
+            }
             Operation::Add => {
                 self.add()?;
             }
-            Operation::Mul => panic!("{}", not_impl_error),
-            Operation::Sub => panic!("{}", not_impl_error),
-            Operation::Div => panic!("{}", not_impl_error),
-            Operation::SDiv => panic!("{}", not_impl_error),
-            Operation::Mod => panic!("{}", not_impl_error),
-            Operation::SMod => panic!("{}", not_impl_error),
-            Operation::AddMod => panic!("{}", not_impl_error),
-            Operation::MulMod => panic!("{}", not_impl_error),
-            Operation::Exp => panic!("{}", not_impl_error),
-            Operation::SignExtend => panic!("{}", not_impl_error),
-            Operation::Lt => panic!("{}", not_impl_error),
-            Operation::Gt => panic!("{}", not_impl_error),
-            Operation::Slt => panic!("{}", not_impl_error),
-            Operation::Sgt => panic!("{}", not_impl_error),
-            Operation::Eq => panic!("{}", not_impl_error),
+            Operation::Mul => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Sub => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Div => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::SDiv => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                // the magnitude of INT_MIN / -1 overflows back to INT_MIN
+                let quotient = if b.is_zero() {
+                    U256::ZERO
+                } else if signed::is_negative(a) != signed::is_negative(b) {
+                    (signed::abs(a) / signed::abs(b)).wrapping_neg()
+                } else {
+                    signed::abs(a) / signed::abs(b)
+                };
+                self.push(quotient)?;
+            }
+            Operation::Mod => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::SMod => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                // the remainder takes the sign of the dividend
+                let remainder = if b.is_zero() {
+                    U256::ZERO
+                } else if signed::is_negative(a) {
+                    (signed::abs(a) % signed::abs(b)).wrapping_neg()
+                } else {
+                    signed::abs(a) % signed::abs(b)
+                };
+                self.push(remainder)?;
+            }
+            Operation::AddMod => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::MulMod => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Exp => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::SignExtend => {
+                let byte = self.pop()?;
+                let value = self.pop()?;
+                // extends the sign bit of the lowest `byte + 1` bytes to the whole word
+                let extended = if byte < U256::from(31) {
+                    let sign_bit = byte.to::<usize>() * 8 + 7;
+                    let mask = (U256::from(1) << sign_bit) - U256::from(1);
+                    if value.bit(sign_bit) {
+                        value | !mask
+                    } else {
+                        value & mask
+                    }
+                } else {
+                    value
+                };
+                self.push(extended)?;
+            }
+            Operation::Lt => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Gt => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Slt => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push(U256::from(signed::to_signed(a) < signed::to_signed(b)))?;
+            }
+            Operation::Sgt => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push(U256::from(signed::to_signed(a) > signed::to_signed(b)))?;
+            }
+            Operation::Eq => return Err(VMError::NotImplemented(operation.mnemonic())),
             Operation::IsZero => {
                 let item = self.pop()?;
                 self.push(U256::from(item.is_zero()))?;
             }
-            Operation::And => panic!("{}", not_impl_error),
-            Operation::Or => panic!("{}", not_impl_error),
-            Operation::Xor => panic!("{}", not_impl_error),
-            Operation::Not => panic!("{}", not_impl_error),
-            Operation::Byte => panic!("{}", not_impl_error),
-            Operation::Shl => panic!("{}", not_impl_error),
-            Operation::Shr => panic!("{}", not_impl_error),
-            Operation::Sar => panic!("{}", not_impl_error),
-            Operation::Address => {
-                self.push(U256::from_be_slice(self.context.address.as_slice()))?;
-            }
-            Operation::Balance => panic!("{}", not_impl_error),
-            Operation::Origin => {
-                self.push(U256::from_be_slice(self.context.caller.as_slice()))?;
+            Operation::And => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Or => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Xor => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Not => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Byte => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Shl => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Shr => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Sar => {
+                let shift = self.pop()?;
+                let value = self.pop()?;
+                // shifting in the sign bit is shifting the complement in zeros
+                let shifted = match (signed::is_negative(value), shift < U256::from(256)) {
+                    (false, true) => value >> shift.to::<usize>(),
+                    (true, true) => !(!value >> shift.to::<usize>()),
+                    (false, false) => U256::ZERO,
+                    (true, false) => U256::MAX,
+                };
+                self.push(shifted)?;
             }
-            Operation::Caller => panic!("{}", not_impl_error),
+            Operation::Keccak256 => {
+                let offset = self.pop_usize()?;
+                let size = self.pop_usize()?;
+
+                let word_gas = gas_cost.dynamic_multiplier * (size as u64).div_ceil(32);
+                let memory_gas = self.expand_memory(offset, size, gas_cost.base + word_gas)?;
+
+                let hash = hash_slice_to_u256(self.read_from_memory(offset, size));
+                self.push(hash)?;
+
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + word_gas + memory_gas,
+                    jump_dest: None,
+                });
+            }
+            Operation::Address => {
+                self.push(U256::from_be_slice(self.context.address.as_slice()))?;
+            }
+            Operation::Balance => {
+                let address = Address::from_word(B256::from(self.pop()?));
+                let dynamic_gas = self.access_account(address, gas_cost.base)?;
+
+                let balance = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .accounts
+                    .get(&address)
+                    .map_or(0, |account| account.balance);
+                self.push(U256::from(balance))?;
+
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + dynamic_gas,
+                    jump_dest: None,
+                });
+            }
+            Operation::Origin => {
+                self.push(U256::from_be_slice(self.context.caller.as_slice()))?;
+            }
+            Operation::Caller => return Err(VMError::NotImplemented(operation.mnemonic())),
             Operation::CallValue => {
                 self.push(U256::from(self.context.value))?;
             }
             Operation::CallDataLoad => {
-                let i = self.pop()?.to::<usize>();
+                let i = self.pop_usize()?;
                 let mut result = [0u8; 32];
 
                 if i < self.context.data.len() {
                     let slice_end: usize = (i + 32).min(self.context.data.len());
-                    result.copy_from_slice(&self.context.data[i..slice_end]);
+                    // bytes past the end of the calldata read as zero
+                    result[..slice_end - i].copy_from_slice(&self.context.data[i..slice_end]);
                 }
 
                 self.push(U256::from_be_slice(&result))?;
@@ -410,119 +1336,281 @@ impl VM {
             Operation::CallDataSize => {
                 self.push(U256::from(self.context.data.len()))?;
             }
-            Operation::CallDataCopy => panic!("{}", not_impl_error),
+            Operation::CallDataCopy => return Err(VMError::NotImplemented(operation.mnemonic())),
             Operation::CodeSize => {
                 self.push(U256::from(self.contract.code.len()))?;
             }
             Operation::CodeCopy => {
-                let dest_offset = self.pop()?.to::<usize>();
-                let offset = self.pop()?.to::<usize>();
-                let size = self.pop()?.to::<usize>();
-
-                let minimum_word_size = (size as u64 + 31) / 32;
-                let static_gas = 3;
-                let dynamic_gas = 3 * minimum_word_size + Self::calc_memory_expansion_gas(size);
+                let dest_offset = self.pop_usize()?;
+                let offset = self.pop_usize()?;
+                let size = self.pop_usize()?;
 
-                if self.gas_available < gas_cost.base + static_gas + dynamic_gas {
-                    return Err(VMError::OutOfGas);
-                }
+                let code = self.contract.code.clone();
+                let dynamic_gas =
+                    self.copy_code(&code, dest_offset, offset, size, gas_cost.base)?;
 
-                self.expand_memory(dest_offset, size)?;
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + dynamic_gas,
+                    jump_dest: None,
+                });
+            }
+            Operation::GasPrice => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::ExtCodeSize => {
+                let address = Address::from_word(B256::from(self.pop()?));
+                let dynamic_gas = self.access_account(address, gas_cost.base)?;
+                // accounts without code, including the precompiles, have a size of zero
+                let size = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .contract
+                    .get(&address)
+                    .map_or(0, Contract::size);
+                self.push(U256::from(size))?;
 
-                // Get the raw bytecode slice
-                for i in 0..size {
-                    let byte = if offset + i < self.contract.code.len() {
-                        self.contract.code[offset + i] // Copy raw byte directly
-                    } else {
-                        0 // For out-of-bound bytes, pad with 0
-                    };
-                    self.memory[dest_offset + i] = byte;
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + dynamic_gas,
+                    jump_dest: None,
+                });
+            }
+            Operation::ExtCodeCopy => {
+                let address = Address::from_word(B256::from(self.pop()?));
+                let dest_offset = self.pop_usize()?;
+                let offset = self.pop_usize()?;
+                let size = self.pop_usize()?;
+                let access_gas = self.access_account(address, gas_cost.base)?;
+
+                // an account without code copies as zeros
+                let code = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .contract
+                    .get(&address)
+                    .map(|contract| contract.code.clone())
+                    .unwrap_or_default();
+                let base_gas = gas_cost.base + access_gas;
+                let dynamic_gas = self.copy_code(&code, dest_offset, offset, size, base_gas)?;
+
+                return Ok(Step::Continue {
+                    gas_used: base_gas + dynamic_gas,
+                    jump_dest: None,
+                });
+            }
+            Operation::ReturnDataSize => {
+                self.push(U256::from(self.return_data.len()))?;
+            }
+            Operation::ReturnDataCopy => {
+                let dest_offset = self.pop_usize()?;
+                let offset = self.pop_usize()?;
+                let size = self.pop_usize()?;
+
+                // unlike the code, the return data can't be read past its end
+                if offset
+                    .checked_add(size)
+                    .is_none_or(|end| end > self.return_data.len())
+                {
+                    return Err(VMError::ReturnDataOutOfBounds);
                 }
+                let return_data = std::mem::take(&mut self.return_data);
+                let dynamic_gas =
+                    self.copy_code(&return_data, dest_offset, offset, size, gas_cost.base);
+                self.return_data = return_data;
+
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + dynamic_gas?,
+                    jump_dest: None,
+                });
+            }
+            Operation::ExtCodeHash => {
+                let address = Address::from_word(B256::from(self.pop()?));
+                let dynamic_gas = self.access_account(address, gas_cost.base)?;
+                // the hash of the deployed code for contracts, EMPTY_CODE_HASH for existing
+                // accounts without code and zero for dead accounts (EIP-1052, EIP-161)
+                let code_hash = {
+                    let state = self.state.lock().unwrap();
+                    match state.contract.get(&address) {
+                        Some(contract) => contract.code_hash(),
+                        None if state.is_dead(&address) => B256::ZERO,
+                        None => state
+                            .accounts
+                            .get(&address)
+                            .map_or(EMPTY_CODE_HASH, |account| account.code_hash),
+                    }
+                };
+                self.push(code_hash.into())?;
 
-                return Ok(ExecutionResult::Success {
-                    return_data: None,
-                    gas_used: gas_cost.base + static_gas + dynamic_gas,
-                    jump_dest: 0,
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + dynamic_gas,
+                    jump_dest: None,
                 });
             }
-            Operation::GasPrice => panic!("{}", not_impl_error),
-            Operation::ExtCodeSize => panic!("{}", not_impl_error),
-            Operation::ExtCodeCopy => panic!("{}", not_impl_error),
-            Operation::ReturnDataSize => panic!("{}", not_impl_error),
-            Operation::ReturnDataCopy => panic!("{}", not_impl_error),
-            Operation::ExtCodeHash => panic!("{}", not_impl_error),
-            Operation::BlockHash => panic!("{}", not_impl_error),
-            Operation::Coinbase => panic!("{}", not_impl_error),
-            Operation::Timestamp => panic!("{}", not_impl_error),
-            Operation::Number => panic!("{}", not_impl_error),
-            Operation::Difficulty => panic!("{}", not_impl_error),
-            Operation::GasLimit => panic!("{}", not_impl_error),
-            Operation::ChainId => panic!("{}", not_impl_error),
-            Operation::SelfBalance => panic!("{}", not_impl_error),
-            Operation::BaseFee => panic!("{}", not_impl_error),
+            Operation::BlockHash => {
+                let number = self.pop()?;
+                // only the 256 most recent blocks are available, excluding the current one
+                let hash = if number < U256::from(self.block.number)
+                    && U256::from(self.block.number) - number <= U256::from(256)
+                {
+                    self.block
+                        .block_hashes
+                        .get(&number.to::<u64>())
+                        .copied()
+                        .unwrap_or_default()
+                } else {
+                    B256::ZERO
+                };
+                self.push(hash.into())?;
+            }
+            Operation::Coinbase => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Timestamp => {
+                self.push(U256::from(self.block.timestamp))?;
+            }
+            Operation::Number => return Err(VMError::NotImplemented(operation.mnemonic())),
+            // PREVRANDAO since the merge (EIP-4399)
+            Operation::Difficulty => {
+                self.push(self.block.prev_randao.into())?;
+            }
+            Operation::GasLimit => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::ChainId => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::SelfBalance => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::BaseFee => return Err(VMError::NotImplemented(operation.mnemonic())),
             Operation::Pop => {
                 self.pop()?; // Simply discard the value at the top of the stack
             }
-            Operation::MLoad => panic!("{}", not_impl_error),
+            Operation::MLoad => {
+                let offset = self.pop_usize()?;
+                let memory_gas = self.expand_memory(offset, 32, gas_cost.base)?;
+                let word = U256::from_be_slice(self.read_from_memory(offset, 32));
+                self.push(word)?;
+
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + memory_gas,
+                    jump_dest: None,
+                });
+            }
             Operation::MStore => {
-                let offset = self.pop()?.to::<usize>();
+                let offset = self.pop_usize()?;
+                let value = self.pop()?;
+                let memory_gas = self.expand_memory(offset, 32, gas_cost.base)?;
+                self.memory[offset..offset + 32].copy_from_slice(&value.to_be_bytes::<32>());
+
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + memory_gas,
+                    jump_dest: None,
+                });
+            }
+            Operation::MStore8 => {
+                let offset = self.pop_usize()?;
                 let value = self.pop()?;
-                self.load_into_memory(offset, value)?;
+                let memory_gas = self.expand_memory(offset, 1, gas_cost.base)?;
+                self.memory[offset] = value.byte(0);
+
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base + memory_gas,
+                    jump_dest: None,
+                });
             }
-            Operation::MStore8 => panic!("{}", not_impl_error),
             Operation::SLoad => {
                 let key = self.pop()?; // Get the storage key from the stack
-                let value = self
-                    .contract
-                    .storage
-                    .get(&key)
-                    .cloned()
-                    .unwrap_or(U256::ZERO);
+                let address = self.context.address;
+                let value = self.sload(&address, key);
+                self.storage_revert.entry((address, key)).or_insert(value);
                 self.push(value)?;
             }
             Operation::SStore => {
+                self.ensure_not_static(operation)?;
                 let storage_key = self.pop()?;
                 let storage_value = self.pop()?;
 
-                let prev_value = self.contract.storage.insert(storage_key, storage_value);
-
-                if prev_value.is_none() {
-                    self.storage_revert
-                        .insert(storage_key, (StorageChangeType::Delete, storage_value));
+                let address = self.context.address;
+                let prev_value = self.sload(&address, storage_key);
+                // the value at the start of the transaction, unless this execution touched the
+                // slot before
+                let original_value = self
+                    .storage_revert
+                    .get(&(address, storage_key))
+                    .copied()
+                    .unwrap_or(prev_value);
+
+                // EIP-2200: a write is never possible on the stipend alone
+                if self.gas_available <= CALL_STIPEND {
+                    return Err(self.out_of_gas(CALL_STIPEND + 1));
+                }
+                let cost = if prev_value == storage_value || original_value != prev_value {
+                    gas_cost.base
+                } else if original_value.is_zero() {
+                    SSTORE_SET_GAS
                 } else {
-                    self.storage_revert
-                        .insert(storage_key, (StorageChangeType::Set, prev_value.unwrap()));
+                    SSTORE_RESET_GAS
+                };
+                if self.gas_available < cost {
+                    return Err(self.out_of_gas(cost));
+                }
+
+                self.state.lock().unwrap().set_storage(
+                    address,
+                    storage_key.into(),
+                    storage_value.into(),
+                );
+                if let Some(trace) = &mut self.storage_trace {
+                    trace.push(StorageWrite {
+                        address,
+                        slot: storage_key,
+                        old_value: prev_value,
+                        new_value: storage_value,
+                        pc: self.pc,
+                    });
+                }
+                self.gas_refund +=
+                    Self::sstore_refund(original_value, prev_value, storage_value, gas_cost.base);
+
+                self.storage_revert
+                    .entry((address, storage_key))
+                    .or_insert(prev_value);
+                return Ok(Step::Continue {
+                    gas_used: cost,
+                    jump_dest: None,
+                });
+            }
+            Operation::Jump => {
+                let offset = self.pop_usize()?;
+
+                if !self.jump_destinations.contains(&offset) {
+                    return Err(VMError::InvalidJump(offset));
                 }
+                return Ok(Step::Continue {
+                    gas_used: gas_cost.base,
+                    jump_dest: Some(offset),
+                });
             }
-            Operation::Jump => panic!("{}", not_impl_error),
             Operation::JumpI => {
-                let offset = self.pop()?.to::<usize>();
+                let offset = self.pop_usize()?;
                 let jump = self.pop()?;
 
                 if !jump.is_zero() {
-                    if let Operation::JumpDest =
-                        Operation::from_byte(self.contract.code[offset], None)
-                            .map_err(|e| VMError::InvalidBytecode)?
-                    {
-                        return Ok(ExecutionResult::Success {
-                            return_data: None,
-                            gas_used: 0,
-                            jump_dest: offset,
-                        });
-                    } else {
-                        return Err(VMError::InvalidJumpDest);
+                    if !self.jump_destinations.contains(&offset) {
+                        return Err(VMError::InvalidJump(offset));
                     }
+                    return Ok(Step::Continue {
+                        gas_used: gas_cost.base,
+                        jump_dest: Some(offset),
+                    });
                 }
             }
-            Operation::PC => panic!("{}", not_impl_error),
-            Operation::MSize => panic!("{}", not_impl_error),
-            Operation::Gas => panic!("{}", not_impl_error),
+            Operation::PC => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::MSize => {
+                self.push(U256::from(self.memory.len()))?;
+            }
+            Operation::Gas => return Err(VMError::NotImplemented(operation.mnemonic())),
             Operation::JumpDest => {
                 // JUMPDEST is a marker for valid jump destinations. It has no effect
                 // on the machine state, so we simply proceed to the next instruction.
                 // No changes are made to the stack, memory, or storage.
             }
             Operation::Push0 => {
+                if self.spec < Spec::Shanghai {
+                    return Err(VMError::InvalidOpcode(operation.mnemonic()));
+                }
                 self.push(U256::ZERO)?;
             }
             Operation::Push1(value)
@@ -564,70 +1652,134 @@ impl VM {
                 if item_num == 0 || item_num > self.stack.len() {
                     return Err(VMError::StackUnderflow);
                 }
-                let item_to_duplicate = self.stack[self.stack.len() - item_num].clone();
+                let item_to_duplicate = self.stack[self.stack.len() - item_num];
                 self.push(item_to_duplicate)?;
             }
-            Operation::Swap1 => panic!("{}", not_impl_error),
-            Operation::Swap2 => panic!("{}", not_impl_error),
-            Operation::Swap3 => panic!("{}", not_impl_error),
-            Operation::Swap4 => panic!("{}", not_impl_error),
-            Operation::Swap5 => panic!("{}", not_impl_error),
-            Operation::Swap6 => panic!("{}", not_impl_error),
-            Operation::Swap7 => panic!("{}", not_impl_error),
-            Operation::Swap8 => panic!("{}", not_impl_error),
-            Operation::Swap9 => panic!("{}", not_impl_error),
-            Operation::Swap10 => panic!("{}", not_impl_error),
-            Operation::Swap11 => panic!("{}", not_impl_error),
-            Operation::Swap12 => panic!("{}", not_impl_error),
-            Operation::Swap13 => panic!("{}", not_impl_error),
-            Operation::Swap14 => panic!("{}", not_impl_error),
-            Operation::Swap15 => panic!("{}", not_impl_error),
-            Operation::Swap16 => panic!("{}", not_impl_error),
-            Operation::Log0 => panic!("{}", not_impl_error),
-            Operation::Log1 => panic!("{}", not_impl_error),
-            Operation::Log2 => panic!("{}", not_impl_error),
-            Operation::Log3 => panic!("{}", not_impl_error),
-            Operation::Log4 => panic!("{}", not_impl_error),
-            Operation::Create => panic!("{}", not_impl_error),
-            Operation::Call => panic!("{}", not_impl_error),
-            Operation::CallCode => panic!("{}", not_impl_error),
+            Operation::Swap1 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap2 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap3 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap4 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap5 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap6 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap7 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap8 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap9 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap10 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap11 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap12 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap13 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap14 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap15 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Swap16 => return Err(VMError::NotImplemented(operation.mnemonic())),
+            Operation::Log0
+            | Operation::Log1
+            | Operation::Log2
+            | Operation::Log3
+            | Operation::Log4
+            | Operation::Create
+            | Operation::Create2
+            | Operation::SelfDestruct => {
+                self.ensure_not_static(operation)?;
+                return Err(VMError::NotImplemented(operation.mnemonic()));
+            }
+            Operation::Call | Operation::CallCode | Operation::DelegateCall => {
+                let kind = match operation {
+                    Operation::Call => CallKind::Call,
+                    Operation::CallCode => CallKind::CallCode,
+                    _ => CallKind::DelegateCall,
+                };
+                let gas = self.pop()?.saturating_to::<u64>();
+                let address = Address::from_word(B256::from(self.pop()?));
+                // DELEGATECALL takes no value argument
+                let value = if kind == CallKind::DelegateCall {
+                    0
+                } else {
+                    self.pop()?.saturating_to::<u64>()
+                };
+                if kind == CallKind::Call && value > 0 {
+                    self.ensure_not_static(operation)?;
+                }
+                let args_offset = self.pop_usize()?;
+                let args_size = self.pop_usize()?;
+                let ret_offset = self.pop_usize()?;
+                let ret_size = self.pop_usize()?;
+
+                let access_gas = self.access_account(address, gas_cost.base)?;
+                // the memory holding both the arguments and the output is paid for upfront
+                let mut memory_gas =
+                    self.expand_memory(args_offset, args_size, gas_cost.base + access_gas)?;
+                memory_gas += self.expand_memory(
+                    ret_offset,
+                    ret_size,
+                    gas_cost.base + access_gas + memory_gas,
+                )?;
+                let args = self.read_from_memory(args_offset, args_size).to_vec();
+
+                let (transfer_cost, stipend) =
+                    self.transfer_value_and_stipend(kind, &address, value);
+                let cost = gas_cost.base + access_gas + memory_gas + transfer_cost;
+                if self.gas_available < cost {
+                    return Err(self.out_of_gas(cost));
+                }
+
+                // EIP-150: all but one 64th of the remaining gas can be forwarded
+                let available = self.gas_available - cost;
+                let gas = gas.min(available - available / 64);
+
+                let outcome = self.message_call(kind, address, value, args, gas + stipend)?;
+
+                // only the first ret_size bytes reach memory, the whole output stays available
+                // to RETURNDATACOPY
+                if ret_size > 0 {
+                    let size = ret_size.min(outcome.output.len());
+                    self.memory[ret_offset..ret_offset + size]
+                        .copy_from_slice(&outcome.output[..size]);
+                }
+                self.return_data = outcome.output;
+                self.push(U256::from(outcome.success))?;
+
+                // the forwarded gas the callee left unused goes back to the caller, and so does
+                // the stipend, which the caller didn't pay for
+                return Ok(Step::Continue {
+                    gas_used: cost + outcome.gas_used - stipend,
+                    jump_dest: None,
+                });
+            }
             Operation::Return => {
-                let offset = self.pop()?.to::<usize>();
-                let size = self.pop()?.to::<usize>();
+                let offset = self.pop_usize()?;
+                let size = self.pop_usize()?;
 
+                let memory_gas = self.expand_memory(offset, size, gas_cost.base)?;
                 let return_data = self.read_from_memory(offset, size);
 
-                return Ok(ExecutionResult::Success {
+                return Ok(Step::Halt(ExecutionResult::Success {
                     return_data: Some(return_data.to_vec()),
-                    gas_used: gas_cost.base,
-                    jump_dest: 0,
-                });
+                    gas_used: gas_cost.base + memory_gas,
+                    storage_writes: vec![],
+                }));
             }
-            Operation::DelegateCall => panic!("{}", not_impl_error),
-            Operation::Create2 => panic!("{}", not_impl_error),
-            Operation::StaticCall => panic!("{}", not_impl_error),
+            Operation::StaticCall => return Err(VMError::NotImplemented(operation.mnemonic())),
             Operation::Revert => {
-                let length = self.pop()?.to::<usize>();
-                let offset = self.pop()?.to::<usize>();
+                let offset = self.pop_usize()?;
+                let length = self.pop_usize()?;
 
-                self.revert_storage();
+                let memory_gas = self.expand_memory(offset, length, gas_cost.base)?;
+                self.revert_state();
                 let revert_data = self.read_from_memory(offset, length);
 
                 // Return the revert result
-                return Ok(ExecutionResult::Revert {
+                return Ok(Step::Halt(ExecutionResult::Revert {
                     reason: revert_data.to_vec(),
-                    gas_used: gas_cost.base,
-                });
+                    gas_used: gas_cost.base + memory_gas,
+                }));
             }
-            Operation::Invalid => panic!("{}", not_impl_error),
-            Operation::SelfDestruct => panic!("{}", not_impl_error),
-            _ => panic!("Unknown operation: {:?}", operation),
+            // the designated invalid instruction halts like any undefined one
+            Operation::Invalid => return Err(VMError::InvalidOpcode(operation.mnemonic())),
         }
 
-        Ok(ExecutionResult::Success {
-            return_data: None,
+        Ok(Step::Continue {
             gas_used: gas_cost.base,
-            jump_dest: 0,
+            jump_dest: None,
         })
     }
 }
@@ -635,17 +1787,21 @@ impl VM {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::hash::hash_string_to_u256;
     use crate::crypto::wallet::Wallet;
     use crate::evm::bytecode_parser::BytecodeParser;
+    use crate::evm::program::Program;
     use crate::transaction::transaction::ETH_TO_WEI;
+    use alloy_primitives::address;
     use alloy_primitives::hex::FromHex;
+    use alloy_primitives::I256;
 
     #[test]
     fn test_add_operation() {
         let code = vec![
             Operation::Push1(U256::from(1)).opcode(),
+            1,
             Operation::Push1(U256::from(1)).opcode(),
+            1,
             Operation::Add.opcode(),
         ];
 
@@ -656,7 +1812,7 @@ mod tests {
                 Address::from_hex("0x169EE3A023A8D9fF2E0D94cf8220b1Ba40D59794").unwrap(),
                 0,
                 vec![],
-                0,
+                100,
             ),
             Arc::new(Mutex::new(State::new())),
         );
@@ -664,17 +1820,64 @@ mod tests {
         assert_eq!(*vm.stack.last().unwrap(), U256::from(2));
     }
 
+    #[test]
+    fn test_signed_operations() {
+        let int = |value: i64| signed::from_signed(I256::try_from(value).unwrap());
+        let int_min = signed::from_signed(I256::MIN);
+        // runs `operation` with `a` on top of `b`
+        let run = |operation: Operation, a: U256, b: U256| {
+            let code = Program::new().push(b).push(a).op(operation).build();
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+                Arc::new(Mutex::new(State::new())),
+            );
+            vm.execute_operations(code).unwrap();
+            vm.stack.pop().unwrap()
+        };
+
+        assert_eq!(run(Operation::SDiv, int(-10), int(3)), int(-3));
+        assert_eq!(run(Operation::SDiv, int(10), int(-3)), int(-3));
+        assert_eq!(run(Operation::SDiv, int_min, int(-1)), int_min);
+        assert_eq!(run(Operation::SDiv, int(-10), int(0)), int(0));
+
+        assert_eq!(run(Operation::SMod, int(-10), int(3)), int(-1));
+        assert_eq!(run(Operation::SMod, int(10), int(-3)), int(1));
+        assert_eq!(run(Operation::SMod, int_min, int(-1)), int(0));
+        assert_eq!(run(Operation::SMod, int(-10), int(0)), int(0));
+
+        assert_eq!(run(Operation::Slt, int(-1), int(0)), int(1));
+        assert_eq!(run(Operation::Slt, int_min, int(-1)), int(1));
+        assert_eq!(run(Operation::Slt, int(0), int(0)), int(0));
+        assert_eq!(run(Operation::Sgt, int(0), int(-1)), int(1));
+        assert_eq!(run(Operation::Sgt, int_min, int(0)), int(0));
+
+        assert_eq!(run(Operation::Sar, int(2), int(-16)), int(-4));
+        assert_eq!(run(Operation::Sar, int(2), int(16)), int(4));
+        assert_eq!(run(Operation::Sar, int(255), int_min), int(-1));
+        assert_eq!(run(Operation::Sar, int(300), int(-1)), int(-1));
+        assert_eq!(run(Operation::Sar, int(300), int(16)), int(0));
+
+        assert_eq!(run(Operation::SignExtend, int(0), int(0xff)), int(-1));
+        assert_eq!(run(Operation::SignExtend, int(0), int(0x7f)), int(0x7f));
+        assert_eq!(
+            run(Operation::SignExtend, int(1), int(0x1_80ff)),
+            int(-0x7f01)
+        );
+        assert_eq!(run(Operation::SignExtend, int(31), int(0xff)), int(0xff));
+    }
+
     #[test]
     fn test_contract_basics() {
         let parser = BytecodeParser::from("./test/Counter.evm").unwrap();
 
         let sender = Wallet::generate();
-        let receiver = Wallet::generate();
+        let _receiver = Wallet::generate();
 
         let mut state = State::new();
         state.set_account(
             sender.address,
-            Account::new(2 * ETH_TO_WEI, B256::ZERO, B256::ZERO),
+            Account::new(2 * ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
         );
 
         let mut vm = VM::new(
@@ -686,7 +1889,7 @@ mod tests {
         let tx_create = Transaction::new(
             Address::ZERO,
             0,
-            1 * ETH_TO_WEI,
+            ETH_TO_WEI,
             100,
             100,
             parser.bytecode,
@@ -695,13 +1898,7 @@ mod tests {
 
         vm.execute_transaction(tx_create).unwrap();
 
-        assert_eq!(
-            *vm.contract
-                .storage
-                .get(&U256::from(0))
-                .unwrap(),
-            U256::from(10)
-        );
+        assert_eq!(vm.storage(U256::ZERO), U256::from(10));
 
         // let tx_inc = Transaction::new(
         //     receiver.address,
@@ -709,18 +1906,73 @@ mod tests {
         //     100,
         //     100,
         //     100,
-        //     hash_string_to_u256("inc()").to_be_bytes::<32>()[..4].to_vec(),
+        //     function_selector("inc()").to_vec(),
         //     Some(&sender.private_key),
         // );
         //
         // vm.execute_transaction(tx_inc).unwrap();
         //
         // assert_eq!(
-        //     *vm.contract.storage.get(&U256::ZERO).unwrap(),
+        //     vm.storage(U256::ZERO),
         //     U256::from(1)
         // );
     }
 
+    #[test]
+    fn test_run_getter() {
+        use crate::crypto::hash::function_selector;
+
+        let selector = function_selector("value()");
+        // returns the slot 0 when called with `value()`, reverts otherwise
+        let code = Program::new()
+            .push(U256::ZERO)
+            .op(Operation::CallDataLoad)
+            // the getter takes no arguments, so the calldata is the selector alone, compared by
+            // adding its negation
+            .push(U256::ZERO.wrapping_sub(U256::from_be_slice(&selector) << 224))
+            .op(Operation::Add)
+            .op(Operation::IsZero)
+            .push(U256::from(44))
+            .op(Operation::JumpI)
+            .op(Operation::Push0)
+            .op(Operation::Push0)
+            .op(Operation::Revert)
+            .op(Operation::JumpDest)
+            .push(U256::ZERO)
+            .op(Operation::SLoad)
+            .push(U256::ZERO)
+            .op(Operation::MStore)
+            .push(U256::from(32))
+            .push(U256::ZERO)
+            .op(Operation::Return)
+            .build();
+        assert_eq!(code[44], Operation::JumpDest.opcode());
+
+        let contract = Address::repeat_byte(0xcc);
+        let mut state = State::new();
+        state.set_storage(contract, B256::ZERO, U256::from(1234).into());
+
+        let mut vm = VM::for_call(
+            code,
+            ExecutionContext::new(Address::ZERO, contract, 0, vec![], 100_000),
+            Arc::new(Mutex::new(state)),
+        );
+        match vm.run(selector.to_vec()).unwrap() {
+            ExecutionResult::Success { return_data, .. } => assert_eq!(
+                return_data.unwrap(),
+                U256::from(1234).to_be_bytes::<32>().to_vec()
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // the VM can be run again with other calldata
+        let other = function_selector("owner()").to_vec();
+        assert!(matches!(
+            vm.run(other).unwrap(),
+            ExecutionResult::Revert { .. }
+        ));
+    }
+
     #[test]
     fn test_storage_revert() {
         let code = vec![
@@ -757,10 +2009,1592 @@ mod tests {
         // Assert that execution resulted in a revert
         // Check that no storage modifications persist after revert
         let key = U256::from(0);
-        assert_eq!(vm.contract.storage.get(&key), None);
+        assert_eq!(vm.storage(key), U256::ZERO);
+        assert!(vm.state.lock().unwrap().storage.is_empty());
         assert!(
             matches!(result, ExecutionResult::Revert { .. }),
             "Expected a revert operation."
         );
     }
+
+    #[test]
+    fn test_revert_restores_value_of_first_read() {
+        let contract = Address::repeat_byte(0xcc);
+        let mut state = State::new();
+        state.set_storage(contract, B256::ZERO, U256::from(7).into());
+
+        let code = Program::new()
+            .op(Operation::Push0)
+            .op(Operation::SLoad)
+            .op(Operation::Pop)
+            .push(U256::from(1))
+            .op(Operation::Push0)
+            .op(Operation::SStore)
+            .push(U256::from(2))
+            .op(Operation::Push0)
+            .op(Operation::SStore)
+            .op(Operation::Push0)
+            .op(Operation::Push0)
+            .op(Operation::Revert)
+            .build();
+        let mut vm = VM::for_call(
+            code,
+            ExecutionContext::new(Address::ZERO, contract, 0, vec![], 100_000),
+            Arc::new(Mutex::new(state)),
+        );
+
+        let result = vm.run(vec![]).unwrap();
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        assert_eq!(vm.storage(U256::ZERO), U256::from(7));
+
+        // a slot that is only read still has its original value recorded
+        let code = Program::new()
+            .push(U256::from(1))
+            .op(Operation::SLoad)
+            .build();
+        let mut vm = VM::for_call(
+            code,
+            ExecutionContext::new(Address::ZERO, contract, 0, vec![], 100_000),
+            vm.state.clone(),
+        );
+        vm.run(vec![]).unwrap();
+        assert_eq!(
+            vm.storage_revert,
+            HashMap::from([((contract, U256::from(1)), U256::ZERO)])
+        );
+    }
+
+    #[test]
+    fn test_invalid_code_discards_writes() {
+        let store = Program::new()
+            .push(U256::from(1))
+            .push(U256::ZERO)
+            .op(Operation::SStore)
+            .build();
+        let mut invalid = store.clone();
+        invalid.push(Operation::Invalid.opcode());
+        let mut undefined = store.clone();
+        undefined.push(0x0c);
+        let mut truncated = store;
+        truncated.push(Operation::Push2(U256::ZERO).opcode());
+        truncated.push(0x01);
+
+        let contract = Address::repeat_byte(0xcc);
+        for code in [invalid, undefined, truncated] {
+            let mut vm = VM::for_call(
+                code.clone(),
+                ExecutionContext::new(Address::ZERO, contract, 0, vec![], 100_000),
+                Arc::new(Mutex::new(State::new())),
+            );
+            assert!(vm.run(vec![]).is_err(), "{}", hex::encode(&code));
+            assert_eq!(vm.storage(U256::ZERO), U256::ZERO);
+            assert_eq!(vm.gas_used(), 100_000);
+        }
+    }
+
+    #[test]
+    fn test_jump_into_push_data() {
+        let code = vec![
+            Operation::Push1(U256::from(0x5b)).opcode(), // JUMPDEST byte as push data
+            0x5b,
+            Operation::Push1(U256::from(1)).opcode(), // Offset of the data byte
+            1,
+            Operation::Jump.opcode(),
+        ];
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100),
+            Arc::new(Mutex::new(State::new())),
+        );
+
+        assert!(matches!(
+            vm.execute_operations(code),
+            Err(VMError::InvalidJump(1))
+        ));
+    }
+
+    #[test]
+    fn test_jump_to_jumpdest() {
+        let code = vec![
+            Operation::Push1(U256::from(4)).opcode(), // Offset of the JUMPDEST
+            4,
+            Operation::Jump.opcode(),
+            Operation::Invalid.opcode(), // Skipped by the jump
+            Operation::JumpDest.opcode(),
+            Operation::Push1(U256::from(7)).opcode(),
+            7,
+        ];
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::from(7)]);
+    }
+
+    #[test]
+    fn test_fuzz_regressions_do_not_panic() {
+        let programs = vec![
+            // Unimplemented opcode
+            vec![Operation::Caller.opcode()],
+            // Jump target that does not fit in usize
+            vec![
+                Operation::Push32(U256::MAX).opcode(),
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                Operation::Jump.opcode(),
+            ],
+        ];
+
+        for code in programs {
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100),
+                Arc::new(Mutex::new(State::new())),
+            );
+            assert!(vm.execute_operations(code).is_err());
+        }
+    }
+
+    #[test]
+    fn test_call_value_rolled_back_on_revert() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+
+        let mut code = vec![
+            Operation::Push1(U256::ZERO).opcode(), // retSize
+            0,
+            Operation::Push1(U256::ZERO).opcode(), // retOffset
+            0,
+            Operation::Push1(U256::ZERO).opcode(), // argsSize
+            0,
+            Operation::Push1(U256::ZERO).opcode(), // argsOffset
+            0,
+            Operation::Push1(U256::from(100)).opcode(), // value
+            100,
+            Operation::Push20(U256::ZERO).opcode(), // address
+        ];
+        code.extend_from_slice(callee.as_slice());
+        code.extend_from_slice(&[
+            Operation::Push2(U256::ZERO).opcode(), // gas
+            0xff,
+            0xff,
+            Operation::Call.opcode(),
+        ]);
+
+        let mut state = State::new();
+        state.set_account(caller, Account::new(1000, EMPTY_CODE_HASH, B256::ZERO));
+        state.contract.insert(
+            callee,
+            Contract::new(vec![
+                Operation::Push0.opcode(),
+                Operation::Push0.opcode(),
+                Operation::Revert.opcode(),
+            ]),
+        );
+        let state = Arc::new(Mutex::new(state));
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+            state.clone(),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::ZERO]);
+        let mut state = state.lock().unwrap();
+        assert_eq!(state.get_account(&caller).unwrap().balance, 1000);
+        assert!(state.get_account(&callee).is_none());
+    }
+
+    #[test]
+    fn test_codecopy_charges_expansion_once() {
+        let code = vec![
+            Operation::Push1(U256::from(64)).opcode(), // Size
+            64,
+            Operation::Push0.opcode(), // Offset
+            Operation::Push0.opcode(), // Dest offset
+            Operation::CodeCopy.opcode(),
+        ];
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        let result = vm.execute_operations(code).unwrap();
+
+        // PUSH1 + 2 * PUSH0 + CODECOPY(3 + 3 * 2 words + expansion of 2 words)
+        let expansion = VM::calc_memory_expansion_gas(64);
+        assert_eq!(expansion, 6);
+        match result {
+            ExecutionResult::Success { gas_used, .. } => {
+                assert_eq!(gas_used, 3 + 2 * 2 + (3 + 3 * 2 + expansion))
+            }
+            _ => panic!("CODECOPY should succeed"),
+        }
+        assert_eq!(vm.memory.len(), 64);
+    }
+
+    #[test]
+    fn test_mstore8_charges_expansion() {
+        let code = Program::new()
+            .push(U256::from(0xab)) // Value
+            .push(U256::from(64)) // Offset
+            .op(Operation::MStore8)
+            .push(U256::from(0xcd)) // Value
+            .push(U256::from(65)) // Offset
+            .op(Operation::MStore8)
+            .build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.execute_operations(code).unwrap();
+
+        // the first write grows the memory to 3 words, the second lands in the last of them
+        let expansion = VM::calc_memory_expansion_gas(96);
+        assert_eq!(expansion, 9);
+        assert_eq!(vm.gas_used(), 4 * 3 + 2 * 3 + expansion);
+        assert_eq!(vm.memory.len(), 96);
+        assert_eq!(vm.memory[64..66], [0xab, 0xcd]);
+    }
+
+    #[test]
+    fn test_extcodehash_distinguishes_empty_and_missing_accounts() {
+        let eoa = Wallet::generate().address;
+        let missing = Wallet::generate().address;
+        let empty = Wallet::generate().address;
+        let contract = Wallet::generate().address;
+        let contract_code = Contract::from_hex("0x6001600201").unwrap();
+
+        let state = Arc::new(Mutex::new(State::new()));
+        {
+            let mut state = state.lock().unwrap();
+            state.set_account(
+                eoa,
+                Account {
+                    balance: ETH_TO_WEI,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(state.get_account(&eoa).unwrap().code_hash, EMPTY_CODE_HASH);
+            state.set_account(empty, Account::default());
+            state.contract.insert(contract, contract_code.clone());
+        }
+
+        let mut program = Program::new();
+        for address in [eoa, missing, empty, contract] {
+            program = program
+                .push(U256::from_be_slice(address.as_slice()))
+                .op(Operation::ExtCodeHash);
+        }
+        let code = program.build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100_000),
+            state,
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(
+            vm.stack,
+            vec![
+                EMPTY_CODE_HASH.into(),
+                U256::ZERO,
+                U256::ZERO,
+                keccak256(&contract_code.code).into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_precompile_accounts_exist_without_code() {
+        let state = State::new().with_precompiles();
+        let contract = Wallet::generate().address;
+        let contract_code = Contract::from_hex("0x6001600201").unwrap();
+        let state = Arc::new(Mutex::new(state));
+        state
+            .lock()
+            .unwrap()
+            .contract
+            .insert(contract, contract_code.clone());
+
+        let mut program = Program::new();
+        for address in [
+            precompiles::IDENTITY,
+            Address::with_last_byte(0x0a),
+            contract,
+        ] {
+            program = program
+                .push(U256::from_be_slice(address.as_slice()))
+                .op(Operation::ExtCodeSize)
+                .push(U256::from_be_slice(address.as_slice()))
+                .op(Operation::ExtCodeHash);
+        }
+        let code = program.build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 10_000),
+            state.clone(),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(
+            vm.stack,
+            vec![
+                U256::ZERO,
+                EMPTY_CODE_HASH.into(),
+                // past the last precompile
+                U256::ZERO,
+                U256::ZERO,
+                U256::from(contract_code.size()),
+                keccak256(&contract_code.code).into()
+            ]
+        );
+
+        // the precompile accounts are never pruned, although empty
+        let mut state = state.lock().unwrap();
+        for address in precompiles::reserved_addresses() {
+            state.remove_if_empty(&address);
+            assert_eq!(state.get_account(&address).unwrap().balance, 0);
+        }
+    }
+
+    #[test]
+    fn test_callcode_and_delegatecall_callvalue() {
+        let caller = Address::repeat_byte(0xaa);
+        let library = Address::repeat_byte(0xbb);
+
+        // stores CALLVALUE at slot 0 of the storage it runs against
+        let library_code = vec![
+            Operation::CallValue.opcode(),
+            Operation::Push0.opcode(),
+            Operation::SStore.opcode(),
+        ];
+
+        let run = |operation: Operation| {
+            let mut code = vec![
+                Operation::Push0.opcode(), // retSize
+                Operation::Push0.opcode(), // retOffset
+                Operation::Push0.opcode(), // argsSize
+                Operation::Push0.opcode(), // argsOffset
+            ];
+            if let Operation::CallCode = operation {
+                // value
+                code.extend_from_slice(&[Operation::Push1(U256::ZERO).opcode(), 7]);
+            }
+            code.push(Operation::Push20(U256::ZERO).opcode()); // address
+            code.extend_from_slice(library.as_slice());
+            code.extend_from_slice(&[
+                Operation::Push2(U256::ZERO).opcode(), // gas
+                0xff,
+                0xff,
+                operation.opcode(),
+            ]);
+
+            let mut state = State::new();
+            state.set_account(caller, Account::new(1000, EMPTY_CODE_HASH, B256::ZERO));
+            state
+                .contract
+                .insert(library, Contract::new(library_code.clone()));
+            let state = Arc::new(Mutex::new(state));
+
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, caller, 5, vec![], 100_000),
+                state.clone(),
+            );
+            vm.execute_operations(code).unwrap();
+
+            assert_eq!(vm.stack, vec![U256::from(1)]);
+            let state = state.lock().unwrap();
+            assert_eq!(state.get_storage(&library, &B256::ZERO), B256::ZERO);
+            U256::from_be_bytes(state.get_storage(&caller, &B256::ZERO).0)
+        };
+
+        // CALLCODE sees the value of the call itself, DELEGATECALL the one of the caller
+        assert_eq!(run(Operation::CallCode), U256::from(7));
+        assert_eq!(run(Operation::DelegateCall), U256::from(5));
+    }
+
+    #[test]
+    fn test_call_identity_precompile_with_value() {
+        let caller = Address::repeat_byte(0xaa);
+        let input = U256::from(0xdeadbeefu64);
+
+        let code = Program::new()
+            .push(input) // Value
+            .push(U256::ZERO) // Offset
+            .op(Operation::MStore)
+            .push(U256::from(32)) // retSize
+            .push(U256::from(32)) // retOffset
+            .push(U256::from(32)) // argsSize
+            .push(U256::ZERO) // argsOffset
+            .push(U256::from(5)) // value
+            .push(U256::from_be_slice(precompiles::IDENTITY.as_slice())) // address
+            .push(U256::from(1000)) // gas
+            .op(Operation::Call)
+            .build();
+
+        let mut state = State::new();
+        state.set_account(caller, Account::new(1000, EMPTY_CODE_HASH, B256::ZERO));
+        let state = Arc::new(Mutex::new(state));
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+            state.clone(),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::from(1)]);
+        assert_eq!(vm.memory[32..64], vm.memory[..32]);
+        assert_eq!(U256::from_be_slice(&vm.memory[32..64]), input);
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.accounts[&precompiles::IDENTITY].balance, 5);
+        assert_eq!(state.accounts[&caller].balance, 995);
+    }
+
+    #[test]
+    fn test_call_output_only_reaches_return_range() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+        let marker = U256::from_be_bytes([0x11; 32]);
+
+        // the callee returns its first word, but also writes far beyond it
+        let callee_code = Program::new()
+            .push(U256::MAX) // Value
+            .push(U256::ZERO) // Offset
+            .op(Operation::MStore)
+            .push(U256::from_be_bytes([0xee; 32])) // Value
+            .push(U256::from(256)) // Offset
+            .op(Operation::MStore)
+            .push(U256::from(32)) // Size
+            .push(U256::ZERO) // Offset
+            .op(Operation::Return)
+            .build();
+        let code = Program::new()
+            .push(marker)
+            .push(U256::ZERO)
+            .op(Operation::MStore)
+            .push(marker)
+            .push(U256::from(32))
+            .op(Operation::MStore)
+            .push(marker)
+            .push(U256::from(64))
+            .op(Operation::MStore)
+            .push(U256::from(16)) // retSize
+            .push(U256::from(32)) // retOffset
+            .op(Operation::Push0) // argsSize
+            .op(Operation::Push0) // argsOffset
+            .op(Operation::Push0) // value
+            .push(U256::from_be_slice(callee.as_slice())) // address
+            .push(U256::from(10_000)) // gas
+            .op(Operation::Call)
+            .build();
+
+        let mut state = State::new();
+        state.set_account(caller, Account::new(0, EMPTY_CODE_HASH, B256::ZERO));
+        state.contract.insert(callee, Contract::new(callee_code));
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+            Arc::new(Mutex::new(state)),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::from(1)]);
+        assert_eq!(vm.memory.len(), 96);
+        assert_eq!(vm.memory[32..48], [0xff; 16]);
+        assert!(vm.memory[..32].iter().all(|&byte| byte == 0x11));
+        assert!(vm.memory[48..].iter().all(|&byte| byte == 0x11));
+    }
+
+    #[test]
+    fn test_call_returns_unused_gas() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+
+        let callee_code = Program::new()
+            .push(U256::from(1))
+            .push(U256::from(2))
+            .op(Operation::Add)
+            .build();
+        let code = Program::new()
+            .op(Operation::Push0) // retSize
+            .op(Operation::Push0) // retOffset
+            .op(Operation::Push0) // argsSize
+            .op(Operation::Push0) // argsOffset
+            .op(Operation::Push0) // value
+            .push(U256::from_be_slice(callee.as_slice())) // address
+            .push(U256::from(50_000)) // gas
+            .op(Operation::Call)
+            .build();
+
+        let mut state = State::new();
+        state.set_account(caller, Account::new(0, EMPTY_CODE_HASH, B256::ZERO));
+        state.contract.insert(callee, Contract::new(callee_code));
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+            Arc::new(Mutex::new(state)),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::from(1)]);
+        // 5 PUSH0 + 2 PUSH + CALL to a cold address, plus the 2 PUSH1 and ADD of the callee
+        let gas_used = 5 * 2 + 2 * 3 + COLD_ACCOUNT_ACCESS_COST + 3 * 3;
+        assert_eq!(vm.gas_used(), gas_used);
+        assert_eq!(vm.gas_available, 100_000 - gas_used);
+    }
+
+    #[test]
+    fn test_call_out_of_gas_rolls_back_callee() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+
+        // the SSTORE needs far more than the forwarded gas
+        let callee_code = Program::new()
+            .push(U256::from(1)) // Value
+            .push(U256::ZERO) // Key
+            .op(Operation::SStore)
+            .build();
+        let code = Program::new()
+            .op(Operation::Push0) // retSize
+            .op(Operation::Push0) // retOffset
+            .op(Operation::Push0) // argsSize
+            .op(Operation::Push0) // argsOffset
+            .push(U256::from(5)) // value
+            .push(U256::from_be_slice(callee.as_slice())) // address
+            .push(U256::from(100)) // gas
+            .op(Operation::Call)
+            .push(U256::from(7))
+            .build();
+
+        let mut state = State::new();
+        state.set_account(caller, Account::new(1000, EMPTY_CODE_HASH, B256::ZERO));
+        state.contract.insert(callee, Contract::new(callee_code));
+        let state = Arc::new(Mutex::new(state));
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+            state.clone(),
+        );
+        vm.execute_operations(code).unwrap();
+
+        // the caller sees the failure and carries on
+        assert_eq!(vm.stack, vec![U256::ZERO, U256::from(7)]);
+        // 4 PUSH0 + 3 PUSH + cold CALL with value + the forwarded gas, all consumed by the callee
+        // along with the stipend + PUSH1
+        let gas_used =
+            4 * 2 + 3 * 3 + COLD_ACCOUNT_ACCESS_COST + CALL_VALUE_TRANSFER_COST + 100 + 3;
+        assert_eq!(vm.gas_used(), gas_used);
+        assert_eq!(vm.gas_available, 100_000 - gas_used);
+
+        let state = state.lock().unwrap();
+        assert!(state.storage.is_empty());
+        assert_eq!(state.accounts[&caller].balance, 1000);
+        assert!(!state.accounts.contains_key(&callee));
+    }
+
+    #[test]
+    fn test_revert_undoes_transfers_of_nested_calls() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+        let recipient = Address::repeat_byte(0xcc);
+
+        // forwards part of the value it received, then reverts
+        let callee_code = Program::new()
+            .op(Operation::Push0) // retSize
+            .op(Operation::Push0) // retOffset
+            .op(Operation::Push0) // argsSize
+            .op(Operation::Push0) // argsOffset
+            .push(U256::from(3)) // value
+            .push(U256::from_be_slice(recipient.as_slice())) // address
+            .op(Operation::Push0) // gas
+            .op(Operation::Call)
+            .op(Operation::Push0)
+            .op(Operation::Push0)
+            .op(Operation::Revert)
+            .build();
+        let code = Program::new()
+            .op(Operation::Push0) // retSize
+            .op(Operation::Push0) // retOffset
+            .op(Operation::Push0) // argsSize
+            .op(Operation::Push0) // argsOffset
+            .push(U256::from(5)) // value
+            .push(U256::from_be_slice(callee.as_slice())) // address
+            .push(U256::from(50_000)) // gas
+            .op(Operation::Call)
+            .build();
+
+        let mut state = State::new();
+        state.set_account(caller, Account::new(1000, EMPTY_CODE_HASH, B256::ZERO));
+        state.contract.insert(callee, Contract::new(callee_code));
+        let state = Arc::new(Mutex::new(state));
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+            state.clone(),
+        );
+        vm.execute_operations(code).unwrap();
+        assert_eq!(vm.stack, vec![U256::ZERO]);
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.accounts[&caller].balance, 1000);
+        assert!(!state.accounts.contains_key(&callee));
+        assert!(!state.accounts.contains_key(&recipient));
+    }
+
+    #[test]
+    fn test_mstore_offset_overflow() {
+        let offset = U256::from(usize::MAX - 5);
+        let mut code = vec![
+            Operation::Push1(U256::ZERO).opcode(), // Value
+            1,
+            Operation::Push32(U256::ZERO).opcode(), // Offset
+        ];
+        code.extend_from_slice(&offset.to_be_bytes::<32>());
+        code.push(Operation::MStore.opcode());
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1_000_000),
+            Arc::new(Mutex::new(State::new())),
+        );
+
+        assert!(matches!(
+            vm.execute_operations(code),
+            Err(VMError::OutOfGas { .. })
+        ));
+        assert!(vm.memory.is_empty());
+    }
+
+    #[test]
+    fn test_static_context_rejects_state_changes() {
+        let code = Program::new()
+            .push(U256::from(2)) // Value
+            .push(U256::from(1)) // Key
+            .op(Operation::SStore)
+            .build();
+        let run = |is_static: bool| {
+            let mut state = State::new();
+            state.set_storage(Address::ZERO, U256::from(1).into(), U256::from(1).into());
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100_000),
+                Arc::new(Mutex::new(state)),
+            );
+            if is_static {
+                vm = vm.with_static();
+            }
+            let result = vm.execute_operations(code.clone());
+            let storage = vm.state.lock().unwrap().storage.clone();
+            (result, storage)
+        };
+
+        let slot = (Address::ZERO, U256::from(1).into());
+        let (result, storage) = run(false);
+        assert!(result.is_ok());
+        assert_eq!(storage[&slot], B256::from(U256::from(2)));
+
+        let (result, storage) = run(true);
+        assert!(matches!(
+            result,
+            Err(VMError::StateChangeInStaticCall("SSTORE"))
+        ));
+        assert_eq!(storage.len(), 1);
+        assert_eq!(storage[&slot], B256::from(U256::from(1)));
+
+        let code = Program::new()
+            .op(Operation::Push0)
+            .op(Operation::Push0)
+            .op(Operation::Log0)
+            .build();
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100_000),
+            Arc::new(Mutex::new(State::new())),
+        )
+        .with_static();
+        assert!(matches!(
+            vm.execute_operations(code),
+            Err(VMError::StateChangeInStaticCall("LOG0"))
+        ));
+    }
+
+    #[test]
+    fn test_out_of_gas_reports_shortfall() {
+        let code = Program::new()
+            .push(U256::from(1))
+            .push(U256::from(2))
+            .op(Operation::Add)
+            .build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 8),
+            Arc::new(Mutex::new(State::new())),
+        );
+        let err = vm.execute_operations(code).unwrap_err();
+
+        assert!(matches!(
+            err,
+            VMError::OutOfGas {
+                needed: 3,
+                available: 2
+            }
+        ));
+        assert_eq!(err.to_string(), "out of gas: 3 needed, 2 available");
+    }
+
+    #[test]
+    fn test_prevrandao() {
+        let mut block = BlockEnv::new(1, 10);
+        block.prev_randao = B256::repeat_byte(0x42);
+
+        let code = Program::new().op(Operation::Difficulty).build();
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+            Arc::new(Mutex::new(State::new())),
+        )
+        .with_block_env(block);
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::from_be_bytes([0x42; 32])]);
+        assert_eq!(vm.gas_used(), 2);
+    }
+
+    #[test]
+    fn test_blockhash() {
+        let mut block = BlockEnv::new(300, 10);
+        for number in [10, 44, 299] {
+            block.block_hashes.insert(number, U256::from(number).into());
+        }
+
+        let code = vec![
+            Operation::Push2(U256::ZERO).opcode(), // in range
+            0x01,
+            0x2b,
+            Operation::BlockHash.opcode(),
+            Operation::Push1(U256::ZERO).opcode(), // more than 256 blocks ago
+            10,
+            Operation::BlockHash.opcode(),
+            Operation::Push1(U256::ZERO).opcode(), // oldest block in range
+            44,
+            Operation::BlockHash.opcode(),
+            Operation::Push2(U256::ZERO).opcode(), // current block
+            0x01,
+            0x2c,
+            Operation::BlockHash.opcode(),
+        ];
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+            Arc::new(Mutex::new(State::new())),
+        )
+        .with_block_env(block);
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(
+            vm.stack,
+            vec![U256::from(299), U256::ZERO, U256::from(44), U256::ZERO]
+        );
+    }
+
+    #[test]
+    fn test_halting_operations_stop_execution() {
+        let run = |halt: Operation| {
+            let code = Program::new()
+                .push(U256::from(1))
+                .push(U256::ZERO)
+                .op(halt)
+                .push(U256::from(42))
+                .push(U256::ZERO)
+                .op(Operation::SStore)
+                .build();
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100_000),
+                Arc::new(Mutex::new(State::new())),
+            );
+            let result = vm.execute_operations(code).unwrap();
+            (result, vm)
+        };
+
+        let (result, vm) = run(Operation::Return);
+        assert!(matches!(
+            result,
+            ExecutionResult::Success { return_data: Some(data), .. } if data == vec![0]
+        ));
+        assert!(vm.stack.is_empty());
+        assert!(vm.state.lock().unwrap().storage.is_empty());
+        // only the two pushes before the RETURN and the word of memory it reads are charged
+        assert_eq!(vm.gas_used(), 6 + VM::calc_memory_expansion_gas(1));
+
+        let (result, vm) = run(Operation::Revert);
+        assert!(matches!(result, ExecutionResult::Revert { .. }));
+        assert!(vm.state.lock().unwrap().storage.is_empty());
+
+        let (result, vm) = run(Operation::Stop);
+        assert!(matches!(
+            result,
+            ExecutionResult::Success {
+                return_data: None,
+                ..
+            }
+        ));
+        assert_eq!(vm.stack, vec![U256::from(1), U256::ZERO]);
+        assert!(vm.state.lock().unwrap().storage.is_empty());
+    }
+
+    #[test]
+    fn test_storage_trace() {
+        let code = Program::new()
+            .push(U256::from(7)) // Value
+            .push(U256::ZERO) // Key
+            .op(Operation::SStore)
+            .push(U256::from(9)) // Value
+            .push(U256::from(1)) // Key
+            .op(Operation::SStore)
+            .build();
+        let address = Wallet::generate().address;
+        let mut state = State::new();
+        state.set_storage(address, U256::from(1).into(), U256::from(5).into());
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, address, 0, vec![], 100_000),
+            Arc::new(Mutex::new(state)),
+        )
+        .with_storage_trace();
+        let result = vm.execute_operations(code).unwrap();
+
+        let ExecutionResult::Success { storage_writes, .. } = result else {
+            panic!("expected the execution to succeed");
+        };
+        assert_eq!(
+            storage_writes,
+            vec![
+                StorageWrite {
+                    address,
+                    slot: U256::ZERO,
+                    old_value: U256::ZERO,
+                    new_value: U256::from(7),
+                    pc: 4,
+                },
+                StorageWrite {
+                    address,
+                    slot: U256::from(1),
+                    old_value: U256::from(5),
+                    new_value: U256::from(9),
+                    pc: 9,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extcodecopy() {
+        let missing = Wallet::generate().address;
+        let existing = Wallet::generate().address;
+        let extcodecopy = |program: Program, address: Address, dest_offset: u64, size: u64| {
+            program
+                .push(U256::from(size))
+                .push(U256::ZERO) // Offset in the code
+                .push(U256::from(dest_offset))
+                .push(U256::from_be_slice(address.as_slice()))
+                .op(Operation::ExtCodeCopy)
+        };
+
+        let program = Program::new()
+            .push(U256::MAX) // Value
+            .push(U256::ZERO) // Offset
+            .op(Operation::MStore);
+        let program = extcodecopy(program, missing, 0, 32);
+        let code = extcodecopy(program, existing, 32, 4).build();
+
+        let mut state = State::new();
+        state
+            .contract
+            .insert(existing, Contract::new(vec![0xaa, 0xbb]));
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100_000),
+            Arc::new(Mutex::new(state)),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.memory[..32], [0u8; 32]);
+        assert_eq!(vm.memory[32..36], [0xaa, 0xbb, 0, 0]);
+    }
+
+    #[test]
+    fn test_gas_schedule_override() {
+        let code = Program::new()
+            .push(U256::from(1))
+            .push(U256::from(2))
+            .op(Operation::Add)
+            .build();
+        let run = |gas_schedule: GasSchedule| {
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+                Arc::new(Mutex::new(State::new())),
+            )
+            .with_gas_schedule(gas_schedule);
+            vm.execute_operations(code.clone()).unwrap();
+            vm.gas_used()
+        };
+
+        assert_eq!(run(GasSchedule::default()), 9);
+        let expensive_add = GasSchedule::default().with_base_cost(Operation::Add.opcode(), 10);
+        assert_eq!(run(expensive_add), 16);
+    }
+
+    #[test]
+    fn test_keccak256() {
+        let run = |code: Vec<u8>| {
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+                Arc::new(Mutex::new(State::new())),
+            );
+            vm.execute_operations(code).map(|_| vm.stack)
+        };
+
+        assert!(matches!(
+            run(vec![Operation::Keccak256.opcode()]),
+            Err(VMError::NotEnoughItemsOnStack(_))
+        ));
+
+        let code = Program::new()
+            .push(U256::ZERO) // Size
+            .push(U256::ZERO) // Offset
+            .op(Operation::Keccak256)
+            .build();
+        assert_eq!(
+            run(code).unwrap(),
+            vec![U256::from_be_bytes(EMPTY_CODE_HASH.0)]
+        );
+    }
+
+    #[test]
+    fn test_push0_requires_shanghai() {
+        let run = |spec: Spec| {
+            let code = vec![Operation::Push0.opcode()];
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+                Arc::new(Mutex::new(State::new())),
+            )
+            .with_spec(spec);
+            vm.execute_operations(code).map(|_| vm.stack)
+        };
+
+        assert!(matches!(
+            run(Spec::London),
+            Err(VMError::InvalidOpcode("PUSH0"))
+        ));
+        assert_eq!(run(Spec::Shanghai).unwrap(), vec![U256::ZERO]);
+    }
+
+    #[test]
+    fn test_balance_of_recipient_is_warm() {
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+        let other = Wallet::generate().address;
+
+        let code = Program::new()
+            .push(U256::from_be_slice(contract_address.as_slice()))
+            .op(Operation::Balance)
+            .push(U256::from_be_slice(other.as_slice()))
+            .op(Operation::Balance)
+            .build();
+
+        let mut state = State::new();
+        state.set_account(other, Account::new(7, EMPTY_CODE_HASH, B256::ZERO));
+        let mut vm = VM::new(
+            Contract::new(code),
+            ExecutionContext::default(),
+            Arc::new(Mutex::new(state)),
+        );
+        let tx = Transaction::new(
+            contract_address,
+            0,
+            100_000,
+            0,
+            100,
+            vec![],
+            Some(&sender.private_key),
+        );
+        vm.execute_transaction(tx).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::ZERO, U256::from(7)]);
+        assert_eq!(
+            vm.gas_used(),
+            3 + WARM_STORAGE_READ_COST + 3 + COLD_ACCOUNT_ACCESS_COST
+        );
+    }
+
+    #[test]
+    fn test_account_access_opcodes_share_warm_addresses() {
+        let other = Address::repeat_byte(0xbb);
+        let word = U256::from_be_slice(other.as_slice());
+
+        // the first access is cold whichever opcode makes it, the later ones are warm
+        let code = Program::new()
+            .push(word)
+            .op(Operation::ExtCodeSize)
+            .push(word)
+            .op(Operation::ExtCodeHash)
+            .op(Operation::Push0) // retSize
+            .op(Operation::Push0) // retOffset
+            .op(Operation::Push0) // argsSize
+            .op(Operation::Push0) // argsOffset
+            .op(Operation::Push0) // value
+            .push(word) // address
+            .op(Operation::Push0) // gas
+            .op(Operation::Call)
+            .push(word)
+            .op(Operation::Balance)
+            .build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 100_000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(
+            vm.gas_used(),
+            4 * 3 + 6 * 2 + COLD_ACCOUNT_ACCESS_COST + 3 * WARM_STORAGE_READ_COST
+        );
+    }
+
+    #[test]
+    fn test_display_execution_result() {
+        // Error("not owner")
+        let mut reason = ERROR_STRING_SELECTOR.to_vec();
+        reason.extend_from_slice(&U256::from(32).to_be_bytes::<32>());
+        reason.extend_from_slice(&U256::from(9).to_be_bytes::<32>());
+        reason.extend_from_slice(&[b"not owner".as_slice(), &[0; 23]].concat());
+
+        let result = ExecutionResult::Revert {
+            reason,
+            gas_used: 120,
+        };
+        assert_eq!(result.revert_message().as_deref(), Some("not owner"));
+        assert_eq!(
+            result.to_string(),
+            "revert (gas used: 120, reason: \"not owner\")"
+        );
+
+        let result = ExecutionResult::Revert {
+            reason: vec![0xde, 0xad],
+            gas_used: 3,
+        };
+        assert_eq!(result.to_string(), "revert (gas used: 3, reason: 0xdead)");
+
+        let result = ExecutionResult::Success {
+            return_data: Some(vec![0x2a]),
+            gas_used: 21,
+            storage_writes: vec![],
+        };
+        assert_eq!(
+            result.to_string(),
+            "success (gas used: 21, return data: 0x2a)"
+        );
+    }
+
+    #[test]
+    fn test_calldataload_pads_tail_with_zeros() {
+        let data: Vec<u8> = (1..=20).collect();
+        let code = vec![
+            Operation::Push1(U256::ZERO).opcode(),
+            10,
+            Operation::CallDataLoad.opcode(),
+        ];
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, data, 1000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.execute_operations(code).unwrap();
+
+        let mut expected = [0u8; 32];
+        expected[..10].copy_from_slice(&(11..=20).collect::<Vec<u8>>());
+        assert_eq!(vm.stack, vec![U256::from_be_bytes(expected)]);
+    }
+
+    #[test]
+    fn test_memory_grows_by_words() {
+        let code = Program::new()
+            .push(U256::from(0xabcd)) // Value
+            .push(U256::from(1)) // Offset
+            .op(Operation::MStore8)
+            .op(Operation::MSize)
+            .push(U256::from(1)) // Value
+            .push(U256::from(40)) // Offset
+            .op(Operation::MStore)
+            .op(Operation::MSize)
+            .build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack, vec![U256::from(32), U256::from(96)]);
+        assert_eq!(vm.memory[..2], [0x00, 0xcd]);
+    }
+
+    #[test]
+    fn test_untouched_memory_reads_as_zero() {
+        // fill most of the stack and the first word of memory before reading far past it
+        let mut program = Program::new();
+        for _ in 0..1000 {
+            program = program.push(U256::MAX);
+        }
+        let code = program
+            .op(Operation::Push0) // Offset
+            .op(Operation::MStore)
+            .push(U256::from(0x1000)) // Offset
+            .op(Operation::MLoad)
+            .build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1_000_000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(vm.stack.len(), 1000);
+        assert_eq!(vm.stack.last(), Some(&U256::ZERO));
+        assert_eq!(vm.memory[..32], [0xff; 32]);
+        assert_eq!(vm.memory.len(), 0x1020);
+        assert!(vm.memory[32..].iter().all(|&byte| byte == 0));
+
+        // any read past the end of the memory is zero, whatever its offset and length
+        let mut seed: usize = 0x2545f491;
+        for _ in 0..100 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let offset = vm.memory.len() + (seed >> 16) % 0x400;
+            let length = 1 + (seed >> 40) % 200;
+            vm.expand_memory(offset, length, 0).unwrap();
+            let bytes = vm.read_from_memory(offset, length);
+            assert_eq!(bytes.len(), length);
+            assert!(bytes.iter().all(|&byte| byte == 0));
+            // dirty the memory right after the read before growing it again
+            vm.expand_memory(offset + length, 32, 0).unwrap();
+            vm.memory[offset + length..offset + length + 32].fill(0xff);
+        }
+    }
+
+    #[test]
+    fn test_execution_context_clone() {
+        let context = ExecutionContext::new(
+            Address::repeat_byte(0xaa),
+            Address::repeat_byte(0xbb),
+            5,
+            vec![0x01, 0x02],
+            21000,
+        );
+        let mut callee_context = context.clone();
+        assert_eq!(callee_context, context);
+
+        callee_context.data.push(0x03);
+        assert_ne!(callee_context, context);
+        assert_eq!(context.data, vec![0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_dump_memory() {
+        let code = vec![
+            Operation::Push1(U256::ZERO).opcode(), // Value
+            0xaa,
+            Operation::Push0.opcode(), // Offset
+            Operation::MStore.opcode(),
+            Operation::Push1(U256::ZERO).opcode(), // Value
+            0xbb,
+            Operation::Push1(U256::ZERO).opcode(), // Offset
+            0x20,
+            Operation::MStore.opcode(),
+            Operation::Push1(U256::ZERO).opcode(),
+            0x01,
+        ];
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1000),
+            Arc::new(Mutex::new(State::new())),
+        );
+        vm.execute_operations(code).unwrap();
+
+        assert_eq!(
+            vm.dump_memory(),
+            format!("0x0000: {:0>64}\n0x0020: {:0>64}", "aa", "bb")
+        );
+        assert_eq!(vm.dump_stack(), vec![U256::from(1)]);
+    }
+
+    #[test]
+    fn test_contract_size_and_code_hash() {
+        let empty = Contract::new(vec![]);
+        assert_eq!(empty.size(), 0);
+        assert_eq!(empty.code_hash(), EMPTY_CODE_HASH);
+
+        let contract = Contract::from_hex("0x6001600201").unwrap();
+        assert_eq!(contract.size(), 5);
+        assert_eq!(contract.code_hash(), keccak256(&contract.code));
+    }
+
+    #[test]
+    fn test_contract_from_hex() {
+        let contract = Contract::from_hex("0x6001600201").unwrap();
+        assert_eq!(contract.code, vec![0x60, 0x01, 0x60, 0x02, 0x01]);
+        assert_eq!(Contract::from_hex("6001").unwrap().code, vec![0x60, 0x01]);
+
+        assert!(matches!(
+            Contract::from_hex("0x600"),
+            Err(hex::FromHexError::OddLength)
+        ));
+        assert!(matches!(
+            Contract::from_hex("0x60zz"),
+            Err(hex::FromHexError::InvalidHexCharacter { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sstore_writes_state_storage() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+
+        let callee_code = Program::new()
+            .push(U256::from(9)) // Value
+            .push(U256::from(1)) // Key
+            .op(Operation::SStore)
+            .build();
+        let run = |halt: Operation| {
+            let code = Program::new()
+                .push(U256::from(5)) // Value
+                .op(Operation::Push0) // Key
+                .op(Operation::SStore)
+                .op(Operation::Push0) // retSize
+                .op(Operation::Push0) // retOffset
+                .op(Operation::Push0) // argsSize
+                .op(Operation::Push0) // argsOffset
+                .op(Operation::Push0) // value
+                .push(U256::from_be_slice(callee.as_slice())) // address
+                .push(U256::from(30_000)) // gas
+                .op(Operation::Call)
+                .op(Operation::Push0)
+                .op(Operation::Push0)
+                .op(halt)
+                .build();
+
+            let mut state = State::new();
+            state
+                .contract
+                .insert(callee, Contract::new(callee_code.clone()));
+            let state = Arc::new(Mutex::new(state));
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+                state.clone(),
+            );
+            vm.execute_operations(code).unwrap();
+
+            let state = state.lock().unwrap();
+            (
+                state.get_storage(&caller, &B256::ZERO),
+                state.get_storage(&callee, &U256::from(1).into()),
+            )
+        };
+
+        // each contract writes the canonical storage of its own address
+        assert_eq!(
+            run(Operation::Return),
+            (U256::from(5).into(), U256::from(9).into())
+        );
+        // reverting the caller also undoes the writes of the call it made
+        assert_eq!(run(Operation::Revert), (B256::ZERO, B256::ZERO));
+    }
+
+    #[test]
+    fn test_supports_matches_execution() {
+        for opcode in 0..=u8::MAX {
+            let data = matches!(opcode, 0x60..=0x7f).then_some(U256::ZERO);
+            let Ok(operation) = Operation::from_byte(opcode, data) else {
+                continue;
+            };
+            let mut code =
+                vec![Operation::Push0.opcode(); operation.stack_req().min_stack_height as usize];
+            code.extend(operation.to_bytes());
+
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], 1_000_000),
+                Arc::new(Mutex::new(State::new())),
+            );
+            let result = vm.execute_operations(code);
+            assert_eq!(
+                VM::supports(&operation),
+                !matches!(result, Err(VMError::NotImplemented(_))),
+                "{}",
+                operation.mnemonic()
+            );
+        }
+        assert!(VM::supports(&Operation::Dup(1)));
+        assert!(!VM::supports(&Operation::Swap1));
+    }
+
+    #[test]
+    fn test_sstore_prices_against_original_value() {
+        let address = Address::repeat_byte(0xaa);
+        let run = |original: u64, values: &[u64]| {
+            let mut program = Program::new();
+            for value in values {
+                program = program
+                    .push(U256::from(*value)) // Value
+                    .op(Operation::Push0) // Key
+                    .op(Operation::SStore);
+            }
+            let code = program.build();
+
+            let mut state = State::new();
+            state.set_storage(address, B256::ZERO, U256::from(original).into());
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, address, 0, vec![], 100_000),
+                Arc::new(Mutex::new(state)),
+            );
+            let gas_used = match vm.execute_operations(code).unwrap() {
+                ExecutionResult::Success { gas_used, .. } => gas_used,
+                result => panic!("unexpected result {:?}", result),
+            };
+            // leave out the pushes of the value and the key
+            (gas_used - (3 + 2) * values.len() as u64, vm.gas_refund())
+        };
+
+        // 0 -> 1 -> 0: a fresh write, then restoring the original zero, which is never a clear
+        assert_eq!(
+            run(0, &[1, 0]),
+            (SSTORE_SET_GAS + 800, SSTORE_SET_GAS - 800)
+        );
+        // the first write to a slot holding a value is cheaper than a fresh one
+        assert_eq!(run(1, &[2]), (SSTORE_RESET_GAS, 0));
+        // further writes hit a dirty slot
+        assert_eq!(run(1, &[2, 3]), (SSTORE_RESET_GAS + 800, 0));
+        // clearing a slot that held a value is refunded, setting it again takes that back
+        assert_eq!(run(1, &[0]), (SSTORE_RESET_GAS, SSTORE_CLEARS_SCHEDULE));
+        assert_eq!(run(1, &[0, 2]), (SSTORE_RESET_GAS + 800, 0));
+        // writing the current value is a no-op
+        assert_eq!(run(1, &[1]), (800, 0));
+    }
+
+    #[test]
+    fn test_value_call_charges_transfer_and_grants_stipend() {
+        let caller = Address::repeat_byte(0xaa);
+        let existing = Address::repeat_byte(0xbb);
+        let new = Address::repeat_byte(0xcc);
+        let with_code = Address::repeat_byte(0xdd);
+
+        let run = |address: Address| {
+            let code = Program::new()
+                .op(Operation::Push0) // retSize
+                .op(Operation::Push0) // retOffset
+                .op(Operation::Push0) // argsSize
+                .op(Operation::Push0) // argsOffset
+                .push(U256::from(5)) // value
+                .push(U256::from_be_slice(address.as_slice())) // address
+                .op(Operation::Push0) // gas
+                .op(Operation::Call)
+                .build();
+
+            let mut state = State::new();
+            state.set_account(caller, Account::new(1000, EMPTY_CODE_HASH, B256::ZERO));
+            state.set_account(existing, Account::new(1, EMPTY_CODE_HASH, B256::ZERO));
+            // runs on the stipend alone
+            let callee_code = Program::new()
+                .push(U256::from(1))
+                .push(U256::from(2))
+                .op(Operation::Add)
+                .build();
+            state.contract.insert(with_code, Contract::new(callee_code));
+            let balance = state
+                .accounts
+                .get(&address)
+                .map_or(0, |account| account.balance);
+            let state = Arc::new(Mutex::new(state));
+
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+                state.clone(),
+            );
+            vm.execute_operations(code).unwrap();
+            assert_eq!(vm.stack, vec![U256::from(1)]);
+            assert_eq!(
+                state.lock().unwrap().accounts[&address].balance,
+                balance + 5
+            );
+            vm.gas_used()
+        };
+
+        // 5 PUSH0 + 2 PUSH + CALL, the unused stipend goes back to the caller
+        let call_gas =
+            5 * 2 + 2 * 3 + COLD_ACCOUNT_ACCESS_COST + CALL_VALUE_TRANSFER_COST - CALL_STIPEND;
+        assert_eq!(run(existing), call_gas);
+        assert_eq!(run(new), call_gas + CALL_NEW_ACCOUNT_COST);
+        assert_eq!(run(with_code), call_gas + 3 * 3);
+    }
+
+    #[test]
+    fn test_step_limit_stops_infinite_loop() {
+        let code = Program::new()
+            .op(Operation::JumpDest)
+            .op(Operation::Push0)
+            .op(Operation::Jump)
+            .build();
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, Address::ZERO, 0, vec![], u64::MAX),
+            Arc::new(Mutex::new(State::new())),
+        )
+        .with_step_limit(1000);
+
+        assert!(matches!(
+            vm.execute_operations(code),
+            Err(VMError::StepLimitExceeded(1000))
+        ));
+        assert_eq!(vm.steps, 1000);
+        // far from running out of gas
+        assert!(vm.gas_used() < 10_000);
+    }
+
+    #[test]
+    fn test_call_truncates_output_to_return_size() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+
+        // the callee returns two words
+        let callee_code = Program::new()
+            .push(U256::from_be_bytes([0x11; 32])) // Value
+            .op(Operation::Push0) // Offset
+            .op(Operation::MStore)
+            .push(U256::from_be_bytes([0x22; 32])) // Value
+            .push(U256::from(32)) // Offset
+            .op(Operation::MStore)
+            .push(U256::from(64)) // Size
+            .op(Operation::Push0) // Offset
+            .op(Operation::Return)
+            .build();
+        let run = |copy: fn(Program) -> Program| {
+            let call = Program::new()
+                .push(U256::from(32)) // retSize
+                .op(Operation::Push0) // retOffset
+                .op(Operation::Push0) // argsSize
+                .op(Operation::Push0) // argsOffset
+                .op(Operation::Push0) // value
+                .push(U256::from_be_slice(callee.as_slice())) // address
+                .push(U256::from(10_000)) // gas
+                .op(Operation::Call)
+                .op(Operation::ReturnDataSize);
+            let code = copy(call).build();
+
+            let mut state = State::new();
+            state.set_account(caller, Account::new(0, EMPTY_CODE_HASH, B256::ZERO));
+            state
+                .contract
+                .insert(callee, Contract::new(callee_code.clone()));
+
+            let mut vm = VM::new(
+                Contract::new(code.clone()),
+                ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+                Arc::new(Mutex::new(state)),
+            );
+            let result = vm.execute_operations(code);
+            (vm, result)
+        };
+
+        let (vm, result) = run(|program| program);
+        result.unwrap();
+        assert_eq!(vm.stack, vec![U256::from(1), U256::from(64)]);
+        assert_eq!(vm.memory, [0x11; 32]);
+
+        // the second word is still available to RETURNDATACOPY
+        let (vm, result) = run(|program| {
+            program
+                .push(U256::from(32)) // size
+                .push(U256::from(32)) // offset
+                .push(U256::from(32)) // destOffset
+                .op(Operation::ReturnDataCopy)
+        });
+        result.unwrap();
+        assert_eq!(vm.memory[..32], [0x11; 32]);
+        assert_eq!(vm.memory[32..], [0x22; 32]);
+
+        // but nothing past it
+        let (_, result) = run(|program| {
+            program
+                .push(U256::from(32)) // size
+                .push(U256::from(33)) // offset
+                .op(Operation::Push0) // destOffset
+                .op(Operation::ReturnDataCopy)
+        });
+        assert!(matches!(result, Err(VMError::ReturnDataOutOfBounds)));
+    }
+
+    #[test]
+    fn test_generate_contract_address() {
+        let sender = address!("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        let expected = [
+            address!("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"),
+            address!("343c43a37d37dff08ae8c4a11544c718abb4fcf8"),
+            address!("f778b86fa74e846c4f0a1fbd1335fe81c00a0c91"),
+            address!("fffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c"),
+        ];
+        for (nonce, expected) in expected.into_iter().enumerate() {
+            assert_eq!(
+                VM::generate_contract_address(sender, nonce as u64),
+                expected
+            );
+        }
+
+        // nonces from 128 on are encoded as a length prefixed big-endian integer
+        let mut rlp = vec![0xd8, 0x94];
+        rlp.extend_from_slice(sender.as_slice());
+        rlp.extend_from_slice(&[0x82, 0x04, 0x00]);
+        assert_eq!(
+            VM::generate_contract_address(sender, 0x400),
+            Address::from_slice(&keccak256(&rlp)[12..])
+        );
+    }
 }
@@ -1,92 +1,960 @@
-use crate::block::account::Account;
+use crate::block::block::{Block, Withdrawal};
+use crate::block::errors::BlockError;
 use crate::block::state::State;
-use crate::evm::evm::{ExecutionContext, VMError, VM};
+use crate::evm::evm::{BlockEnv, Contract, ExecutionContext, ExecutionResult, VMError, VM};
 use crate::transaction::errors::TransactionError;
-use crate::transaction::transaction::{Transaction, TRANSACTION_GAS_COST};
-use alloy_primitives::B256;
+use crate::transaction::receipt::Receipt;
+use crate::transaction::transaction::{Transaction, GWEI_TO_WEI, TRANSACTION_GAS_COST};
+use alloy_primitives::{Address, B256};
+use std::error::Error;
 use std::sync::{Arc, Mutex};
 
+/// Caps the gas refund of a transaction to a fifth of the gas it used (EIP-3529).
+const MAX_REFUND_QUOTIENT: u64 = 5;
+/// Bounds the number of operations a simulated call executes, so a call looping forever
+/// can't hang the node.
+pub const SIMULATION_STEP_LIMIT: u64 = 10_000_000;
+
 pub struct Executor;
 
+/// Outcome of executing all the transactions and withdrawals of a block.
+#[derive(Debug)]
+pub struct BlockExecutionResult {
+    pub gas_used: u64,
+    pub receipts: Vec<Receipt>,
+    pub state_root: B256,
+}
+
 impl Executor {
+    /// Executes a contract call and returns the outcome of the execution.
+    ///
+    /// Storage changes are only committed back to the state if the call succeeds;
+    /// a reverted call leaves the contract untouched.
     pub fn process_transaction_contract(
         transaction: Transaction,
+        block: &BlockEnv,
         state: Arc<Mutex<State>>,
-    ) -> Result<(), VMError> {
-        // TODO: unnecessarily wasteful clone on large list
-        let contract = state
-            .lock()
-            .unwrap()
-            .contract
-            .get(&transaction.to)
-            .ok_or(VMError::ContractNotFound)?
-            .clone();
+    ) -> Result<ExecutionResult, VMError> {
+        let sender = transaction
+            .get_sender_address()
+            .ok_or(VMError::InvalidTransaction)?;
+        Self::execute_contract(transaction, sender, block, state, None).map(|(result, _)| result)
+    }
+
+    /// Executes a contract call or creation, returning its outcome and the gas refund it accrued.
+    fn execute_contract(
+        transaction: Transaction,
+        sender: Address,
+        block: &BlockEnv,
+        state: Arc<Mutex<State>>,
+        step_limit: Option<u64>,
+    ) -> Result<(ExecutionResult, u64), VMError> {
+        let contract = if transaction.is_creation() {
+            // the code is set from the output of the init code
+            Contract::new(vec![])
+        } else {
+            // TODO: unnecessarily wasteful clone on large list
+            state
+                .lock()
+                .unwrap()
+                .contract
+                .get(&transaction.to)
+                .ok_or(VMError::ContractNotFound(transaction.to))?
+                .clone()
+        };
 
         let mut evm = VM::new(
-            contract.clone(),
+            contract,
             ExecutionContext::new(
-                transaction.get_sender_address().unwrap(),
+                sender,
                 transaction.to,
                 transaction.value,
                 transaction.input_data.clone(),
                 transaction.gas_limit,
             ),
-            state,
-        );
-        evm.execute_transaction(transaction)?;
-        Ok(())
+            state.clone(),
+        )
+        .with_block_env(block.clone());
+        if let Some(step_limit) = step_limit {
+            evm = evm.with_step_limit(step_limit);
+        }
+        let result = evm.execute_unsigned_transaction(transaction, sender)?;
+
+        if let ExecutionResult::Success { .. } = result {
+            state
+                .lock()
+                .unwrap()
+                .contract
+                .insert(evm.address(), evm.contract().clone());
+        }
+        Ok((result, evm.gas_refund()))
+    }
+
+    /// Runs a contract call against a copy of the state, discarding every change it makes.
+    ///
+    /// No fees are charged and nothing is validated besides the call itself, so this can be
+    /// used to inspect the outcome of a transaction before submitting it. The call is stopped
+    /// after `SIMULATION_STEP_LIMIT` operations.
+    pub fn simulate(
+        transaction: Transaction,
+        block: &BlockEnv,
+        state: &Arc<Mutex<State>>,
+    ) -> Result<ExecutionResult, VMError> {
+        let sender = transaction
+            .get_sender_address()
+            .ok_or(VMError::InvalidTransaction)?;
+        let snapshot = Arc::new(Mutex::new(state.lock().unwrap().clone()));
+        Self::execute_contract(
+            transaction,
+            sender,
+            block,
+            snapshot,
+            Some(SIMULATION_STEP_LIMIT),
+        )
+        .map(|(result, _)| result)
     }
 
+    /// Validates and applies a transaction to the state of the given block.
+    ///
+    /// The sender is charged for the full gas limit up front and refunded for the gas the
+    /// transaction did not use. Calls into a contract execute its code; if the execution fails,
+    /// its state changes and the value transfer are rolled back, but the gas is still paid for.
     pub fn process_transaction(
         transaction: &Transaction,
-        base_fee: u64,
+        block: &BlockEnv,
         state: Arc<Mutex<State>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<Receipt, Box<dyn std::error::Error>> {
+        // recovering the sender also validates the signature
+        let sender_address = transaction.recover_sender()?;
+        Self::execute_unsigned(transaction, sender_address, block, state)
+    }
+
+    /// Applies a transaction sent by `sender_address` without checking its signature.
+    ///
+    /// Meant for transactions already known to be valid, such as those of a replayed block or
+    /// a simulated call, where recovering the sender again is wasted work. Everything else is
+    /// validated as in `process_transaction`.
+    pub fn execute_unsigned(
+        transaction: &Transaction,
+        sender_address: Address,
+        block: &BlockEnv,
+        state: Arc<Mutex<State>>,
+    ) -> Result<Receipt, Box<dyn std::error::Error>> {
+        let base_fee = block.base_fee;
+
+        let (effective_gas_price, transfer_revert) = {
+            let mut state = state.lock().unwrap();
+
+            // Get sender account
+            let sender = state
+                .accounts
+                .get_mut(&sender_address)
+                .ok_or(Box::new(TransactionError::SenderAccountDoesNotExist))?;
+            transaction.validate(sender, base_fee)?;
+
+            let effective_gas_price = transaction.effective_gas_price(base_fee);
+            let upfront_fee = transaction.gas_limit * effective_gas_price;
+            sender.balance -= upfront_fee;
+            sender.nonce += 1;
+
+            // balances before the value transfer, restored if the execution fails. The VM
+            // undoes its own changes, while the fee and the nonce stay charged.
+            let mut transfer_revert = vec![];
+
+            // EIP-161: a zero-value transfer must not create an empty account.
+            // A contract creation transfers the value to the new contract itself.
+            if transaction.value > 0 && !transaction.is_creation() {
+                let sender = state.accounts.get_mut(&sender_address).unwrap();
+                transfer_revert.push((sender_address, Some(sender.balance)));
+                sender.balance -= transaction.value;
+                let recipient_balance = state
+                    .accounts
+                    .get(&transaction.to)
+                    .map(|account| account.balance);
+                transfer_revert.push((transaction.to, recipient_balance));
+                state.accounts.entry(transaction.to).or_default().balance += transaction.value;
+            }
+
+            (effective_gas_price, transfer_revert)
+        };
+
+        let is_contract = transaction.is_creation()
+            || state.lock().unwrap().contract.contains_key(&transaction.to);
+        let (success, gas_used) = if is_contract {
+            match Self::execute_contract(
+                transaction.clone(),
+                sender_address,
+                block,
+                state.clone(),
+                None,
+            ) {
+                Ok((ExecutionResult::Success { gas_used, .. }, gas_refund)) => {
+                    let gas_used = TRANSACTION_GAS_COST + gas_used;
+                    (
+                        true,
+                        gas_used - gas_refund.min(gas_used / MAX_REFUND_QUOTIENT),
+                    )
+                }
+                // a reverted execution keeps none of its refunds
+                Ok((ExecutionResult::Revert { gas_used, .. }, _)) => {
+                    (false, TRANSACTION_GAS_COST + gas_used)
+                }
+                // an exceptional halt consumes all the gas
+                Err(_) => (false, transaction.gas_limit),
+            }
+        } else {
+            (true, TRANSACTION_GAS_COST)
+        };
+
+        let mut state = state.lock().unwrap();
+        if !success {
+            // in reverse, so a transfer to the sender itself ends on its balance from before
+            for (address, balance) in transfer_revert.into_iter().rev() {
+                match balance {
+                    Some(balance) => state.accounts.entry(address).or_default().balance = balance,
+                    None => {
+                        state.accounts.remove(&address);
+                    }
+                }
+            }
+        }
+
+        // Refund the unused gas
+        let refund = (transaction.gas_limit - gas_used) * effective_gas_price;
+        state.accounts.get_mut(&sender_address).unwrap().balance += refund;
+
+        // EIP-161: accounts touched by the transaction are removed if left empty
+        state.remove_if_empty(&sender_address);
+        state.remove_if_empty(&transaction.to);
+
+        Ok(Receipt {
+            success,
+            gas_used,
+            effective_gas_price,
+            priority_fee_per_gas: effective_gas_price - base_fee,
+        })
+    }
+
+    /// Replays a block: applies its transactions in order, pays the tips and the block reward
+    /// to its fee recipient and credits its withdrawals.
+    ///
+    /// `env` must be the environment the block was produced in, including the hashes of its
+    /// ancestors read by BLOCKHASH, see `App::block_env`.
+    ///
+    /// Fails if a transaction is invalid or the block exceeds its gas limit, in which case the
+    /// state is left as it was before the block.
+    pub fn execute_block(
+        block: &Block,
+        env: &BlockEnv,
+        block_reward: u64,
+        state: Arc<Mutex<State>>,
+    ) -> Result<BlockExecutionResult, Box<dyn Error>> {
+        let snapshot = state.lock().unwrap().clone();
+        let restore = |err: Box<dyn Error>| {
+            *state.lock().unwrap() = snapshot.clone();
+            err
+        };
+
+        let mut receipts = Vec::with_capacity(block.transactions().len());
+        let mut gas_used = 0;
+        let mut priority_fees = 0;
+        for transaction in block.transactions() {
+            let receipt =
+                Self::process_transaction(transaction, env, state.clone()).map_err(restore)?;
+            gas_used += receipt.gas_used;
+            priority_fees += receipt.priority_fee();
+            receipts.push(receipt);
+        }
+        if gas_used > block.gas_limit() {
+            return Err(restore(BlockError::GasLimitExceeded.into()));
+        }
+
+        Self::credit_fee_recipient(
+            block.fee_recipient(),
+            priority_fees + block_reward,
+            state.clone(),
+        );
+        Self::process_withdrawals(block.withdrawals(), state.clone());
+
+        let state_root = state.lock().unwrap().root();
+        Ok(BlockExecutionResult {
+            gas_used,
+            receipts,
+            state_root,
+        })
+    }
+
+    /// Credits the tips and the block reward of a block to its fee recipient. The base fee
+    /// portion of the fees was already charged to the senders and is not credited to anyone,
+    /// which burns it.
+    pub fn credit_fee_recipient(fee_recipient: Address, amount: u64, state: Arc<Mutex<State>>) {
+        if amount > 0 {
+            let mut state = state.lock().unwrap();
+            let coinbase = state.accounts.entry(fee_recipient).or_default();
+            coinbase.balance += amount;
+        }
+    }
+
+    /// Credits the withdrawn amounts (denominated in gwei) to their recipients.
+    pub fn process_withdrawals(withdrawals: &[Withdrawal], state: Arc<Mutex<State>>) {
+        let mut state = state.lock().unwrap();
+        for withdrawal in withdrawals {
+            let recipient = state.accounts.entry(withdrawal.address).or_default();
+            recipient.balance += withdrawal.amount * GWEI_TO_WEI;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::{Account, EMPTY_CODE_HASH};
+    use crate::crypto::wallet::Wallet;
+    use crate::evm::evm::{SSTORE_CLEARS_SCHEDULE, SSTORE_RESET_GAS, SSTORE_SET_GAS};
+    use crate::evm::operation::Operation;
+    use crate::evm::program::Program;
+    use crate::transaction::transaction::{ETH_TO_WEI, RECOVERIES};
+    use alloy_primitives::{B256, U256};
+
+    #[test]
+    fn test_contract_revert_keeps_state() {
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+
+        let code = vec![
+            Operation::Push1(U256::from(42)).opcode(), // Value to store
+            42,
+            Operation::Push1(U256::from(0)).opcode(), // Key
+            0,
+            Operation::SStore.opcode(),
+            Operation::Push2(U256::from(0xbeef)).opcode(), // Revert reason
+            0xbe,
+            0xef,
+            Operation::Push1(U256::from(0)).opcode(), // Memory offset
+            0,
+            Operation::MStore.opcode(),
+            Operation::Push1(U256::from(2)).opcode(), // Revert data length
+            2,
+            Operation::Push1(U256::from(30)).opcode(), // Revert data offset
+            30,
+            Operation::Revert.opcode(),
+        ];
+        let state = Arc::new(Mutex::new(State::new()));
+        {
+            let mut state = state.lock().unwrap();
+            state.contract.insert(contract_address, Contract::new(code));
+            state.set_storage(contract_address, B256::ZERO, U256::from(7).into());
+        }
+
+        let tx = Transaction::new(
+            contract_address,
+            0,
+            100_000,
+            100,
+            100,
+            vec![],
+            Some(&sender.private_key),
+        );
+
+        let result =
+            Executor::process_transaction_contract(tx, &BlockEnv::default(), state.clone())
+                .unwrap();
+
+        match result {
+            ExecutionResult::Revert { reason, .. } => assert_eq!(reason, vec![0xbe, 0xef]),
+            _ => panic!("Expected a revert result."),
+        }
+        assert_eq!(
+            state
+                .lock()
+                .unwrap()
+                .get_storage(&contract_address, &B256::ZERO),
+            B256::from(U256::from(7))
+        );
+    }
+
+    #[test]
+    fn test_priority_fee_above_max_fee_rejected() {
+        let sender = Wallet::generate();
+        let state = Arc::new(Mutex::new(State::new()));
+        state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = Transaction::new(
+            Wallet::generate().address,
+            100,
+            TRANSACTION_GAS_COST,
+            20, // max tip above the max total fee
+            10,
+            vec![],
+            Some(&sender.private_key),
+        );
+
+        let err =
+            Executor::process_transaction(&tx, &BlockEnv::new(0, 1), state.clone()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TransactionError>(),
+            Some(TransactionError::PriorityFeeExceedsMaxFee)
+        ));
+        let mut state = state.lock().unwrap();
+        assert_eq!(state.get_account(&sender.address).unwrap().nonce, 0);
+        assert_eq!(
+            state.get_account(&sender.address).unwrap().balance,
+            ETH_TO_WEI
+        );
+    }
+
+    #[test]
+    fn test_unused_gas_refunded() {
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+        let code = vec![
+            Operation::Push1(U256::from(1)).opcode(), // Value
+            1,
+            Operation::Push1(U256::from(0)).opcode(), // Key
+            0,
+            Operation::SStore.opcode(),
+        ];
+        let execution_gas = 3 + 3 + SSTORE_SET_GAS;
+
+        let state = Arc::new(Mutex::new(State::new()));
+        {
+            let mut state = state.lock().unwrap();
+            state.set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            state.contract.insert(contract_address, Contract::new(code));
+        }
+
+        let gas_limit = 100_000;
+        let tx = Transaction::new(
+            contract_address,
+            0,
+            gas_limit,
+            2,
+            20,
+            vec![],
+            Some(&sender.private_key),
+        );
+
+        let base_fee = 10;
+        let receipt =
+            Executor::process_transaction(&tx, &BlockEnv::new(0, base_fee), state.clone()).unwrap();
+
+        assert!(receipt.success);
+        assert_eq!(receipt.gas_used, TRANSACTION_GAS_COST + execution_gas);
         let mut state = state.lock().unwrap();
+        assert_eq!(
+            state.get_account(&sender.address).unwrap().balance,
+            ETH_TO_WEI - (TRANSACTION_GAS_COST + execution_gas) * (base_fee + 2)
+        );
+        assert_eq!(
+            state.get_storage(&contract_address, &B256::ZERO),
+            B256::from(U256::from(1))
+        );
+    }
 
-        // Get sender account
-        let sender = state
-            .accounts
-            .get_mut(
-                &transaction
-                    .get_sender_address()
-                    .ok_or(Box::new(TransactionError::InvalidTransaction))?,
-            )
-            .ok_or(Box::new(TransactionError::SenderAccountDoesNotExist))?;
-
-        if base_fee > transaction.max_fee_per_gas {
-            return Err(Box::new(TransactionError::MaximumGasFeeBelowBaseFee));
+    #[test]
+    fn test_reverted_transaction_refunds_unused_gas() {
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+        let code = Program::new()
+            .push(U256::from(1))
+            .push(U256::ZERO)
+            .op(Operation::SStore)
+            .op(Operation::Push0)
+            .op(Operation::Push0)
+            .op(Operation::Revert)
+            .build();
+        // REVERT itself is free, only the operations before it are paid for
+        let execution_gas = 3 + 3 + SSTORE_SET_GAS + 2 + 2;
+
+        let state = Arc::new(Mutex::new(State::new()));
+        {
+            let mut state = state.lock().unwrap();
+            state.set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            state.contract.insert(contract_address, Contract::new(code));
         }
 
-        let total_fee = TRANSACTION_GAS_COST
-            * transaction
-                .max_fee_per_gas
-                .min(base_fee + transaction.max_priority_fee_per_gas);
+        let tx = Transaction::new(
+            contract_address,
+            1000,
+            100_000,
+            2,
+            20,
+            vec![],
+            Some(&sender.private_key),
+        );
+
+        let base_fee = 10;
+        let receipt =
+            Executor::process_transaction(&tx, &BlockEnv::new(0, base_fee), state.clone()).unwrap();
+
+        assert!(!receipt.success);
+        assert_eq!(receipt.gas_used, TRANSACTION_GAS_COST + execution_gas);
+        let mut state = state.lock().unwrap();
+        // the value comes back along with the unspent gas
+        assert_eq!(
+            state.get_account(&sender.address).unwrap().balance,
+            ETH_TO_WEI - (TRANSACTION_GAS_COST + execution_gas) * (base_fee + 2)
+        );
+        assert_eq!(state.get_account(&sender.address).unwrap().nonce, 1);
+        assert_eq!(
+            state.get_storage(&contract_address, &B256::ZERO),
+            B256::ZERO
+        );
+    }
+
+    #[test]
+    fn test_zero_value_transfer_creates_no_account() {
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate().address;
+
+        let state = Arc::new(Mutex::new(State::new()));
+        state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = Transaction::new(
+            receiver,
+            0,
+            TRANSACTION_GAS_COST,
+            2,
+            20,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let receipt =
+            Executor::process_transaction(&tx, &BlockEnv::new(0, 10), state.clone()).unwrap();
+
+        assert!(receipt.success);
+        let mut state = state.lock().unwrap();
+        assert!(state.get_account(&receiver).is_none());
+        assert!(state.get_account(&sender.address).is_some());
+    }
+
+    #[test]
+    fn test_simulate_leaves_state_unchanged() {
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+        let code = vec![
+            Operation::Push1(U256::from(1)).opcode(), // Value
+            1,
+            Operation::Push1(U256::from(0)).opcode(), // Key
+            0,
+            Operation::SStore.opcode(),
+        ];
+
+        let state = Arc::new(Mutex::new(State::new()));
+        state
+            .lock()
+            .unwrap()
+            .contract
+            .insert(contract_address, Contract::new(code));
+
+        let tx = Transaction::new(
+            contract_address,
+            0,
+            100_000,
+            2,
+            20,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let result = Executor::simulate(tx, &BlockEnv::default(), &state).unwrap();
+
+        assert!(matches!(result, ExecutionResult::Success { .. }));
+        let state = state.lock().unwrap();
+        assert!(state.storage.is_empty());
+        assert!(state.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_sender_recovered_once_per_validation() {
+        let sender = Wallet::generate();
+        let state = Arc::new(Mutex::new(State::new()));
+        state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = Transaction::new(
+            Wallet::generate().address,
+            100,
+            TRANSACTION_GAS_COST,
+            2,
+            20,
+            vec![],
+            Some(&sender.private_key),
+        );
+
+        RECOVERIES.with(|count| count.set(0));
+        Executor::process_transaction(&tx, &BlockEnv::new(0, 10), state).unwrap();
+        assert_eq!(RECOVERIES.with(|count| count.get()), 1);
+    }
+
+    #[test]
+    fn test_execute_unsigned_transaction() {
+        let sender = Address::repeat_byte(0xaa);
+        let contract_address = Address::repeat_byte(0xcc);
+        let code = Program::new()
+            .push(U256::from(7)) // Value
+            .op(Operation::Push0) // Key
+            .op(Operation::SStore)
+            .build();
+
+        let state = Arc::new(Mutex::new(State::new()));
+        {
+            let mut state = state.lock().unwrap();
+            state.set_account(
+                sender,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            state.contract.insert(contract_address, Contract::new(code));
+        }
+
+        // never signed, so the sender can't be recovered from it
+        let tx = Transaction::new(contract_address, 100, 100_000, 2, 20, vec![], None);
+        assert!(Executor::process_transaction(&tx, &BlockEnv::new(0, 10), state.clone()).is_err());
+
+        RECOVERIES.with(|count| count.set(0));
+        let receipt =
+            Executor::execute_unsigned(&tx, sender, &BlockEnv::new(0, 10), state.clone()).unwrap();
+        assert_eq!(RECOVERIES.with(|count| count.get()), 0);
 
-        if transaction.gas_limit < TRANSACTION_GAS_COST {
-            return Err(Box::new(TransactionError::InsufficientGas));
+        assert!(receipt.success);
+        let mut state = state.lock().unwrap();
+        assert_eq!(
+            state.get_storage(&contract_address, &B256::ZERO),
+            B256::from(U256::from(7))
+        );
+        let account = state.get_account(&sender).unwrap();
+        assert_eq!(account.nonce, 1);
+        assert_eq!(
+            account.balance,
+            ETH_TO_WEI - 100 - receipt.gas_used * receipt.effective_gas_price
+        );
+    }
+
+    #[test]
+    fn test_gas_refund_capped() {
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+
+        // clear three occupied storage slots
+        let mut code = vec![];
+        let mut storage = vec![];
+        for key in 0..3u8 {
+            code.extend_from_slice(&[
+                Operation::Push0.opcode(),             // Value
+                Operation::Push1(U256::ZERO).opcode(), // Key
+                key,
+                Operation::SStore.opcode(),
+            ]);
+            storage.push(U256::from(key));
         }
 
-        if !transaction.verify_signature() {
-            return Err(Box::new(TransactionError::InvalidSignature));
+        let state = Arc::new(Mutex::new(State::new()));
+        {
+            let mut state = state.lock().unwrap();
+            state.set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            state.contract.insert(contract_address, Contract::new(code));
+            for key in storage {
+                state.set_storage(contract_address, key.into(), U256::from(1).into());
+            }
         }
 
-        if sender.balance < transaction.value + total_fee {
-            return Err(Box::new(TransactionError::InsufficientBalance));
+        let tx = Transaction::new(
+            contract_address,
+            0,
+            100_000,
+            0,
+            10,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let receipt = Executor::process_transaction(&tx, &BlockEnv::new(0, 10), state).unwrap();
+
+        // the accrued 3 * 4800 exceeds a fifth of the gas used
+        let gas_used = TRANSACTION_GAS_COST + 3 * (2 + 3 + SSTORE_RESET_GAS);
+        assert!(3 * SSTORE_CLEARS_SCHEDULE > gas_used / 5);
+        assert_eq!(receipt.gas_used, gas_used - gas_used / 5);
+    }
+
+    #[test]
+    fn test_create_requires_affordable_value() {
+        // init code returning the single byte 0x2a as the runtime code
+        let init_code = vec![
+            Operation::Push1(U256::ZERO).opcode(), // Value
+            0x2a,
+            Operation::Push0.opcode(), // Offset
+            Operation::MStore.opcode(),
+            Operation::Push1(U256::ZERO).opcode(), // Size
+            1,
+            Operation::Push1(U256::ZERO).opcode(), // Offset
+            31,
+            Operation::Return.opcode(),
+        ];
+        let gas_limit = 100_000;
+        let base_fee = 10;
+
+        let deploy = |balance: u64| {
+            let sender = Wallet::generate();
+            let state = Arc::new(Mutex::new(State::new()));
+            state.lock().unwrap().set_account(
+                sender.address,
+                Account::new(balance, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            let tx = Transaction::new(
+                Address::ZERO,
+                1000,
+                gas_limit,
+                0,
+                base_fee,
+                init_code.clone(),
+                Some(&sender.private_key),
+            );
+            let result =
+                Executor::process_transaction(&tx, &BlockEnv::new(0, base_fee), state.clone());
+            (result, state)
+        };
+
+        let (result, state) = deploy(gas_limit * base_fee + 999);
+        assert!(result.is_err());
+        let state = state.lock().unwrap();
+        assert!(state.contract.is_empty());
+        assert_eq!(state.accounts.len(), 1);
+        drop(state);
+
+        let (result, state) = deploy(ETH_TO_WEI);
+        assert!(result.unwrap().success);
+        let state = state.lock().unwrap();
+        let (address, contract) = state.contract.iter().next().unwrap();
+        assert_eq!(contract.code, vec![0x2a]);
+        assert_eq!(state.accounts[address].balance, 1000);
+    }
+
+    #[test]
+    fn test_failed_creation_returns_endowment() {
+        let base_fee = 10;
+        let deploy = |init_code: Vec<u8>| {
+            let sender = Wallet::generate();
+            let state = Arc::new(Mutex::new(State::new()));
+            state.lock().unwrap().set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            let tx = Transaction::new(
+                Address::ZERO,
+                1000,
+                100_000,
+                0,
+                base_fee,
+                init_code,
+                Some(&sender.private_key),
+            );
+            let receipt =
+                Executor::process_transaction(&tx, &BlockEnv::new(0, base_fee), state.clone())
+                    .unwrap();
+            assert!(!receipt.success);
+
+            // only the fee is kept, the new account is gone along with the endowment
+            let state = state.lock().unwrap();
+            assert!(state.contract.is_empty());
+            assert_eq!(state.accounts.len(), 1);
+            assert_eq!(
+                state.accounts[&sender.address].balance,
+                ETH_TO_WEI - receipt.gas_used * base_fee
+            );
+        };
+
+        // init code reverting
+        deploy(
+            Program::new()
+                .op(Operation::Push0)
+                .op(Operation::Push0)
+                .op(Operation::Revert)
+                .build(),
+        );
+        // init code stopping without returning any code
+        deploy(Program::new().op(Operation::Stop).build());
+    }
+
+    #[test]
+    fn test_create_fails_on_address_collision() {
+        // init code returning the single byte 0x2a as the runtime code
+        let init_code = vec![
+            Operation::Push1(U256::ZERO).opcode(), // Value
+            0x2a,
+            Operation::Push0.opcode(), // Offset
+            Operation::MStore.opcode(),
+            Operation::Push1(U256::ZERO).opcode(), // Size
+            1,
+            Operation::Push1(U256::ZERO).opcode(), // Offset
+            31,
+            Operation::Return.opcode(),
+        ];
+        let gas_limit = 100_000;
+        let base_fee = 10;
+
+        let sender = Wallet::generate();
+        let tx = Transaction::new(
+            Address::ZERO,
+            0,
+            gas_limit,
+            0,
+            base_fee,
+            init_code,
+            Some(&sender.private_key),
+        );
+        let deploy = |state: &Arc<Mutex<State>>| {
+            state.lock().unwrap().set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            Executor::process_transaction(&tx, &BlockEnv::new(0, base_fee), state.clone()).unwrap()
+        };
+
+        // find out where the transaction deploys to
+        let state = Arc::new(Mutex::new(State::new()));
+        assert!(deploy(&state).success);
+        let address = *state.lock().unwrap().contract.keys().next().unwrap();
+
+        let state = Arc::new(Mutex::new(State::new()));
+        let existing = Contract::new(vec![0x00]);
+        state
+            .lock()
+            .unwrap()
+            .set_account(address, Account::new(0, existing.code_hash(), B256::ZERO));
+        state.lock().unwrap().contract.insert(address, existing);
+
+        let receipt = deploy(&state);
+        assert!(!receipt.success);
+        assert_eq!(receipt.gas_used, gas_limit);
+        let state = state.lock().unwrap();
+        assert_eq!(state.contract[&address].code, vec![0x00]);
+        assert_eq!(state.accounts[&sender.address].nonce, 1);
+    }
+
+    #[test]
+    fn test_deployment_transaction() {
+        let sender = Wallet::generate();
+        let state = Arc::new(Mutex::new(State::new()));
+        state.lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        // returns the single byte 0x2a as the code of the contract
+        let init_code = Program::new()
+            .push(U256::from(0x2a))
+            .op(Operation::Push0)
+            .op(Operation::MStore8)
+            .push(U256::from(1))
+            .op(Operation::Push0)
+            .op(Operation::Return)
+            .build();
+        let tx = Transaction::deployment(init_code, 100, 100_000, 0, 10, &sender.private_key);
+        assert!(tx.is_creation());
+        assert_eq!(tx.recover_sender().unwrap(), sender.address);
+
+        let receipt =
+            Executor::process_transaction(&tx, &BlockEnv::new(0, 10), state.clone()).unwrap();
+        assert!(receipt.success);
+
+        let state = state.lock().unwrap();
+        let (address, contract) = state.contract.iter().next().unwrap();
+        assert_eq!(contract.code, vec![0x2a]);
+        assert_eq!(state.accounts[address].balance, 100);
+    }
+
+    #[test]
+    fn test_creation_rejected_at_max_nonce() {
+        let sender = Wallet::generate();
+        let state = Arc::new(Mutex::new(State::new()));
+        let mut account = Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO);
+        account.nonce = u64::MAX;
+        state.lock().unwrap().set_account(sender.address, account);
+
+        let mut tx = Transaction::new(Address::ZERO, 0, 100_000, 0, 10, vec![], None);
+        tx.nonce = u64::MAX;
+        tx.sign(&sender.private_key);
+
+        let err =
+            Executor::process_transaction(&tx, &BlockEnv::new(0, 10), state.clone()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TransactionError>(),
+            Some(TransactionError::NonceMax)
+        ));
+        {
+            let state = state.lock().unwrap();
+            assert_eq!(state.accounts[&sender.address].nonce, u64::MAX);
+            assert_eq!(state.accounts[&sender.address].balance, ETH_TO_WEI);
+            assert!(state.contract.is_empty());
         }
 
-        sender.balance -= transaction.value + total_fee;
-        sender.nonce += 1;
+        // the VM refuses the creation on its own as well
+        assert!(matches!(
+            Executor::process_transaction_contract(tx, &BlockEnv::default(), state),
+            Err(VMError::NonceOverflow(address)) if address == sender.address
+        ));
+    }
+
+    #[test]
+    fn test_execute_block_is_deterministic() {
+        let sender = Wallet::generate();
+        let genesis = || {
+            let mut state = State::new();
+            state.set_account(
+                sender.address,
+                Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+            );
+            Arc::new(Mutex::new(state))
+        };
+        let transfer = |nonce: u64, signer: &Wallet| {
+            let mut tx = Transaction::new(
+                Wallet::generate().address,
+                1000,
+                TRANSACTION_GAS_COST,
+                2,
+                20,
+                vec![],
+                None,
+            );
+            tx.nonce = nonce;
+            tx.sign(&signer.private_key);
+            tx
+        };
 
-        let recipient = state.accounts.entry(transaction.to).or_insert(Account {
-            nonce: 0,
-            balance: 0,
-            code_hash: B256::ZERO,
-            storage_root: B256::ZERO,
-        });
+        let mut block = Block::new(0, 0, B256::ZERO, B256::ZERO);
+        block.set_gas_limit(30_000_000);
+        block.set_base_fee_per_gas(10);
+        block.set_fee_recipient(Wallet::generate().address);
+        block.add_transaction(transfer(0, &sender));
+        block.add_transaction(transfer(1, &sender));
 
-        recipient.balance += transaction.value;
+        let env = BlockEnv::new(block.slot(), block.base_fee_per_gas());
+        let first = Executor::execute_block(&block, &env, 0, genesis()).unwrap();
+        let second = Executor::execute_block(&block, &env, 0, genesis()).unwrap();
+        assert_eq!(first.gas_used, 2 * TRANSACTION_GAS_COST);
+        assert_eq!(first.receipts.len(), 2);
+        assert_eq!(first.state_root, second.state_root);
 
-        Ok(())
+        // a transaction from an unfunded sender invalidates the whole block
+        block.add_transaction(transfer(0, &Wallet::generate()));
+        let state = genesis();
+        let root = state.lock().unwrap().root();
+        assert!(Executor::execute_block(&block, &env, 0, state.clone()).is_err());
+        assert_eq!(state.lock().unwrap().root(), root);
     }
 }
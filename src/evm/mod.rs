@@ -1,4 +1,12 @@
 pub mod bytecode_parser;
+#[allow(clippy::module_inception)]
 pub mod evm;
 pub mod operation;
+pub mod precompiles;
+pub mod profile;
+pub mod program;
 pub mod executor;
+pub mod runner;
+pub mod signed;
+pub mod spec;
+pub mod storage_reader;
@@ -1,7 +1,7 @@
 use alloy_primitives::U256;
-use strum_macros::FromRepr;
+use strum_macros::{FromRepr, IntoStaticStr};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OperationError {
     InvalidOpcodeFormat,
     InvalidPush,
@@ -15,6 +15,38 @@ pub struct GasCost {
     pub dynamic_multiplier: u64, // For operations with dynamic costs
 }
 
+/// Base gas cost of every opcode, by default the costs of `Operation::gas_cost`.
+///
+/// Lets alternative gas schedules be tried without touching the operations themselves.
+#[derive(Clone)]
+pub struct GasSchedule {
+    base: [u64; 256],
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        let mut base = [0; 256];
+        for (byte, cost) in base.iter_mut().enumerate() {
+            // unknown opcodes fail before they are charged for
+            *cost = Operation::from_byte(byte as u8, Some(U256::ZERO))
+                .map_or(0, |operation| operation.gas_cost().base);
+        }
+        Self { base }
+    }
+}
+
+impl GasSchedule {
+    /// Replaces the base cost of `opcode`.
+    pub fn with_base_cost(mut self, opcode: u8, gas: u64) -> Self {
+        self.base[opcode as usize] = gas;
+        self
+    }
+
+    pub fn base_cost(&self, operation: &Operation) -> u64 {
+        self.base[operation.opcode() as usize]
+    }
+}
+
 // Stack requirements for operation
 pub struct StackReq {
     pub min_stack_height: u32, // Required minimum stack height
@@ -23,7 +55,7 @@ pub struct StackReq {
 }
 
 #[repr(u8)]
-#[derive(FromRepr, Debug, Clone)]
+#[derive(FromRepr, IntoStaticStr, Debug, Clone)]
 pub enum Operation {
     // 0x00 - 0x0f: Stop and Arithmetic
     Stop = 0x0,
@@ -190,7 +222,7 @@ impl Operation {
             // 0xa0 - 0xa4: Logging
             // 0xf0 - 0xff: System
             0x00..0x5f | 0x90..=0x9f | 0xa0..=0xff => {
-                Ok(Operation::from_repr(byte).ok_or(OperationError::InvalidOpcodeFormat)?)
+                Ok(Operation::from_repr(byte).ok_or(OperationError::UnknownOpcode)?)
             }
 
             0x5f => Ok(Operation::Push0),
@@ -239,9 +271,7 @@ impl Operation {
             }
 
             // Dup operations (0x80 - 0x8f)
-            0x80..=0x8f => Ok(Operation::Dup((byte - 0x80 + 1) as u8)),
-
-            _ => Err(OperationError::UnknownOpcode),
+            0x80..=0x8f => Ok(Operation::Dup(byte - 0x80 + 1)),
         }
     }
 
@@ -288,7 +318,7 @@ impl Operation {
     pub fn gas_cost(&self) -> GasCost {
         match self {
             // Zero gas operations
-            Operation::Stop | Operation::Return => GasCost {
+            Operation::Stop | Operation::Return | Operation::Revert => GasCost {
                 base: 0,
                 dynamic_multiplier: 0,
             },
@@ -333,10 +363,12 @@ impl Operation {
                 base: 800,
                 dynamic_multiplier: 0,
             },
+            // a no-op or a write to a slot already written in the transaction, the first write
+            // costs SSTORE_SET_GAS or SSTORE_RESET_GAS instead (EIP-2200)
             Operation::SStore => GasCost {
-                base: 5000,
-                dynamic_multiplier: 15000,
-            }, // Can be 20k for new value
+                base: 800,
+                dynamic_multiplier: 0,
+            },
 
             // Memory operations have dynamic costs based on size
             Operation::MLoad | Operation::MStore => GasCost {
@@ -344,7 +376,57 @@ impl Operation {
                 dynamic_multiplier: 3,
             },
 
-            // Push operations
+            // the warm access cost, cold accesses are charged the difference on top
+            Operation::Balance
+            | Operation::ExtCodeSize
+            | Operation::ExtCodeCopy
+            | Operation::ExtCodeHash
+            | Operation::Call
+            | Operation::CallCode
+            | Operation::DelegateCall
+            | Operation::StaticCall => GasCost {
+                base: 100,
+                dynamic_multiplier: 0,
+            },
+
+            Operation::Keccak256 => GasCost {
+                base: 30,
+                dynamic_multiplier: 6,
+            },
+
+            Operation::BlockHash => GasCost {
+                base: 20,
+                dynamic_multiplier: 0,
+            },
+            Operation::Timestamp
+            | Operation::Difficulty
+            | Operation::PC
+            | Operation::MSize
+            | Operation::Gas
+            | Operation::ReturnDataSize => GasCost {
+                base: 2,
+                dynamic_multiplier: 0,
+            },
+
+            // Flow operations
+            Operation::JumpDest => GasCost {
+                base: 1,
+                dynamic_multiplier: 0,
+            },
+            Operation::Jump => GasCost {
+                base: 8,
+                dynamic_multiplier: 0,
+            },
+            Operation::JumpI => GasCost {
+                base: 10,
+                dynamic_multiplier: 0,
+            },
+
+            // Push operations, PUSH0 has no immediate to read (EIP-3855)
+            Operation::Push0 => GasCost {
+                base: 2,
+                dynamic_multiplier: 0,
+            },
             Operation::Push1(_)
             | Operation::Push2(_)
             | Operation::Push3(_)
@@ -381,15 +463,33 @@ impl Operation {
                 dynamic_multiplier: 0,
             },
 
+            // Duplication and exchange operations
+            Operation::Dup(_)
+            | Operation::Swap1
+            | Operation::Swap2
+            | Operation::Swap3
+            | Operation::Swap4
+            | Operation::Swap5
+            | Operation::Swap6
+            | Operation::Swap7
+            | Operation::Swap8
+            | Operation::Swap9
+            | Operation::Swap10
+            | Operation::Swap11
+            | Operation::Swap12
+            | Operation::Swap13
+            | Operation::Swap14
+            | Operation::Swap15
+            | Operation::Swap16 => GasCost {
+                base: 3,
+                dynamic_multiplier: 0,
+            },
+
             // System operations
             Operation::Create => GasCost {
                 base: 32000,
                 dynamic_multiplier: 200,
             },
-            Operation::Call => GasCost {
-                base: 700,
-                dynamic_multiplier: 9000,
-            },
             Operation::SelfDestruct => GasCost {
                 base: 5000,
                 dynamic_multiplier: 25000,
@@ -449,14 +549,18 @@ impl Operation {
             | Operation::CodeSize
             | Operation::CallValue
             | Operation::Origin
-            | Operation::Address => StackReq {
+            | Operation::Address
+            | Operation::Timestamp
+            | Operation::Difficulty
+            | Operation::MSize
+            | Operation::ReturnDataSize => StackReq {
                 min_stack_height: 0,
                 stack_inputs: 0,
                 stack_outputs: 1,
             },
 
             Operation::CallDataLoad => StackReq {
-                min_stack_height: 0,
+                min_stack_height: 1,
                 stack_inputs: 1,
                 stack_outputs: 1,
             },
@@ -468,7 +572,13 @@ impl Operation {
                 stack_outputs: 0,
             },
 
-            Operation::IsZero | Operation::SLoad => StackReq {
+            Operation::IsZero
+            | Operation::Balance
+            | Operation::MLoad
+            | Operation::SLoad
+            | Operation::ExtCodeSize
+            | Operation::ExtCodeHash
+            | Operation::BlockHash => StackReq {
                 min_stack_height: 1,
                 stack_inputs: 1,
                 stack_outputs: 1,
@@ -485,17 +595,44 @@ impl Operation {
                 stack_outputs: 0,
             },
 
-            Operation::Add | Operation::Sub | Operation::Mul | Operation::Div => StackReq {
+            Operation::Add
+            | Operation::Sub
+            | Operation::Mul
+            | Operation::Div
+            | Operation::SDiv
+            | Operation::SMod
+            | Operation::SignExtend
+            | Operation::Slt
+            | Operation::Sgt
+            | Operation::Sar
+            | Operation::Keccak256 => StackReq {
                 min_stack_height: 2,
                 stack_inputs: 2,
                 stack_outputs: 1,
             },
 
-            Operation::CodeCopy => StackReq {
+            Operation::CodeCopy | Operation::ReturnDataCopy => StackReq {
                 min_stack_height: 3,
                 stack_inputs: 3,
                 stack_outputs: 0,
             },
+            Operation::ExtCodeCopy => StackReq {
+                min_stack_height: 4,
+                stack_inputs: 4,
+                stack_outputs: 0,
+            },
+
+            Operation::Call | Operation::CallCode => StackReq {
+                min_stack_height: 7,
+                stack_inputs: 7,
+                stack_outputs: 1,
+            },
+
+            Operation::DelegateCall => StackReq {
+                min_stack_height: 6,
+                stack_inputs: 6,
+                stack_outputs: 1,
+            },
 
             Operation::Dup(n) if *n >= 1 && *n <= 16 => StackReq {
                 min_stack_height: *n as u32,
@@ -503,16 +640,303 @@ impl Operation {
                 stack_outputs: 1,
             },
 
-            // Default conservative requirements
-            _ => panic!("Stack requirements not implemented for {:?}", self),
+            // Unsupported operations are rejected by the interpreter itself
+            _ => StackReq {
+                min_stack_height: 0,
+                stack_inputs: 0,
+                stack_outputs: 0,
+            },
+        }
+    }
+
+    // Encode the operation back into bytecode, including the PUSH immediate.
+    // The immediate is always emitted at the full width of the PUSH variant,
+    // so leading zero bytes of the original bytecode are preserved.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode()];
+        if let Some(value) = self.push_value() {
+            let width = self.additional_bytes();
+            bytes.extend_from_slice(&value.to_be_bytes::<32>()[32 - width..]);
+        }
+        bytes
+    }
+
+    // Get the immediate value of a PUSH operation
+    pub fn push_value(&self) -> Option<U256> {
+        match self {
+            Operation::Push1(value)
+            | Operation::Push2(value)
+            | Operation::Push3(value)
+            | Operation::Push4(value)
+            | Operation::Push5(value)
+            | Operation::Push6(value)
+            | Operation::Push7(value)
+            | Operation::Push8(value)
+            | Operation::Push9(value)
+            | Operation::Push10(value)
+            | Operation::Push11(value)
+            | Operation::Push12(value)
+            | Operation::Push13(value)
+            | Operation::Push14(value)
+            | Operation::Push15(value)
+            | Operation::Push16(value)
+            | Operation::Push17(value)
+            | Operation::Push18(value)
+            | Operation::Push19(value)
+            | Operation::Push20(value)
+            | Operation::Push21(value)
+            | Operation::Push22(value)
+            | Operation::Push23(value)
+            | Operation::Push24(value)
+            | Operation::Push25(value)
+            | Operation::Push26(value)
+            | Operation::Push27(value)
+            | Operation::Push28(value)
+            | Operation::Push29(value)
+            | Operation::Push30(value)
+            | Operation::Push31(value)
+            | Operation::Push32(value) => Some(*value),
+            _ => None,
         }
     }
 
     pub fn opcode(&self) -> u8 {
+        // DUP1 to DUP16 share the discriminant of DUP1, the depth selects the opcode
+        if let Operation::Dup(n) = self {
+            return 0x80 + n - 1;
+        }
+
         // SAFETY: This is safe because:
         // 1. The enum is #[repr(u8)]
         // 2. We're only reading the discriminant, not the associated data
         // 3. The size of u8 matches the repr
         unsafe { *(self as *const Operation as *const u8) }
     }
+
+    /// Canonical uppercase name of the operation, e.g. `ADD`, `PUSH1` or `DUP3`.
+    pub fn mnemonic(&self) -> &'static str {
+        const DUPS: [&str; 16] = [
+            "DUP1", "DUP2", "DUP3", "DUP4", "DUP5", "DUP6", "DUP7", "DUP8", "DUP9", "DUP10",
+            "DUP11", "DUP12", "DUP13", "DUP14", "DUP15", "DUP16",
+        ];
+        match self {
+            Operation::Stop => "STOP",
+            Operation::Add => "ADD",
+            Operation::Mul => "MUL",
+            Operation::Sub => "SUB",
+            Operation::Div => "DIV",
+            Operation::SDiv => "SDIV",
+            Operation::Mod => "MOD",
+            Operation::SMod => "SMOD",
+            Operation::AddMod => "ADDMOD",
+            Operation::MulMod => "MULMOD",
+            Operation::Exp => "EXP",
+            Operation::SignExtend => "SIGNEXTEND",
+            Operation::Lt => "LT",
+            Operation::Gt => "GT",
+            Operation::Slt => "SLT",
+            Operation::Sgt => "SGT",
+            Operation::Eq => "EQ",
+            Operation::IsZero => "ISZERO",
+            Operation::And => "AND",
+            Operation::Or => "OR",
+            Operation::Xor => "XOR",
+            Operation::Not => "NOT",
+            Operation::Byte => "BYTE",
+            Operation::Shl => "SHL",
+            Operation::Shr => "SHR",
+            Operation::Sar => "SAR",
+            Operation::Keccak256 => "KECCAK256",
+            Operation::Address => "ADDRESS",
+            Operation::Balance => "BALANCE",
+            Operation::Origin => "ORIGIN",
+            Operation::Caller => "CALLER",
+            Operation::CallValue => "CALLVALUE",
+            Operation::CallDataLoad => "CALLDATALOAD",
+            Operation::CallDataSize => "CALLDATASIZE",
+            Operation::CallDataCopy => "CALLDATACOPY",
+            Operation::CodeSize => "CODESIZE",
+            Operation::CodeCopy => "CODECOPY",
+            Operation::GasPrice => "GASPRICE",
+            Operation::ExtCodeSize => "EXTCODESIZE",
+            Operation::ExtCodeCopy => "EXTCODECOPY",
+            Operation::ReturnDataSize => "RETURNDATASIZE",
+            Operation::ReturnDataCopy => "RETURNDATACOPY",
+            Operation::ExtCodeHash => "EXTCODEHASH",
+            Operation::BlockHash => "BLOCKHASH",
+            Operation::Coinbase => "COINBASE",
+            Operation::Timestamp => "TIMESTAMP",
+            Operation::Number => "NUMBER",
+            Operation::Difficulty => "DIFFICULTY",
+            Operation::GasLimit => "GASLIMIT",
+            Operation::ChainId => "CHAINID",
+            Operation::SelfBalance => "SELFBALANCE",
+            Operation::BaseFee => "BASEFEE",
+            Operation::Pop => "POP",
+            Operation::MLoad => "MLOAD",
+            Operation::MStore => "MSTORE",
+            Operation::MStore8 => "MSTORE8",
+            Operation::SLoad => "SLOAD",
+            Operation::SStore => "SSTORE",
+            Operation::Jump => "JUMP",
+            Operation::JumpI => "JUMPI",
+            Operation::PC => "PC",
+            Operation::MSize => "MSIZE",
+            Operation::Gas => "GAS",
+            Operation::JumpDest => "JUMPDEST",
+            Operation::Push0 => "PUSH0",
+            Operation::Push1(_) => "PUSH1",
+            Operation::Push2(_) => "PUSH2",
+            Operation::Push3(_) => "PUSH3",
+            Operation::Push4(_) => "PUSH4",
+            Operation::Push5(_) => "PUSH5",
+            Operation::Push6(_) => "PUSH6",
+            Operation::Push7(_) => "PUSH7",
+            Operation::Push8(_) => "PUSH8",
+            Operation::Push9(_) => "PUSH9",
+            Operation::Push10(_) => "PUSH10",
+            Operation::Push11(_) => "PUSH11",
+            Operation::Push12(_) => "PUSH12",
+            Operation::Push13(_) => "PUSH13",
+            Operation::Push14(_) => "PUSH14",
+            Operation::Push15(_) => "PUSH15",
+            Operation::Push16(_) => "PUSH16",
+            Operation::Push17(_) => "PUSH17",
+            Operation::Push18(_) => "PUSH18",
+            Operation::Push19(_) => "PUSH19",
+            Operation::Push20(_) => "PUSH20",
+            Operation::Push21(_) => "PUSH21",
+            Operation::Push22(_) => "PUSH22",
+            Operation::Push23(_) => "PUSH23",
+            Operation::Push24(_) => "PUSH24",
+            Operation::Push25(_) => "PUSH25",
+            Operation::Push26(_) => "PUSH26",
+            Operation::Push27(_) => "PUSH27",
+            Operation::Push28(_) => "PUSH28",
+            Operation::Push29(_) => "PUSH29",
+            Operation::Push30(_) => "PUSH30",
+            Operation::Push31(_) => "PUSH31",
+            Operation::Push32(_) => "PUSH32",
+            Operation::Swap1 => "SWAP1",
+            Operation::Swap2 => "SWAP2",
+            Operation::Swap3 => "SWAP3",
+            Operation::Swap4 => "SWAP4",
+            Operation::Swap5 => "SWAP5",
+            Operation::Swap6 => "SWAP6",
+            Operation::Swap7 => "SWAP7",
+            Operation::Swap8 => "SWAP8",
+            Operation::Swap9 => "SWAP9",
+            Operation::Swap10 => "SWAP10",
+            Operation::Swap11 => "SWAP11",
+            Operation::Swap12 => "SWAP12",
+            Operation::Swap13 => "SWAP13",
+            Operation::Swap14 => "SWAP14",
+            Operation::Swap15 => "SWAP15",
+            Operation::Swap16 => "SWAP16",
+            Operation::Log0 => "LOG0",
+            Operation::Log1 => "LOG1",
+            Operation::Log2 => "LOG2",
+            Operation::Log3 => "LOG3",
+            Operation::Log4 => "LOG4",
+            Operation::Create => "CREATE",
+            Operation::Call => "CALL",
+            Operation::CallCode => "CALLCODE",
+            Operation::Return => "RETURN",
+            Operation::DelegateCall => "DELEGATECALL",
+            Operation::Create2 => "CREATE2",
+            Operation::StaticCall => "STATICCALL",
+            Operation::Revert => "REVERT",
+            Operation::Invalid => "INVALID",
+            Operation::SelfDestruct => "SELFDESTRUCT",
+            Operation::Dup(n) => DUPS[usize::from(*n).clamp(1, 16) - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_byte_round_trip() {
+        let mut known = 0;
+        for byte in 0..=0xffu8 {
+            match Operation::from_byte(byte, Some(U256::from(1))) {
+                Ok(operation) => {
+                    known += 1;
+                    assert_eq!(operation.opcode(), byte, "{:?}", operation);
+                    assert_eq!(operation.to_bytes()[0], byte, "{:?}", operation);
+                    assert_eq!(
+                        Operation::from_byte(operation.opcode(), operation.push_value())
+                            .map(|decoded| decoded.opcode()),
+                        Ok(byte)
+                    );
+                }
+                Err(err) => assert_eq!(err, OperationError::UnknownOpcode, "{:#04x}", byte),
+            }
+        }
+        // 0x00-0x0b, 0x10-0x1d, 0x20, 0x30-0x3f, 0x40-0x48, 0x50-0x5b, PUSH0-PUSH32, DUP, SWAP,
+        // LOG and the 10 system operations
+        assert_eq!(known, 12 + 14 + 1 + 16 + 9 + 12 + 33 + 16 + 16 + 5 + 10);
+
+        assert_eq!(Operation::Push0.opcode(), 0x5f);
+        assert_eq!(Operation::JumpDest.opcode(), 0x5b);
+        assert_eq!(Operation::Dup(16).opcode(), 0x8f);
+        assert!(matches!(
+            Operation::from_byte(0x60, None),
+            Err(OperationError::PushOpNeedsData)
+        ));
+    }
+
+    #[test]
+    fn test_mnemonics() {
+        for (operation, mnemonic) in [
+            (Operation::Stop, "STOP"),
+            (Operation::Add, "ADD"),
+            (Operation::SDiv, "SDIV"),
+            (Operation::IsZero, "ISZERO"),
+            (Operation::Keccak256, "KECCAK256"),
+            (Operation::CallDataLoad, "CALLDATALOAD"),
+            (Operation::ReturnDataCopy, "RETURNDATACOPY"),
+            (Operation::MStore8, "MSTORE8"),
+            (Operation::JumpDest, "JUMPDEST"),
+            (Operation::Push0, "PUSH0"),
+            (Operation::Push1(U256::from(1)), "PUSH1"),
+            (Operation::Push32(U256::MAX), "PUSH32"),
+            (Operation::Dup(1), "DUP1"),
+            (Operation::Dup(16), "DUP16"),
+            (Operation::Swap3, "SWAP3"),
+            (Operation::Log4, "LOG4"),
+            (Operation::DelegateCall, "DELEGATECALL"),
+            (Operation::SelfDestruct, "SELFDESTRUCT"),
+        ] {
+            assert_eq!(operation.mnemonic(), mnemonic);
+        }
+
+        // every opcode has its own mnemonic
+        let mnemonics: std::collections::HashSet<&str> = (0..=0xffu8)
+            .filter_map(|byte| Operation::from_byte(byte, Some(U256::ZERO)).ok())
+            .map(|operation| operation.mnemonic())
+            .collect();
+        assert_eq!(mnemonics.len(), 144);
+    }
+
+    #[test]
+    fn test_stack_and_flow_gas_costs() {
+        // DUP1-DUP16 and SWAP1-SWAP16
+        for byte in 0x80..=0x9fu8 {
+            let operation = Operation::from_byte(byte, None).unwrap();
+            assert_eq!(operation.gas_cost().base, 3, "{:?}", operation);
+        }
+        for (operation, gas) in [
+            (Operation::JumpDest, 1),
+            (Operation::Jump, 8),
+            (Operation::JumpI, 10),
+            (Operation::PC, 2),
+            (Operation::MSize, 2),
+            (Operation::Gas, 2),
+        ] {
+            assert_eq!(operation.gas_cost().base, gas, "{:?}", operation);
+        }
+    }
 }
@@ -0,0 +1,425 @@
+// Contracts implemented natively by the client at fixed low addresses. They have no code in
+// the state, so calls have to be dispatched to them before looking up the callee's code.
+
+use alloy_primitives::{address, Address};
+use bn::{AffineG1, AffineG2, Fq, Fq2, Fr, Group, Gt, G1, G2};
+
+use crate::evm::evm::VMError;
+
+pub const IDENTITY: Address = address!("0000000000000000000000000000000000000004");
+pub const BN256_ADD: Address = address!("0000000000000000000000000000000000000006");
+pub const BN256_MUL: Address = address!("0000000000000000000000000000000000000007");
+pub const BN256_PAIRING: Address = address!("0000000000000000000000000000000000000008");
+pub const BLAKE2F: Address = address!("0000000000000000000000000000000000000009");
+
+/// Addresses of all the precompiles.
+pub const ADDRESSES: [Address; 5] = [IDENTITY, BN256_ADD, BN256_MUL, BN256_PAIRING, BLAKE2F];
+
+/// Gas costs of the alt_bn128 precompiles since Istanbul (EIP-1108).
+const BN256_ADD_COST: u64 = 150;
+const BN256_MUL_COST: u64 = 6000;
+const BN256_PAIRING_BASE_COST: u64 = 45_000;
+const BN256_PAIRING_PAIR_COST: u64 = 34_000;
+
+/// Size of a G1 point, followed by a G2 point, in the input of the pairing check.
+const BN256_PAIR_SIZE: usize = 192;
+
+/// Size of the input of BLAKE2F: the rounds, the state `h`, the message block `m`, the offset
+/// counter `t` and the final block flag (EIP-152).
+const BLAKE2F_INPUT_SIZE: usize = 213;
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+/// Order in which the words of the message block are mixed in each round.
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// Addresses reserved for precompiles, `0x01` to `0x09`, whether or not they are implemented.
+pub fn reserved_addresses() -> impl Iterator<Item = Address> {
+    (1..=9).map(Address::with_last_byte)
+}
+
+/// Whether `address` is reserved for a precompile, see `reserved_addresses`.
+pub fn is_reserved(address: &Address) -> bool {
+    reserved_addresses().any(|reserved| reserved == *address)
+}
+
+/// Executes a precompile on `input` with `gas`, returning the gas it used and its output.
+pub type Precompile = fn(input: &[u8], gas: u64) -> Result<(u64, Vec<u8>), VMError>;
+
+/// Returns the precompile at `address`, if there is one.
+pub fn get(address: &Address) -> Option<Precompile> {
+    match *address {
+        IDENTITY => Some(identity),
+        BN256_ADD => Some(bn256_add),
+        BN256_MUL => Some(bn256_mul),
+        BN256_PAIRING => Some(bn256_pairing),
+        BLAKE2F => Some(blake2f),
+        _ => None,
+    }
+}
+
+/// Fails if `gas_used` is more than the `gas` given to the precompile.
+fn charge(gas_used: u64, gas: u64) -> Result<(), VMError> {
+    if gas < gas_used {
+        return Err(VMError::OutOfGas {
+            needed: gas_used,
+            available: gas,
+        });
+    }
+    Ok(())
+}
+
+/// Returns its input unchanged.
+fn identity(input: &[u8], gas: u64) -> Result<(u64, Vec<u8>), VMError> {
+    let gas_used = 15 + 3 * (input.len() as u64).div_ceil(32);
+    charge(gas_used, gas)?;
+    Ok((gas_used, input.to_vec()))
+}
+
+/// Adds two points of the alt_bn128 curve (EIP-196).
+fn bn256_add(input: &[u8], gas: u64) -> Result<(u64, Vec<u8>), VMError> {
+    charge(BN256_ADD_COST, gas)?;
+    let input = padded(input, 128);
+    let sum = read_g1(&input[..64])? + read_g1(&input[64..])?;
+    Ok((BN256_ADD_COST, write_g1(sum)))
+}
+
+/// Multiplies a point of the alt_bn128 curve by a scalar (EIP-196).
+fn bn256_mul(input: &[u8], gas: u64) -> Result<(u64, Vec<u8>), VMError> {
+    charge(BN256_MUL_COST, gas)?;
+    let input = padded(input, 96);
+    let point = read_g1(&input[..64])?;
+    // the scalar is reduced modulo the group order
+    let scalar = Fr::from_slice(&input[64..])
+        .map_err(|_| VMError::InvalidPrecompileInput("invalid scalar"))?;
+    Ok((BN256_MUL_COST, write_g1(point * scalar)))
+}
+
+/// Checks that the product of the pairings of the given (G1, G2) pairs is one (EIP-197). An
+/// empty input passes.
+fn bn256_pairing(input: &[u8], gas: u64) -> Result<(u64, Vec<u8>), VMError> {
+    if !input.len().is_multiple_of(BN256_PAIR_SIZE) {
+        return Err(VMError::InvalidPrecompileInput(
+            "pairing input is not a list of pairs",
+        ));
+    }
+    let gas_used =
+        BN256_PAIRING_BASE_COST + BN256_PAIRING_PAIR_COST * (input.len() / BN256_PAIR_SIZE) as u64;
+    charge(gas_used, gas)?;
+
+    let mut pairs = Vec::new();
+    for pair in input.chunks(BN256_PAIR_SIZE) {
+        let g1 = read_g1(&pair[..64])?;
+        let g2 = read_g2(&pair[64..])?;
+        // a pairing with the point at infinity is one
+        if !g1.is_zero() && !g2.is_zero() {
+            pairs.push((g1, g2));
+        }
+    }
+    let success = pairs.is_empty() || bn::pairing_batch(&pairs) == Gt::one();
+
+    let mut output = vec![0; 32];
+    output[31] = success as u8;
+    Ok((gas_used, output))
+}
+
+/// Runs the BLAKE2b compression function F for the given number of rounds, charging one gas
+/// per round (EIP-152).
+fn blake2f(input: &[u8], gas: u64) -> Result<(u64, Vec<u8>), VMError> {
+    if input.len() != BLAKE2F_INPUT_SIZE {
+        return Err(VMError::InvalidPrecompileInput(
+            "blake2f input must be 213 bytes",
+        ));
+    }
+    let final_block = match input[212] {
+        0 => false,
+        1 => true,
+        _ => return Err(VMError::InvalidPrecompileInput("invalid final block flag")),
+    };
+    let rounds = u32::from_be_bytes(input[..4].try_into().unwrap());
+    let gas_used = rounds as u64;
+    charge(gas_used, gas)?;
+
+    // every other field is made of little-endian words
+    let word = |offset: usize| u64::from_le_bytes(input[offset..offset + 8].try_into().unwrap());
+    let mut h: [u64; 8] = std::array::from_fn(|i| word(4 + 8 * i));
+    let m: [u64; 16] = std::array::from_fn(|i| word(68 + 8 * i));
+    let t = [word(196), word(204)];
+    blake2b_compress(rounds, &mut h, &m, t, final_block);
+
+    Ok((
+        gas_used,
+        h.iter().flat_map(|word| word.to_le_bytes()).collect(),
+    ))
+}
+
+fn blake2b_compress(rounds: u32, h: &mut [u64; 8], m: &[u64; 16], t: [u64; 2], final_block: bool) {
+    let mut v = [0; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&BLAKE2B_IV);
+    v[12] ^= t[0];
+    v[13] ^= t[1];
+    if final_block {
+        v[14] = !v[14];
+    }
+
+    for round in 0..rounds as usize {
+        let s = &BLAKE2B_SIGMA[round % 10];
+        blake2b_mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        blake2b_mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        blake2b_mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        blake2b_mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        blake2b_mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        blake2b_mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        blake2b_mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        blake2b_mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+/// Mixes the message words `x` and `y` into four words of the working vector.
+fn blake2b_mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// `input` right-padded with zeros, or truncated, to `size` bytes.
+fn padded(input: &[u8], size: usize) -> Vec<u8> {
+    let mut input = input[..input.len().min(size)].to_vec();
+    input.resize(size, 0);
+    input
+}
+
+fn read_fq(bytes: &[u8]) -> Result<Fq, VMError> {
+    Fq::from_slice(bytes).map_err(|_| VMError::InvalidPrecompileInput("coordinate not in field"))
+}
+
+/// Reads a G1 point encoded as its `x` and `y` coordinates, `(0, 0)` being the point at
+/// infinity.
+fn read_g1(bytes: &[u8]) -> Result<G1, VMError> {
+    let x = read_fq(&bytes[..32])?;
+    let y = read_fq(&bytes[32..64])?;
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1::zero());
+    }
+    AffineG1::new(x, y)
+        .map(G1::from)
+        .map_err(|_| VMError::InvalidPrecompileInput("point not on curve"))
+}
+
+/// Reads a G2 point, each coordinate encoded as its imaginary part followed by its real part.
+fn read_g2(bytes: &[u8]) -> Result<G2, VMError> {
+    let x = Fq2::new(read_fq(&bytes[32..64])?, read_fq(&bytes[..32])?);
+    let y = Fq2::new(read_fq(&bytes[96..128])?, read_fq(&bytes[64..96])?);
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2::zero());
+    }
+    AffineG2::new(x, y)
+        .map(G2::from)
+        .map_err(|_| VMError::InvalidPrecompileInput("point not on curve"))
+}
+
+/// Encodes a G1 point as its `x` and `y` coordinates, `(0, 0)` for the point at infinity.
+fn write_g1(point: G1) -> Vec<u8> {
+    let mut output = vec![0; 64];
+    if let Some(point) = AffineG1::from_jacobian(point) {
+        point.x().to_big_endian(&mut output[..32]).unwrap();
+        point.y().to_big_endian(&mut output[32..]).unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{hex, U256};
+
+    const G1_X: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+    const G1_Y: &str = "0000000000000000000000000000000000000000000000000000000000000002";
+    // y of the negated generator, the field modulus minus 2
+    const G1_NEG_Y: &str = "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd45";
+    const G1_DOUBLE: &str = "030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd3\
+                             15ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4";
+    const G2: &str = "198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c2\
+                      1800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed\
+                      090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b\
+                      12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa";
+
+    fn input(parts: &[&str]) -> Vec<u8> {
+        hex::decode(parts.concat()).unwrap()
+    }
+
+    fn word(value: u64) -> String {
+        hex::encode(U256::from(value).to_be_bytes::<32>())
+    }
+
+    #[test]
+    fn test_bn256_add() {
+        let (gas_used, output) = bn256_add(&input(&[G1_X, G1_Y, G1_X, G1_Y]), 150).unwrap();
+        assert_eq!(gas_used, BN256_ADD_COST);
+        assert_eq!(output, input(&[G1_DOUBLE]));
+
+        // the point at infinity is the identity, and a missing input reads as infinity
+        let (_, output) = bn256_add(&input(&[G1_X, G1_Y]), 150).unwrap();
+        assert_eq!(output, input(&[G1_X, G1_Y]));
+        let (_, output) = bn256_add(&[], 150).unwrap();
+        assert_eq!(output, vec![0; 64]);
+        // a point and its negation add up to infinity
+        let (_, output) = bn256_add(&input(&[G1_X, G1_Y, G1_X, G1_NEG_Y]), 150).unwrap();
+        assert_eq!(output, vec![0; 64]);
+
+        assert!(matches!(
+            bn256_add(&input(&[G1_X, G1_X]), 150),
+            Err(VMError::InvalidPrecompileInput(_))
+        ));
+        assert!(matches!(
+            bn256_add(&input(&[G1_X, G1_Y]), 149),
+            Err(VMError::OutOfGas { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bn256_mul() {
+        let (gas_used, output) = bn256_mul(&input(&[G1_X, G1_Y, &word(2)]), 6000).unwrap();
+        assert_eq!(gas_used, BN256_MUL_COST);
+        assert_eq!(output, input(&[G1_DOUBLE]));
+
+        let (_, output) = bn256_mul(&input(&[G1_X, G1_Y, &word(1)]), 6000).unwrap();
+        assert_eq!(output, input(&[G1_X, G1_Y]));
+        // multiplying by zero or multiplying infinity gives infinity
+        let (_, output) = bn256_mul(&input(&[G1_X, G1_Y]), 6000).unwrap();
+        assert_eq!(output, vec![0; 64]);
+        let (_, output) = bn256_mul(&input(&[&word(0), &word(0), &word(5)]), 6000).unwrap();
+        assert_eq!(output, vec![0; 64]);
+
+        assert!(matches!(
+            bn256_mul(&input(&[G1_X, G1_X, &word(2)]), 6000),
+            Err(VMError::InvalidPrecompileInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_blake2f() {
+        // the EIP-152 vectors hash "abc" in a single block with the BLAKE2b-512 parameters
+        let vector = |rounds: &str, final_block: &str| {
+            input(&[
+                rounds,
+                "48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5",
+                "d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b",
+                "6162630000000000000000000000000000000000000000000000000000000000",
+                &"00".repeat(96),
+                "03000000000000000000000000000000",
+                final_block,
+            ])
+        };
+
+        let (gas_used, output) = blake2f(&vector("0000000c", "01"), 12).unwrap();
+        assert_eq!(gas_used, 12);
+        assert_eq!(
+            hex::encode(output),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        let (_, output) = blake2f(&vector("0000000c", "00"), 12).unwrap();
+        assert_eq!(
+            hex::encode(output),
+            "75ab69d3190a562c51aef8d88f1c2775876944407270c42c9844252c26d28752\
+             98743e7f6d5ea2f2d3e8d226039cd31b4e426ac4f2d3d666a610c2116fde4735"
+        );
+        let (_, output) = blake2f(&vector("00000001", "01"), 1).unwrap();
+        assert_eq!(
+            hex::encode(output),
+            "b63a380cb2897d521994a85234ee2c181b5f844d2c624c002677e9703449d2fb\
+             a551b3a8333bcdf5f2f7e08993d53923de3d64fcc68c034e717b9293fed7a421"
+        );
+        let (gas_used, output) = blake2f(&vector("00000000", "01"), 0).unwrap();
+        assert_eq!(gas_used, 0);
+        assert_eq!(
+            hex::encode(output),
+            "08c9bcf367e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5\
+             d282e6ad7f520e511f6c3e2b8c68059b9442be0454267ce079217e1319cde05b"
+        );
+
+        let valid = vector("0000000c", "01");
+        for invalid in [&valid[..212], &[valid.as_slice(), &[0]].concat()] {
+            assert!(matches!(
+                blake2f(invalid, 12),
+                Err(VMError::InvalidPrecompileInput(_))
+            ));
+        }
+        assert!(matches!(
+            blake2f(&vector("0000000c", "02"), 12),
+            Err(VMError::InvalidPrecompileInput(_))
+        ));
+        assert!(matches!(blake2f(&valid, 11), Err(VMError::OutOfGas { .. })));
+    }
+
+    #[test]
+    fn test_bn256_pairing() {
+        let one = word(1);
+        let zero = word(0);
+
+        // e(P, Q) * e(-P, Q) == 1
+        let (gas_used, output) =
+            bn256_pairing(&input(&[G1_X, G1_Y, G2, G1_X, G1_NEG_Y, G2]), 113_000).unwrap();
+        assert_eq!(gas_used, 45_000 + 2 * 34_000);
+        assert_eq!(output, input(&[&one]));
+
+        // e(P, Q) != 1
+        let (_, output) = bn256_pairing(&input(&[G1_X, G1_Y, G2]), 79_000).unwrap();
+        assert_eq!(output, input(&[&zero]));
+
+        // the empty product is one, as is a pairing with the point at infinity
+        assert_eq!(
+            bn256_pairing(&[], 45_000).unwrap(),
+            (45_000, input(&[&one]))
+        );
+        let infinity = [zero.as_str(); 2].concat();
+        let (_, output) = bn256_pairing(&input(&[&infinity, G2]), 79_000).unwrap();
+        assert_eq!(output, input(&[&one]));
+
+        assert!(matches!(
+            bn256_pairing(&input(&[G1_X, G1_Y]), 100_000),
+            Err(VMError::InvalidPrecompileInput(_))
+        ));
+        // a G2 point off the curve
+        let (g2_x, g2_y) = G2.split_at(128);
+        assert!(matches!(
+            bn256_pairing(&input(&[G1_X, G1_Y, g2_y, g2_x]), 79_000),
+            Err(VMError::InvalidPrecompileInput(_))
+        ));
+        assert!(matches!(
+            bn256_pairing(&input(&[G1_X, G1_Y, G2]), 78_999),
+            Err(VMError::OutOfGas { .. })
+        ));
+    }
+}
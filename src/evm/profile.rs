@@ -0,0 +1,116 @@
+// Attribution of the gas of an execution to the operations that consumed it, exported in the
+// folded stack format read by flamegraph tools such as `inferno-flamegraph`.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use alloy_primitives::Address;
+
+/// Gas consumed by each operation, keyed by the stack of contracts it ran in.
+#[derive(Default, Clone, Debug)]
+pub struct GasProfile {
+    gas: BTreeMap<(Vec<Address>, &'static str), u64>,
+    // gas of nested calls already attributed to the operations of the callee, to be left out
+    // of the next recorded operation, i.e. the call itself
+    nested_gas: u64,
+}
+
+impl GasProfile {
+    /// Attributes `gas` to `operation` executed by the last contract of `call_stack`.
+    pub fn record(&mut self, call_stack: &[Address], operation: &'static str, gas: u64) {
+        let gas = gas.saturating_sub(std::mem::take(&mut self.nested_gas));
+        *self
+            .gas
+            .entry((call_stack.to_vec(), operation))
+            .or_default() += gas;
+    }
+
+    /// Adds the profile of a call made by the next recorded operation.
+    pub fn merge_call(&mut self, callee: GasProfile) {
+        self.nested_gas += callee.total();
+        for (key, gas) in callee.gas {
+            *self.gas.entry(key).or_default() += gas;
+        }
+    }
+
+    /// Total gas attributed to operations.
+    pub fn total(&self) -> u64 {
+        self.gas.values().sum()
+    }
+
+    /// Writes one `0xcaller;0xcallee;Operation gas` line per operation and call stack.
+    pub fn write_folded<W: Write>(&self, mut out: W) -> io::Result<()> {
+        for ((call_stack, operation), gas) in &self.gas {
+            for address in call_stack {
+                write!(out, "{};", address)?;
+            }
+            writeln!(out, "{} {}", operation, gas)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::{Account, EMPTY_CODE_HASH};
+    use crate::block::state::State;
+    use crate::evm::evm::{Contract, ExecutionContext, VM};
+    use crate::evm::operation::Operation;
+    use crate::evm::program::Program;
+    use alloy_primitives::{B256, U256};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_folded_gas_profile() {
+        let caller = Address::repeat_byte(0xaa);
+        let callee = Address::repeat_byte(0xbb);
+
+        let callee_code = Program::new()
+            .push(U256::from(1))
+            .push(U256::from(2))
+            .op(Operation::Add)
+            .build();
+        let code = Program::new()
+            .op(Operation::Push0) // retSize
+            .op(Operation::Push0) // retOffset
+            .op(Operation::Push0) // argsSize
+            .op(Operation::Push0) // argsOffset
+            .op(Operation::Push0) // value
+            .push(U256::from_be_slice(callee.as_slice())) // address
+            .push(U256::from(10_000)) // gas
+            .op(Operation::Call)
+            .build();
+
+        let mut state = State::new();
+        state.set_account(caller, Account::new(0, EMPTY_CODE_HASH, B256::ZERO));
+        state.contract.insert(callee, Contract::new(callee_code));
+
+        let mut vm = VM::new(
+            Contract::new(code.clone()),
+            ExecutionContext::new(Address::ZERO, caller, 0, vec![], 100_000),
+            Arc::new(Mutex::new(state)),
+        )
+        .with_gas_profile();
+        vm.execute_operations(code).unwrap();
+
+        let profile = vm.gas_profile().unwrap();
+        assert_eq!(profile.total(), vm.gas_used());
+
+        let mut folded = Vec::new();
+        profile.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        assert!(folded.contains(&format!("{};Push0 10\n", caller)));
+        assert!(folded.contains(&format!("{};Call 2600\n", caller)));
+        assert!(folded.contains(&format!("{};{};Add 3\n", caller, callee)));
+        assert!(folded.contains(&format!("{};{};Push1 6\n", caller, callee)));
+
+        // the frames under the top-level contract add up to the gas of the whole execution
+        let top_level_gas: u64 = folded
+            .lines()
+            .filter(|line| line.starts_with(&caller.to_string()))
+            .map(|line| line.rsplit(' ').next().unwrap().parse::<u64>().unwrap())
+            .sum();
+        assert_eq!(top_level_gas, vm.gas_used());
+    }
+}
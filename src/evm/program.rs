@@ -0,0 +1,79 @@
+// Assembles bytecode from operations, so tests don't have to interleave opcodes and PUSH
+// immediates by hand.
+
+use alloy_primitives::U256;
+
+use crate::evm::operation::Operation;
+
+#[derive(Default)]
+pub struct Program {
+    code: Vec<u8>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes `value` with the narrowest PUSH that holds it. Zero is pushed as `PUSH1 0x00`,
+    /// use `.op(Operation::Push0)` for `PUSH0`.
+    pub fn push(self, value: U256) -> Self {
+        let width = value.byte_len().max(1) as u8;
+        let operation = Operation::from_byte(0x5f + width, Some(value))
+            .expect("a PUSH of 1 to 32 bytes is always valid");
+        self.op(operation)
+    }
+
+    /// Appends an operation, including the immediate of a PUSH.
+    pub fn op(mut self, operation: Operation) -> Self {
+        self.code.extend(operation.to_bytes());
+        self
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_matches_hand_written_bytecode() {
+        // the fixture of `test_storage_revert`
+        let hand_written = vec![
+            Operation::Push1(U256::from(42)).opcode(), // Value to store
+            42,
+            Operation::Push1(U256::from(0)).opcode(), // Key
+            0,
+            Operation::SStore.opcode(),
+            Operation::Push1(U256::from(0)).opcode(), // Key
+            0,
+            Operation::SLoad.opcode(),
+            Operation::Push1(U256::from(10)).opcode(), // Revert memory length
+            10,
+            Operation::Push1(U256::from(0)).opcode(), // Revert memory offset
+            0,
+            Operation::Revert.opcode(),
+        ];
+
+        let program = Program::new()
+            .push(U256::from(42))
+            .push(U256::ZERO)
+            .op(Operation::SStore)
+            .push(U256::ZERO)
+            .op(Operation::SLoad)
+            .push(U256::from(10))
+            .push(U256::ZERO)
+            .op(Operation::Revert)
+            .build();
+        assert_eq!(program, hand_written);
+
+        let wide = Program::new()
+            .push(U256::from(0x0100))
+            .op(Operation::Push0)
+            .build();
+        assert_eq!(wide, vec![0x61, 0x01, 0x00, 0x5f]);
+    }
+}
@@ -0,0 +1,126 @@
+// One-shot execution of a piece of bytecode outside of a transaction, used by the `run`
+// subcommand of the node binary.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use alloy_primitives::{hex, Address, U256};
+
+use crate::block::state::State;
+use crate::evm::evm::{Contract, ExecutionContext, ExecutionResult, VMError, VM};
+
+pub const DEFAULT_RUN_GAS: u64 = 10_000_000;
+
+#[derive(Debug)]
+pub enum RunError {
+    InvalidHex(String),
+    Io(std::io::Error),
+    Execution(VMError),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::InvalidHex(input) => write!(f, "invalid hex input: {}", input),
+            RunError::Io(err) => write!(f, "failed to read bytecode: {}", err),
+            RunError::Execution(err) => write!(f, "execution failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Result of a one-shot run.
+#[derive(Debug)]
+pub struct RunReport {
+    pub success: bool,
+    pub return_data: Vec<u8>,
+    pub revert_reason: Vec<u8>,
+    pub gas_used: u64,
+    pub stack: Vec<U256>,
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "status:        {}",
+            if self.success { "success" } else { "revert" }
+        )?;
+        writeln!(f, "gas used:      {}", self.gas_used)?;
+        if self.success {
+            writeln!(
+                f,
+                "return data:   {}",
+                hex::encode_prefixed(&self.return_data)
+            )?;
+        } else {
+            writeln!(
+                f,
+                "revert reason: {}",
+                hex::encode_prefixed(&self.revert_reason)
+            )?;
+        }
+        write!(f, "stack:")?;
+        for item in self.stack.iter().rev() {
+            write!(f, "\n  {:#066x}", item)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a hex string, with or without the `0x` prefix.
+pub fn decode_hex(input: &str) -> Result<Vec<u8>, RunError> {
+    hex::decode(input.trim()).map_err(|_| RunError::InvalidHex(input.to_string()))
+}
+
+/// Loads bytecode from a `.evm` file if `input` is a path to one, or decodes `input` as hex.
+pub fn load_bytecode(input: &str) -> Result<Vec<u8>, RunError> {
+    if Path::new(input).is_file() {
+        let content = fs::read_to_string(input).map_err(RunError::Io)?;
+        decode_hex(&content)
+    } else {
+        decode_hex(input)
+    }
+}
+
+/// Executes `code` in a fresh VM against an empty state, called with `value` wei.
+pub fn run(code: Vec<u8>, calldata: Vec<u8>, value: u64, gas: u64) -> Result<RunReport, RunError> {
+    let state = Arc::new(Mutex::new(State::new()));
+    let context = ExecutionContext::new(Address::ZERO, Address::ZERO, value, calldata, gas);
+    let mut vm = VM::new(Contract::new(code.clone()), context, state);
+
+    let result = vm.execute_operations(code).map_err(RunError::Execution)?;
+    let (success, return_data, revert_reason) = match result {
+        ExecutionResult::Success { return_data, .. } => {
+            (true, return_data.unwrap_or_default(), vec![])
+        }
+        ExecutionResult::Revert { reason, .. } => (false, vec![], reason),
+    };
+
+    Ok(RunReport {
+        success,
+        return_data,
+        revert_reason,
+        gas_used: vm.gas_used(),
+        stack: vm.stack().to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_add_fixture() {
+        let code = load_bytecode("./test/Add.evm").unwrap();
+
+        let report = run(code, vec![], 0, DEFAULT_RUN_GAS).unwrap();
+
+        assert!(report.success);
+        assert!(!report.return_data.is_empty());
+        assert!(report.gas_used > 0);
+    }
+}
@@ -0,0 +1,51 @@
+// Two's complement interpretation of stack words, shared by the signed operations SDIV, SMOD,
+// SLT, SGT, SAR and SIGNEXTEND.
+
+use alloy_primitives::{I256, U256};
+
+/// Reads a word as a signed integer.
+pub fn to_signed(value: U256) -> I256 {
+    I256::from_raw(value)
+}
+
+/// Stores a signed integer as a word.
+pub fn from_signed(value: I256) -> U256 {
+    value.into_raw()
+}
+
+pub fn is_negative(value: U256) -> bool {
+    value.bit(255)
+}
+
+/// Magnitude of a word read as a signed integer. The magnitude of `INT_MIN`, 2^255, is
+/// representable as an unsigned word.
+pub fn abs(value: U256) -> U256 {
+    to_signed(value).unsigned_abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_helpers() {
+        let int_min = U256::from(1) << 255;
+        let minus_one = U256::MAX;
+
+        assert_eq!(to_signed(int_min), I256::MIN);
+        assert_eq!(to_signed(minus_one), I256::MINUS_ONE);
+        assert_eq!(to_signed(U256::ZERO), I256::ZERO);
+        assert_eq!(from_signed(I256::MIN), int_min);
+        assert_eq!(from_signed(I256::MINUS_ONE), minus_one);
+        assert_eq!(from_signed(I256::ZERO), U256::ZERO);
+
+        assert!(is_negative(int_min));
+        assert!(is_negative(minus_one));
+        assert!(!is_negative(U256::ZERO));
+        assert!(!is_negative(int_min - U256::from(1)));
+
+        assert_eq!(abs(int_min), int_min);
+        assert_eq!(abs(minus_one), U256::from(1));
+        assert_eq!(abs(U256::ZERO), U256::ZERO);
+    }
+}
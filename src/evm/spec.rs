@@ -0,0 +1,12 @@
+// Hardforks whose rules the VM can follow.
+
+/// A hardfork, ordered by activation so that `spec >= Spec::Shanghai` checks whether the rules
+/// of Shanghai apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Spec {
+    Berlin,
+    London,
+    /// Adds PUSH0 (EIP-3855).
+    #[default]
+    Shanghai,
+}
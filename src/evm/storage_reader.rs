@@ -0,0 +1,146 @@
+// Decoding of contract storage following the Solidity storage layout, where variables smaller
+// than a word are packed into a shared slot from its low-order end.
+
+use alloy_primitives::{Address, U256};
+
+use crate::block::state::State;
+use crate::crypto::hash::mapping_slot;
+
+/// Solidity type of a value type storage variable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageType {
+    /// `uintN`, with the width in bits.
+    Uint(usize),
+    Bool,
+    Address,
+}
+
+impl StorageType {
+    /// Number of bytes the type occupies in its slot.
+    pub fn size(&self) -> usize {
+        match self {
+            StorageType::Uint(bits) => bits / 8,
+            StorageType::Bool => 1,
+            StorageType::Address => 20,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StorageValue {
+    Uint(U256),
+    Bool(bool),
+    Address(Address),
+}
+
+/// Location of a storage variable: its slot and the byte offset within the slot, counted from
+/// the low-order end as in the layout reported by `solc --storage-layout`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageVariable {
+    pub slot: U256,
+    pub offset: usize,
+    pub ty: StorageType,
+}
+
+impl StorageVariable {
+    pub fn new(slot: U256, offset: usize, ty: StorageType) -> Self {
+        assert!(
+            offset + ty.size() <= 32,
+            "a storage variable must fit in its slot"
+        );
+        Self { slot, offset, ty }
+    }
+
+    /// The value stored under `key` in a mapping at `base_slot`, see `mapping_slot` for the
+    /// encoding of the key.
+    pub fn mapping_value(base_slot: U256, key: &[u8], ty: StorageType) -> Self {
+        Self::new(mapping_slot(base_slot, key), 0, ty)
+    }
+}
+
+/// Reads typed values out of the storage of the contract at `address`.
+pub struct StorageReader<'a> {
+    state: &'a State,
+    address: Address,
+}
+
+impl<'a> StorageReader<'a> {
+    pub fn new(state: &'a State, address: Address) -> Self {
+        Self { state, address }
+    }
+
+    pub fn read(&self, variable: &StorageVariable) -> StorageValue {
+        let word: U256 = self
+            .state
+            .get_storage(&self.address, &variable.slot.into())
+            .into();
+        let bits = variable.ty.size() * 8;
+        let mask = if bits == 256 {
+            U256::MAX
+        } else {
+            (U256::from(1) << bits) - U256::from(1)
+        };
+        let value = (word >> (variable.offset * 8)) & mask;
+
+        match variable.ty {
+            StorageType::Uint(_) => StorageValue::Uint(value),
+            StorageType::Bool => StorageValue::Bool(!value.is_zero()),
+            StorageType::Address => StorageValue::Address(Address::from_word(value.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_packed_and_mapping_values() {
+        let owner = Address::repeat_byte(0xaa);
+        let low = U256::from(7);
+        let high = U256::from(u128::MAX - 1);
+
+        // contract { uint128 low; uint128 high; address owner; bool paused;
+        //            mapping(uint256 => uint256) balances; }
+        let contract = Address::repeat_byte(0xcc);
+        let mut state = State::new();
+        let mut store = |slot: U256, value: U256| {
+            state.set_storage(contract, slot.into(), value.into());
+        };
+        store(U256::ZERO, (high << 128) | low);
+        store(
+            U256::from(1),
+            (U256::from(1) << 160) | U256::from_be_slice(owner.as_slice()),
+        );
+        let key = U256::from(3).to_be_bytes::<32>();
+        store(mapping_slot(U256::from(2), &key), U256::from(1000));
+
+        let reader = StorageReader::new(&state, contract);
+        let read = |slot: u64, offset, ty| {
+            reader.read(&StorageVariable::new(U256::from(slot), offset, ty))
+        };
+        assert_eq!(read(0, 0, StorageType::Uint(128)), StorageValue::Uint(low));
+        assert_eq!(
+            read(0, 16, StorageType::Uint(128)),
+            StorageValue::Uint(high)
+        );
+        assert_eq!(
+            read(1, 0, StorageType::Address),
+            StorageValue::Address(owner)
+        );
+        assert_eq!(read(1, 20, StorageType::Bool), StorageValue::Bool(true));
+        assert_eq!(
+            read(0, 0, StorageType::Uint(256)),
+            StorageValue::Uint((high << 128) | low)
+        );
+
+        let balance = StorageVariable::mapping_value(U256::from(2), &key, StorageType::Uint(256));
+        assert_eq!(reader.read(&balance), StorageValue::Uint(U256::from(1000)));
+        let missing = StorageVariable::mapping_value(
+            U256::from(2),
+            &U256::from(4).to_be_bytes::<32>(),
+            StorageType::Uint(256),
+        );
+        assert_eq!(reader.read(&missing), StorageValue::Uint(U256::ZERO));
+    }
+}
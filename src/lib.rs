@@ -2,5 +2,10 @@ pub mod block;
 pub mod blockchain;
 pub mod config;
 pub mod crypto;
+pub mod mempool;
 pub mod transaction;
 pub mod evm;
+pub mod rpc;
+
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
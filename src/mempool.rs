@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use alloy_primitives::{Address, B256};
+
+use crate::block::account::Account;
+use crate::transaction::errors::TransactionError;
+use crate::transaction::transaction::Transaction;
+
+/// Number of recently accepted transaction hashes remembered to reject replays.
+const SEEN_TRANSACTIONS_CAPACITY: usize = 16_384;
+/// Number of pending transactions, ready or queued, the mempool holds by default.
+pub const DEFAULT_MEMPOOL_CAPACITY: usize = 4096;
+
+/// Transactions waiting to be included in a block.
+///
+/// A transaction is ready once every lower nonce of its sender is either executed or ready
+/// itself. Transactions arriving ahead of a nonce gap are queued until the gap fills.
+///
+/// Once `capacity` transactions are pending, a new one is only accepted if it pays a higher
+/// priority fee than the cheapest pending transaction, which is evicted to make room.
+pub struct Mempool {
+    // ready transactions with their sender, in execution order
+    ready: VecDeque<(Address, Transaction)>,
+    // sender -> nonce -> transaction waiting for a lower nonce
+    queued: HashMap<Address, BTreeMap<u64, Transaction>>,
+    // sender -> nonce of the next transaction to become ready
+    next_nonces: HashMap<Address, u64>,
+    // hashes of the recently accepted transactions still pending or taken for inclusion,
+    // oldest first
+    seen: HashSet<B256>,
+    seen_order: VecDeque<B256>,
+    capacity: usize,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+            queued: HashMap::new(),
+            next_nonces: HashMap::new(),
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+            capacity: DEFAULT_MEMPOOL_CAPACITY,
+        }
+    }
+
+    /// Sets the maximum number of pending transactions.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Adds a transaction whose sender's account currently has `account_nonce`.
+    ///
+    /// Returns the hash of the accepted transaction. A transaction accepted before, whether
+    /// still pending or already taken for inclusion in a block, is rejected as already known,
+    /// independently of the nonce checks. One that was evicted or failed to execute can be
+    /// submitted again.
+    pub fn submit(
+        &mut self,
+        transaction: Transaction,
+        account_nonce: u64,
+    ) -> Result<B256, TransactionError> {
+        let sender = transaction.recover_sender()?;
+        let hash = transaction.hash();
+        if self.seen.contains(&hash) {
+            return Err(TransactionError::AlreadyKnown);
+        }
+
+        let next_nonce = self.next_nonces.entry(sender).or_insert(account_nonce);
+        *next_nonce = (*next_nonce).max(account_nonce);
+        if transaction.nonce < *next_nonce {
+            return Err(TransactionError::NonceTooLow);
+        }
+
+        if self.len() >= self.capacity {
+            let (lowest_sender, lowest_nonce, lowest_fee) = self
+                .lowest_fee_transaction()
+                .ok_or(TransactionError::MempoolFull)?;
+            if transaction.max_priority_fee_per_gas <= lowest_fee {
+                return Err(TransactionError::MempoolFull);
+            }
+            self.evict(lowest_sender, lowest_nonce);
+        }
+
+        // the eviction may have reopened a nonce gap of the sender
+        let next_nonce = self.next_nonces.get_mut(&sender).unwrap();
+        if transaction.nonce > *next_nonce {
+            self.queued
+                .entry(sender)
+                .or_default()
+                .insert(transaction.nonce, transaction);
+            self.remember(hash);
+            return Ok(hash);
+        }
+
+        self.ready.push_back((sender, transaction));
+        *next_nonce += 1;
+
+        // promote the queued transactions that no longer wait for a gap
+        if let Some(queued) = self.queued.get_mut(&sender) {
+            while let Some(transaction) = queued.remove(next_nonce) {
+                self.ready.push_back((sender, transaction));
+                *next_nonce += 1;
+            }
+            if queued.is_empty() {
+                self.queued.remove(&sender);
+            }
+        }
+        self.remember(hash);
+        Ok(hash)
+    }
+
+    /// Sender, nonce and priority fee of the pending transaction paying the lowest priority
+    /// fee. Among equal fees, the one with the highest nonce is picked, as the fewest
+    /// transactions depend on it.
+    fn lowest_fee_transaction(&self) -> Option<(Address, u64, u64)> {
+        let queued = self.queued.iter().flat_map(|(sender, queued)| {
+            queued
+                .values()
+                .map(move |transaction| (*sender, transaction))
+        });
+        self.ready
+            .iter()
+            .map(|(sender, transaction)| (*sender, transaction))
+            .chain(queued)
+            .map(|(sender, transaction)| {
+                (
+                    sender,
+                    transaction.nonce,
+                    transaction.max_priority_fee_per_gas,
+                )
+            })
+            .min_by_key(|(_, nonce, fee)| (*fee, std::cmp::Reverse(*nonce)))
+    }
+
+    /// Removes the pending transaction of `sender` at `nonce`. Its ready successors go back to
+    /// waiting for the nonce to be filled again.
+    fn evict(&mut self, sender: Address, nonce: u64) {
+        if let Some(queued) = self.queued.get_mut(&sender) {
+            if let Some(transaction) = queued.remove(&nonce) {
+                if queued.is_empty() {
+                    self.queued.remove(&sender);
+                }
+                self.forget(transaction.hash());
+                return;
+            }
+        }
+
+        let (evicted, ready): (VecDeque<_>, VecDeque<_>) = std::mem::take(&mut self.ready)
+            .into_iter()
+            .partition(|(from, transaction)| *from == sender && transaction.nonce >= nonce);
+        self.ready = ready;
+        let queued = self.queued.entry(sender).or_default();
+        let mut removed = None;
+        for (_, transaction) in evicted {
+            if transaction.nonce == nonce {
+                removed = Some(transaction.hash());
+            } else {
+                queued.insert(transaction.nonce, transaction);
+            }
+        }
+        if queued.is_empty() {
+            self.queued.remove(&sender);
+        }
+        self.next_nonces.insert(sender, nonce);
+        if let Some(hash) = removed {
+            self.forget(hash);
+        }
+    }
+
+    /// Forgets a transaction that left the mempool without being included in a block, so it
+    /// can be submitted again.
+    pub fn forget(&mut self, hash: B256) {
+        if self.seen.remove(&hash) {
+            self.seen_order.retain(|seen| *seen != hash);
+        }
+    }
+
+    /// Resets the next nonce of `sender` to `account_nonce` after one of its transactions was
+    /// taken but failed to execute. Its ready successors go back to waiting for the nonce to be
+    /// filled again, so the sender can resubmit it.
+    pub fn reset_nonce(&mut self, sender: Address, account_nonce: u64) {
+        self.evict(sender, account_nonce);
+    }
+
+    fn remember(&mut self, hash: B256) {
+        if self.seen_order.len() == SEEN_TRANSACTIONS_CAPACITY {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(hash);
+        self.seen_order.push_back(hash);
+    }
+
+    /// Adds many transactions at once, `account` gives the current account of a sender.
+    ///
+    /// Each transaction is first validated against its sender's account and `base_fee`, as in
+    /// `Transaction::validate_pending`. Returns the outcome of each transaction in the order of
+    /// the batch. Transactions are submitted by ascending nonce, so those of a sender become
+    /// ready in order whatever their position in the batch, and a rejected transaction does
+    /// not affect the others.
+    pub fn submit_batch(
+        &mut self,
+        transactions: Vec<Transaction>,
+        base_fee: u64,
+        account: impl Fn(&Address) -> Option<Account>,
+    ) -> Vec<Result<B256, TransactionError>> {
+        let mut transactions: Vec<(usize, Transaction)> =
+            transactions.into_iter().enumerate().collect();
+        transactions.sort_by_key(|(_, transaction)| transaction.nonce);
+
+        let mut results: Vec<(usize, Result<B256, TransactionError>)> = transactions
+            .into_iter()
+            .map(|(index, transaction)| {
+                let result = transaction.recover_sender().and_then(|sender| {
+                    let account =
+                        account(&sender).ok_or(TransactionError::SenderAccountDoesNotExist)?;
+                    transaction.validate_pending(&account, base_fee)?;
+                    self.submit(transaction, account.nonce)
+                });
+                (index, result)
+            })
+            .collect();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Returns the next transaction that is ready to be executed without taking it.
+    pub fn peek_ready(&self) -> Option<&Transaction> {
+        self.ready.front().map(|(_, transaction)| transaction)
+    }
+
+    /// Takes the next transaction that is ready to be executed.
+    pub fn pop_ready(&mut self) -> Option<Transaction> {
+        self.ready.pop_front().map(|(_, transaction)| transaction)
+    }
+
+    /// Number of transactions ready to be executed.
+    pub fn ready_len(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Number of transactions waiting for a nonce gap to fill.
+    pub fn queued_len(&self) -> usize {
+        self.queued.values().map(BTreeMap::len).sum()
+    }
+
+    /// Number of pending transactions, ready or queued.
+    pub fn len(&self) -> usize {
+        self.ready_len() + self.queued_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::EMPTY_CODE_HASH;
+    use crate::crypto::wallet::Wallet;
+    use crate::transaction::builder::TransactionBuilder;
+    use crate::transaction::transaction::{ETH_TO_WEI, TRANSACTION_GAS_COST};
+
+    #[test]
+    fn test_submit_batch() {
+        let sender = Wallet::generate();
+        let other = Wallet::generate();
+        let transaction = |wallet: &Wallet, nonce| {
+            TransactionBuilder::new()
+                .nonce(nonce)
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_fee_per_gas(20)
+                .build_signed(&wallet.private_key)
+                .unwrap()
+        };
+
+        let batch = vec![
+            transaction(&sender, 2),
+            transaction(&other, 0),
+            transaction(&sender, 0), // already executed
+            transaction(&sender, 1),
+        ];
+        let hashes: Vec<B256> = batch.iter().map(Transaction::hash).collect();
+
+        let mut mempool = Mempool::new();
+        let results = mempool.submit_batch(batch, 0, |address| {
+            let mut account = Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO);
+            if *address == sender.address {
+                account.nonce = 1;
+            }
+            Some(account)
+        });
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), &hashes[0]);
+        assert_eq!(results[1].as_ref().unwrap(), &hashes[1]);
+        assert!(matches!(results[2], Err(TransactionError::NonceTooLow)));
+        assert_eq!(results[3].as_ref().unwrap(), &hashes[3]);
+
+        assert_eq!(mempool.queued_len(), 0);
+        let ready: Vec<B256> = std::iter::from_fn(|| mempool.pop_ready())
+            .map(|transaction| transaction.hash())
+            .collect();
+        assert_eq!(ready, vec![hashes[1], hashes[3], hashes[0]]);
+    }
+
+    #[test]
+    fn test_replayed_transaction_is_rejected() {
+        let sender = Wallet::generate();
+        let transaction = TransactionBuilder::new()
+            .nonce(0)
+            .to(Wallet::generate().address)
+            .gas_limit(TRANSACTION_GAS_COST)
+            .max_fee_per_gas(20)
+            .build_signed(&sender.private_key)
+            .unwrap();
+
+        let mut mempool = Mempool::new();
+        mempool.submit(transaction.clone(), 0).unwrap();
+        assert!(matches!(
+            mempool.submit(transaction.clone(), 0),
+            Err(TransactionError::AlreadyKnown)
+        ));
+
+        // once taken for inclusion, a replay is still recognized before the nonce checks
+        mempool.pop_ready().unwrap();
+        assert!(matches!(
+            mempool.submit(transaction, 1),
+            Err(TransactionError::AlreadyKnown)
+        ));
+        assert_eq!(mempool.ready_len(), 0);
+    }
+
+    #[test]
+    fn test_evicted_transaction_can_be_resubmitted() {
+        let transaction = |priority_fee| {
+            TransactionBuilder::new()
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_priority_fee_per_gas(priority_fee)
+                .max_fee_per_gas(20)
+                .build_signed(&Wallet::generate().private_key)
+                .unwrap()
+        };
+        let cheap = transaction(1);
+
+        let mut mempool = Mempool::new().with_capacity(1);
+        mempool.submit(cheap.clone(), 0).unwrap();
+        mempool.submit(transaction(2), 0).unwrap();
+        assert_eq!(mempool.len(), 1);
+
+        mempool.pop_ready().unwrap();
+        assert_eq!(mempool.submit(cheap.clone(), 0).unwrap(), cheap.hash());
+    }
+
+    #[test]
+    fn test_full_mempool_evicts_lowest_fee() {
+        let transaction = |wallet: &Wallet, nonce, priority_fee| {
+            TransactionBuilder::new()
+                .nonce(nonce)
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_priority_fee_per_gas(priority_fee)
+                .max_fee_per_gas(100)
+                .build_signed(&wallet.private_key)
+                .unwrap()
+        };
+        let senders: Vec<Wallet> = (0..3).map(|_| Wallet::generate()).collect();
+
+        let mut mempool = Mempool::new().with_capacity(3);
+        let cheapest = transaction(&senders[0], 0, 1);
+        mempool.submit(cheapest.clone(), 0).unwrap();
+        mempool.submit(transaction(&senders[1], 0, 2), 0).unwrap();
+        mempool.submit(transaction(&senders[2], 0, 2), 0).unwrap();
+        assert_eq!(mempool.len(), 3);
+
+        // a transaction that doesn't outbid the cheapest one is turned away
+        let rejected = transaction(&Wallet::generate(), 0, 1);
+        assert!(matches!(
+            mempool.submit(rejected, 0),
+            Err(TransactionError::MempoolFull)
+        ));
+
+        let expensive = transaction(&Wallet::generate(), 0, 10);
+        mempool.submit(expensive.clone(), 0).unwrap();
+        assert_eq!(mempool.len(), 3);
+        let pending: Vec<B256> = std::iter::from_fn(|| mempool.pop_ready())
+            .map(|transaction| transaction.hash())
+            .collect();
+        assert!(!pending.contains(&cheapest.hash()));
+        assert!(pending.contains(&expensive.hash()));
+    }
+
+    #[test]
+    fn test_eviction_requeues_successors() {
+        let sender = Wallet::generate();
+        let transaction = |nonce, priority_fee| {
+            TransactionBuilder::new()
+                .nonce(nonce)
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_priority_fee_per_gas(priority_fee)
+                .max_fee_per_gas(100)
+                .build_signed(&sender.private_key)
+                .unwrap()
+        };
+
+        let mut mempool = Mempool::new().with_capacity(2);
+        mempool.submit(transaction(0, 1), 0).unwrap();
+        mempool.submit(transaction(1, 5), 0).unwrap();
+
+        // evicting the first nonce leaves a gap the second one waits behind
+        mempool
+            .submit(transaction(5, 3), 0)
+            .expect("outbids the cheapest transaction");
+        assert_eq!(mempool.ready_len(), 0);
+        assert_eq!(mempool.queued_len(), 2);
+
+        // filling the gap again evicts the cheapest queued transaction and promotes the rest
+        mempool.submit(transaction(0, 10), 0).unwrap();
+        assert_eq!(mempool.ready_len(), 2);
+        assert_eq!(mempool.queued_len(), 0);
+        assert_eq!(mempool.pop_ready().unwrap().nonce, 0);
+        assert_eq!(mempool.pop_ready().unwrap().nonce, 1);
+    }
+
+    #[test]
+    fn test_out_of_order_nonces_are_queued() {
+        let sender = Wallet::generate();
+        let transaction = |nonce| {
+            TransactionBuilder::new()
+                .nonce(nonce)
+                .to(Wallet::generate().address)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_fee_per_gas(20)
+                .build_signed(&sender.private_key)
+                .unwrap()
+        };
+
+        let mut mempool = Mempool::new();
+        mempool.submit(transaction(5), 4).unwrap();
+        assert_eq!(mempool.ready_len(), 0);
+        assert_eq!(mempool.queued_len(), 1);
+
+        mempool.submit(transaction(4), 4).unwrap();
+        assert_eq!(mempool.queued_len(), 0);
+        assert_eq!(mempool.pop_ready().unwrap().nonce, 4);
+        assert_eq!(mempool.pop_ready().unwrap().nonce, 5);
+        assert!(mempool.pop_ready().is_none());
+
+        assert!(matches!(
+            mempool.submit(transaction(3), 4),
+            Err(TransactionError::NonceTooLow)
+        ));
+    }
+}
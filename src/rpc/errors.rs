@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+use crate::transaction::errors::TransactionError;
+
+#[derive(Error, Debug)]
+pub enum RpcError {
+    #[error("method not found: {0}")]
+    MethodNotFound(String),
+    #[error("invalid params: {0}")]
+    InvalidParams(String),
+    #[error("transaction rejected: {0}")]
+    TransactionRejected(#[from] TransactionError),
+}
+
+impl RpcError {
+    /// Error code as defined by the JSON-RPC 2.0 specification.
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            // EIP-1474
+            RpcError::TransactionRejected(_) => -32003,
+        }
+    }
+}
@@ -0,0 +1,509 @@
+// JSON-RPC 2.0 request handling on top of the node. Transport is left to the caller:
+// requests are dispatched as already-parsed JSON values.
+
+use std::str::FromStr;
+
+use alloy_primitives::{hex, Address, B256};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::block::block::Block;
+use crate::blockchain::App;
+use crate::rpc::errors::RpcError;
+use crate::transaction::receipt::Receipt;
+use crate::transaction::transaction::Transaction;
+
+#[derive(Deserialize, Debug)]
+pub struct Request {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Vec<Value>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ErrorObject {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorObject>,
+}
+
+pub fn handle(app: &mut App, request: Request) -> Response {
+    let (result, error) = match dispatch(app, &request.method, &request.params) {
+        Ok(result) => (Some(result), None),
+        Err(err) => (
+            None,
+            Some(ErrorObject {
+                code: err.code(),
+                message: err.to_string(),
+            }),
+        ),
+    };
+
+    Response {
+        jsonrpc: request.jsonrpc,
+        id: request.id,
+        result,
+        error,
+    }
+}
+
+fn dispatch(app: &mut App, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+    match method {
+        "eth_getTransactionByHash" => {
+            let hash = hash_param(params, 0)?;
+            Ok(app
+                .get_transaction(hash)
+                .map_or(Value::Null, |(height, transaction, _)| {
+                    transaction_to_json(transaction, height)
+                }))
+        }
+        "eth_getTransactionReceipt" => {
+            let hash = hash_param(params, 0)?;
+            Ok(app
+                .get_transaction(hash)
+                .map_or(Value::Null, |(height, transaction, receipt)| {
+                    receipt_to_json(transaction, receipt, height)
+                }))
+        }
+        "eth_getBlockByNumber" => {
+            let block = match block_number_param(params, 0)? {
+                Some(number) => app.get_block_by_number(number),
+                None => app.head(),
+            };
+            let full = bool_param(params, 1)?;
+            Ok(block.map_or(Value::Null, |block| block_to_json(block, full)))
+        }
+        "eth_getBlockByHash" => {
+            let hash = hash_param(params, 0)?;
+            let full = bool_param(params, 1)?;
+            Ok(app
+                .get_block_by_hash(hash)
+                .map_or(Value::Null, |block| block_to_json(block, full)))
+        }
+        "eth_sendRawTransaction" => {
+            let bytes = bytes_param(params, 0)?;
+            let hash = app.send_raw_transaction(&bytes)?;
+            Ok(json!(hash))
+        }
+        "eth_getBalance" => {
+            let address = address_param(params, 0)?;
+            let state = app.state();
+            let balance = state
+                .lock()
+                .unwrap()
+                .accounts
+                .get(&address)
+                .map_or(0, |account| account.balance);
+            Ok(quantity(balance))
+        }
+        #[cfg(feature = "devnet")]
+        "devnet_setBalance" => {
+            let address = address_param(params, 0)?;
+            let balance = quantity_param(params, 1)?;
+            let state = app.state();
+            state
+                .lock()
+                .unwrap()
+                .accounts
+                .entry(address)
+                .or_default()
+                .balance = balance;
+            Ok(Value::Bool(true))
+        }
+        #[cfg(feature = "devnet")]
+        "devnet_mine" => {
+            use crate::blockchain::Blockchain;
+
+            app.produce_block();
+            Ok(quantity(app.height() - 1))
+        }
+        _ => Err(RpcError::MethodNotFound(method.to_string())),
+    }
+}
+
+fn hash_param(params: &[Value], index: usize) -> Result<B256, RpcError> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .and_then(|hash| B256::from_str(hash).ok())
+        .ok_or(RpcError::InvalidParams(format!(
+            "expected a 32 byte hash at position {}",
+            index
+        )))
+}
+
+fn address_param(params: &[Value], index: usize) -> Result<Address, RpcError> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .and_then(|address| Address::from_str(address).ok())
+        .ok_or(RpcError::InvalidParams(format!(
+            "expected a 20 byte address at position {}",
+            index
+        )))
+}
+
+fn bytes_param(params: &[Value], index: usize) -> Result<Vec<u8>, RpcError> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .and_then(|data| hex::decode(data).ok())
+        .ok_or(RpcError::InvalidParams(format!(
+            "expected hex data at position {}",
+            index
+        )))
+}
+
+#[cfg(feature = "devnet")]
+fn quantity_param(params: &[Value], index: usize) -> Result<u64, RpcError> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .and_then(|quantity| quantity.strip_prefix("0x"))
+        .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+        .ok_or(RpcError::InvalidParams(format!(
+            "expected a hex quantity at position {}",
+            index
+        )))
+}
+
+/// Parses a block number given as a hex quantity, `earliest` or `latest`. `None` stands for the
+/// latest block.
+fn block_number_param(params: &[Value], index: usize) -> Result<Option<u64>, RpcError> {
+    let invalid = || {
+        RpcError::InvalidParams(format!(
+            "expected a block number or tag at position {}",
+            index
+        ))
+    };
+    match params
+        .get(index)
+        .and_then(Value::as_str)
+        .ok_or_else(invalid)?
+    {
+        "latest" => Ok(None),
+        "earliest" => Ok(Some(0)),
+        number => number
+            .strip_prefix("0x")
+            .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+            .map(Some)
+            .ok_or_else(invalid),
+    }
+}
+
+/// Parses an optional boolean flag, absent flags are false.
+fn bool_param(params: &[Value], index: usize) -> Result<bool, RpcError> {
+    match params.get(index) {
+        None => Ok(false),
+        Some(value) => value.as_bool().ok_or(RpcError::InvalidParams(format!(
+            "expected a boolean at position {}",
+            index
+        ))),
+    }
+}
+
+fn quantity(value: u64) -> Value {
+    Value::String(format!("{:#x}", value))
+}
+
+fn transaction_to_json(transaction: &Transaction, height: u64) -> Value {
+    json!({
+        "type": "0x2",
+        "hash": transaction.hash(),
+        "blockNumber": quantity(height),
+        "chainId": quantity(transaction.chain_id),
+        "nonce": quantity(transaction.nonce),
+        "from": transaction.get_sender_address(),
+        "to": transaction.to,
+        "value": quantity(transaction.value),
+        "gas": quantity(transaction.gas_limit),
+        "maxPriorityFeePerGas": quantity(transaction.max_priority_fee_per_gas),
+        "maxFeePerGas": quantity(transaction.max_fee_per_gas),
+        "input": hex::encode_prefixed(&transaction.input_data),
+    })
+}
+
+/// Renders the header fields of a block, with its transactions either as hashes or, when `full`
+/// is set, as transaction objects.
+fn block_to_json(block: &Block, full: bool) -> Value {
+    let transactions: Vec<Value> = block
+        .transactions()
+        .iter()
+        .map(|transaction| {
+            if full {
+                transaction_to_json(transaction, block.slot())
+            } else {
+                json!(transaction.hash())
+            }
+        })
+        .collect();
+
+    json!({
+        "number": quantity(block.slot()),
+        "hash": block.hash(),
+        "parentHash": block.parent_root(),
+        "stateRoot": block.state_root(),
+        "miner": block.fee_recipient(),
+        "gasLimit": quantity(block.gas_limit()),
+        "gasUsed": quantity(block.gas_used()),
+        "baseFeePerGas": quantity(block.base_fee_per_gas()),
+        "transactions": transactions,
+    })
+}
+
+fn receipt_to_json(transaction: &Transaction, receipt: &Receipt, height: u64) -> Value {
+    json!({
+        "transactionHash": transaction.hash(),
+        "blockNumber": quantity(height),
+        "from": transaction.get_sender_address(),
+        "to": transaction.to,
+        "status": quantity(receipt.success as u64),
+        "gasUsed": quantity(receipt.gas_used),
+        "effectiveGasPrice": quantity(receipt.effective_gas_price),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::{Account, EMPTY_CODE_HASH};
+    use crate::blockchain::Blockchain;
+    use crate::crypto::wallet::Wallet;
+    use crate::transaction::transaction::{ETH_TO_WEI, GWEI_TO_WEI, TRANSACTION_GAS_COST};
+
+    fn request(method: &str, params: Vec<Value>) -> Request {
+        Request {
+            jsonrpc: "2.0".to_string(),
+            id: json!(1),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    #[test]
+    fn test_get_transaction_and_receipt() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate();
+
+        app.state().lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = Transaction::new(
+            receiver.address,
+            100,
+            TRANSACTION_GAS_COST,
+            GWEI_TO_WEI,
+            2 * GWEI_TO_WEI,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let hash = tx.hash();
+        app.transaction_sender().send(tx).unwrap();
+        app.produce_block();
+
+        let response = handle(
+            &mut app,
+            request("eth_getTransactionByHash", vec![json!(hash)]),
+        );
+        let transaction = response.result.unwrap();
+        assert_eq!(transaction["hash"], json!(hash));
+        assert_eq!(transaction["from"], json!(sender.address));
+        assert_eq!(transaction["value"], json!("0x64"));
+
+        let response = handle(
+            &mut app,
+            request("eth_getTransactionReceipt", vec![json!(hash)]),
+        );
+        let receipt = response.result.unwrap();
+        assert_eq!(receipt["status"], json!("0x1"));
+        assert_eq!(receipt["gasUsed"], json!("0x5208"));
+        assert_eq!(receipt["blockNumber"], json!("0x0"));
+
+        let response = handle(
+            &mut app,
+            request("eth_getTransactionReceipt", vec![json!(B256::ZERO)]),
+        );
+        assert_eq!(response.result, Some(Value::Null));
+    }
+
+    #[test]
+    fn test_get_block_by_number_and_hash() {
+        let mut app = App::new();
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate();
+
+        app.state().lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        app.produce_block();
+        let tx = Transaction::new(
+            receiver.address,
+            100,
+            TRANSACTION_GAS_COST,
+            GWEI_TO_WEI,
+            2 * GWEI_TO_WEI,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let tx_hash = tx.hash();
+        app.transaction_sender().send(tx).unwrap();
+        app.produce_block();
+
+        let first = app.get_block_by_number(0).unwrap().hash();
+        let second = app.get_block_by_number(1).unwrap().hash();
+
+        for (number, hash, transaction_count) in [("0x0", first, 0), ("0x1", second, 1)] {
+            let by_number = handle(
+                &mut app,
+                request("eth_getBlockByNumber", vec![json!(number), json!(false)]),
+            )
+            .result
+            .unwrap();
+            let by_hash = handle(
+                &mut app,
+                request("eth_getBlockByHash", vec![json!(hash), json!(false)]),
+            )
+            .result
+            .unwrap();
+
+            assert_eq!(by_number, by_hash);
+            assert_eq!(by_number["number"], json!(number));
+            assert_eq!(by_number["hash"], json!(hash));
+            assert_eq!(
+                by_number["transactions"].as_array().unwrap().len(),
+                transaction_count
+            );
+        }
+
+        let latest = handle(
+            &mut app,
+            request("eth_getBlockByNumber", vec![json!("latest"), json!(true)]),
+        )
+        .result
+        .unwrap();
+        assert_eq!(latest["parentHash"], json!(first));
+        assert_eq!(latest["gasUsed"], json!("0x5208"));
+        assert_eq!(latest["transactions"][0]["hash"], json!(tx_hash));
+        assert_eq!(latest["transactions"][0]["blockNumber"], json!("0x1"));
+
+        let response = handle(
+            &mut app,
+            request("eth_getBlockByNumber", vec![json!("0x2")]),
+        );
+        assert_eq!(response.result, Some(Value::Null));
+        let response = handle(
+            &mut app,
+            request("eth_getBlockByHash", vec![json!(B256::ZERO)]),
+        );
+        assert_eq!(response.result, Some(Value::Null));
+        let response = handle(&mut app, request("eth_getBlockByNumber", vec![json!(1)]));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[cfg(feature = "devnet")]
+    #[test]
+    fn test_devnet_set_balance_and_mine() {
+        let mut app = App::new();
+        let address = Wallet::generate().address;
+
+        let response = handle(
+            &mut app,
+            request("eth_getBalance", vec![json!(address), json!("latest")]),
+        );
+        assert_eq!(response.result, Some(json!("0x0")));
+
+        let response = handle(
+            &mut app,
+            request(
+                "devnet_setBalance",
+                vec![json!(address), json!("0xde0b6b3a7640000")],
+            ),
+        );
+        assert_eq!(response.result, Some(json!(true)));
+
+        let response = handle(
+            &mut app,
+            request("eth_getBalance", vec![json!(address), json!("latest")]),
+        );
+        assert_eq!(response.result, Some(quantity(ETH_TO_WEI)));
+
+        let response = handle(&mut app, request("devnet_mine", vec![]));
+        assert_eq!(response.result, Some(json!("0x0")));
+        assert_eq!(app.height(), 1);
+
+        let response = handle(
+            &mut app,
+            request("devnet_setBalance", vec![json!(address), json!(1)]),
+        );
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_send_raw_transaction_auto_mine() {
+        let mut app = App::new().with_auto_mine(true);
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate();
+        app.state().lock().unwrap().set_account(
+            sender.address,
+            Account::new(ETH_TO_WEI, EMPTY_CODE_HASH, B256::ZERO),
+        );
+
+        let tx = Transaction::new(
+            receiver.address,
+            100,
+            TRANSACTION_GAS_COST,
+            GWEI_TO_WEI,
+            2 * GWEI_TO_WEI,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let response = handle(
+            &mut app,
+            request(
+                "eth_sendRawTransaction",
+                vec![json!(hex::encode_prefixed(tx.to_rlp()))],
+            ),
+        );
+        assert_eq!(response.result, Some(json!(tx.hash())));
+
+        // mined by the call itself
+        assert_eq!(app.height(), 1);
+        let response = handle(
+            &mut app,
+            request(
+                "eth_getBalance",
+                vec![json!(receiver.address), json!("latest")],
+            ),
+        );
+        assert_eq!(response.result, Some(json!("0x64")));
+
+        let response = handle(
+            &mut app,
+            request("eth_sendRawTransaction", vec![json!("0x02c1")]),
+        );
+        assert_eq!(response.error.unwrap().code, -32003);
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        let mut app = App::new();
+        let response = handle(&mut app, request("eth_unknown", vec![]));
+        assert_eq!(response.error.unwrap().code, -32601);
+    }
+}
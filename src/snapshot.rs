@@ -0,0 +1,189 @@
+// Canonical snapshots of a post-execution world, used to diff this VM against a reference
+// implementation byte-for-byte. All maps are ordered and all binary values are lowercase hex,
+// so two equal states always serialize to identical JSON.
+
+use std::collections::BTreeMap;
+
+use alloy_primitives::hex;
+use serde::Serialize;
+
+use crate::block::account::Account;
+use crate::block::state::State;
+use crate::evm::evm::{Contract, ExecutionResult};
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AccountSnapshot {
+    pub nonce: u64,
+    pub balance: u64,
+    pub code_hash: String,
+    pub storage_root: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ContractSnapshot {
+    pub code: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct StateSnapshot {
+    pub accounts: BTreeMap<String, AccountSnapshot>,
+    pub storage: BTreeMap<String, BTreeMap<String, String>>,
+    pub contracts: BTreeMap<String, ContractSnapshot>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ResultSnapshot {
+    pub status: &'static str,
+    pub gas_used: u64,
+    pub return_data: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Snapshot {
+    pub result: ResultSnapshot,
+    pub state: StateSnapshot,
+}
+
+impl From<&Account> for AccountSnapshot {
+    fn from(account: &Account) -> Self {
+        Self {
+            nonce: account.nonce,
+            balance: account.balance,
+            code_hash: hex::encode_prefixed(account.code_hash),
+            storage_root: hex::encode_prefixed(account.storage_root),
+        }
+    }
+}
+
+impl From<&Contract> for ContractSnapshot {
+    fn from(contract: &Contract) -> Self {
+        Self {
+            code: hex::encode_prefixed(&contract.code),
+        }
+    }
+}
+
+impl From<&State> for StateSnapshot {
+    fn from(state: &State) -> Self {
+        let mut storage: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for ((address, key), value) in &state.storage {
+            storage
+                .entry(hex::encode_prefixed(address))
+                .or_default()
+                .insert(hex::encode_prefixed(key), hex::encode_prefixed(value));
+        }
+
+        Self {
+            accounts: state
+                .accounts
+                .iter()
+                .map(|(address, account)| (hex::encode_prefixed(address), account.into()))
+                .collect(),
+            storage,
+            contracts: state
+                .contract
+                .iter()
+                .map(|(address, contract)| (hex::encode_prefixed(address), contract.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<&ExecutionResult> for ResultSnapshot {
+    fn from(result: &ExecutionResult) -> Self {
+        match result {
+            ExecutionResult::Success {
+                return_data,
+                gas_used,
+                ..
+            } => Self {
+                status: "success",
+                gas_used: *gas_used,
+                return_data: hex::encode_prefixed(return_data.as_deref().unwrap_or_default()),
+            },
+            ExecutionResult::Revert { reason, gas_used } => Self {
+                status: "revert",
+                gas_used: *gas_used,
+                return_data: hex::encode_prefixed(reason),
+            },
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn capture(result: &ExecutionResult, state: &State) -> Self {
+        Self {
+            result: result.into(),
+            state: state.into(),
+        }
+    }
+
+    /// Serializes the snapshot into its canonical JSON form.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("snapshot serialization cannot fail")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::account::EMPTY_CODE_HASH;
+    use crate::crypto::wallet::Wallet;
+    use crate::evm::evm::BlockEnv;
+    use crate::evm::executor::Executor;
+    use crate::evm::operation::Operation;
+    use crate::transaction::transaction::Transaction;
+    use alloy_primitives::{Address, B256, U256};
+    use std::sync::{Arc, Mutex};
+
+    fn run(sender: &Wallet, contract_address: Address) -> String {
+        let code = vec![
+            Operation::Push1(U256::from(1)).opcode(), // Value
+            1,
+            Operation::Push1(U256::from(0)).opcode(), // Key
+            0,
+            Operation::SStore.opcode(),
+            Operation::Push1(U256::from(2)).opcode(), // Value
+            2,
+            Operation::Push1(U256::from(1)).opcode(), // Key
+            1,
+            Operation::SStore.opcode(),
+        ];
+
+        let mut state = State::new();
+        state.set_account(
+            sender.address,
+            Account::new(1000, EMPTY_CODE_HASH, B256::ZERO),
+        );
+        state.contract.insert(contract_address, Contract::new(code));
+        let state = Arc::new(Mutex::new(state));
+
+        let tx = Transaction::new(
+            contract_address,
+            0,
+            100_000,
+            100,
+            100,
+            vec![],
+            Some(&sender.private_key),
+        );
+        let result =
+            Executor::process_transaction_contract(tx, &BlockEnv::default(), state.clone())
+                .unwrap();
+
+        let state = state.lock().unwrap();
+        Snapshot::capture(&result, &state).to_json()
+    }
+
+    #[test]
+    fn test_snapshot_is_deterministic() {
+        let sender = Wallet::generate();
+        let contract_address = Wallet::generate().address;
+
+        let first = run(&sender, contract_address);
+        let second = run(&sender, contract_address);
+
+        assert_eq!(first, second);
+        assert!(first.contains("\"status\":\"success\""));
+    }
+}
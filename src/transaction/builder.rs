@@ -0,0 +1,143 @@
+use alloy_primitives::Address;
+use k256::ecdsa::SigningKey;
+
+use crate::transaction::errors::TransactionError;
+use crate::transaction::transaction::{AccessList, Transaction};
+
+/// Builds a `Transaction` field by field.
+///
+/// `gas_limit` and `max_fee_per_gas` are required; leaving `to` unset creates a contract.
+#[derive(Default)]
+pub struct TransactionBuilder {
+    chain_id: u64,
+    nonce: u64,
+    to: Address,
+    value: u64,
+    gas_limit: Option<u64>,
+    max_priority_fee_per_gas: u64,
+    max_fee_per_gas: Option<u64>,
+    input_data: Vec<u8>,
+    access_list: AccessList,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn to(mut self, to: Address) -> Self {
+        self.to = to;
+        self
+    }
+
+    pub fn value(mut self, value: u64) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = Some(gas_limit);
+        self
+    }
+
+    pub fn max_priority_fee_per_gas(mut self, max_priority_fee_per_gas: u64) -> Self {
+        self.max_priority_fee_per_gas = max_priority_fee_per_gas;
+        self
+    }
+
+    pub fn max_fee_per_gas(mut self, max_fee_per_gas: u64) -> Self {
+        self.max_fee_per_gas = Some(max_fee_per_gas);
+        self
+    }
+
+    pub fn input_data(mut self, input_data: Vec<u8>) -> Self {
+        self.input_data = input_data;
+        self
+    }
+
+    pub fn access_list(mut self, access_list: AccessList) -> Self {
+        self.access_list = access_list;
+        self
+    }
+
+    /// Builds the unsigned transaction.
+    pub fn build(self) -> Result<Transaction, TransactionError> {
+        let gas_limit = self
+            .gas_limit
+            .ok_or(TransactionError::MissingField("gas_limit"))?;
+        let max_fee_per_gas = self
+            .max_fee_per_gas
+            .ok_or(TransactionError::MissingField("max_fee_per_gas"))?;
+        if self.max_priority_fee_per_gas > max_fee_per_gas {
+            return Err(TransactionError::PriorityFeeExceedsMaxFee);
+        }
+
+        let mut transaction = Transaction::new(
+            self.to,
+            self.value,
+            gas_limit,
+            self.max_priority_fee_per_gas,
+            max_fee_per_gas,
+            self.input_data,
+            None,
+        );
+        transaction.chain_id = self.chain_id;
+        transaction.nonce = self.nonce;
+        transaction.access_list = self.access_list;
+        Ok(transaction)
+    }
+
+    /// Builds the transaction and signs it with `private_key`.
+    pub fn build_signed(self, private_key: &SigningKey) -> Result<Transaction, TransactionError> {
+        let mut transaction = self.build()?;
+        transaction.sign(private_key);
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::wallet::Wallet;
+    use crate::transaction::transaction::TRANSACTION_GAS_COST;
+
+    #[test]
+    fn test_build_signed() {
+        let sender = Wallet::generate();
+        let receiver = Wallet::generate().address;
+
+        let tx = TransactionBuilder::new()
+            .chain_id(1)
+            .nonce(7)
+            .to(receiver)
+            .value(100)
+            .gas_limit(TRANSACTION_GAS_COST)
+            .max_priority_fee_per_gas(2)
+            .max_fee_per_gas(20)
+            .build_signed(&sender.private_key)
+            .unwrap();
+
+        assert_eq!(tx.chain_id, 1);
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.to, receiver);
+        assert_eq!(tx.max_priority_fee_per_gas, 2);
+        assert_eq!(tx.max_fee_per_gas, 20);
+        assert!(tx.verify_signature());
+        assert_eq!(tx.get_sender_address(), Some(sender.address));
+
+        assert!(matches!(
+            TransactionBuilder::new().max_fee_per_gas(20).build(),
+            Err(TransactionError::MissingField("gas_limit"))
+        ));
+    }
+}
@@ -10,8 +10,28 @@ pub enum TransactionError {
     InsufficientGas,
     #[error("maximum gas fee below base fee")]
     MaximumGasFeeBelowBaseFee,
+    #[error("maximum priority fee exceeds maximum gas fee")]
+    PriorityFeeExceedsMaxFee,
+    #[error("nonce too low")]
+    NonceTooLow,
+    #[error("nonce too high")]
+    NonceTooHigh,
+    #[error("nonce has max value")]
+    NonceMax,
+    #[error("transaction already known")]
+    AlreadyKnown,
+    #[error("mempool is full and the transaction doesn't outbid any pending one")]
+    MempoolFull,
     #[error("invalid transaction signature")]
     InvalidSignature,
+    #[error("missing transaction field: {0}")]
+    MissingField(&'static str),
+    #[error("invalid amount: {0:?}")]
+    InvalidAmount(String),
+    #[error("invalid transaction JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("invalid transaction RLP: {0}")]
+    InvalidRlp(#[from] alloy_rlp::Error),
     #[error("invalid transaction")]
     InvalidTransaction,
-}
\ No newline at end of file
+}
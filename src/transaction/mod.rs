@@ -1,2 +1,6 @@
+pub mod builder;
 pub mod errors;
+pub mod receipt;
+#[allow(clippy::module_inception)]
 pub mod transaction;
+pub mod units;
@@ -0,0 +1,23 @@
+/// Outcome of a transaction that was included in a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    pub success: bool,
+    pub gas_used: u64,
+    // EIP-1559
+    // the price per unit of gas actually paid, i.e. base fee + priority fee
+    pub effective_gas_price: u64,
+    // the portion of the effective gas price paid to the block's fee recipient
+    pub priority_fee_per_gas: u64,
+}
+
+impl Receipt {
+    /// Tip paid to the fee recipient of the block.
+    pub fn priority_fee(&self) -> u64 {
+        self.priority_fee_per_gas * self.gas_used
+    }
+
+    /// Portion of the fee that is burned (EIP-1559 base fee).
+    pub fn burned_fee(&self) -> u64 {
+        (self.effective_gas_price - self.priority_fee_per_gas) * self.gas_used
+    }
+}
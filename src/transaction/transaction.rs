@@ -1,14 +1,22 @@
 // EIP-2718 - multiple transaction formats via Recursive Length Prefix (RLP) encoding
 
+use crate::block::account::Account;
 use crate::transaction::errors::TransactionError;
-use alloy_primitives::{Address, Keccak256};
-use k256::ecdsa::signature::hazmat::PrehashVerifier;
+use alloy_primitives::{keccak256, Address, Bytes, Keccak256, B256, U256, U64};
+use alloy_rlp::{Decodable, Encodable, Header};
 use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
 
 pub const TRANSACTION_GAS_COST: u64 = 21000;
 pub const GWEI_TO_WEI: u64 = 1_000_000_000;
 pub const ETH_TO_WEI: u64 = GWEI_TO_WEI * 1_000_000_000;
+/// Type of the EIP-1559 transactions, the only type supported.
+pub const EIP1559_TRANSACTION_TYPE: u8 = 0x02;
 
+/// Addresses and storage keys a transaction intends to access (EIP-2930).
+pub type AccessList = Vec<(Address, Vec<B256>)>;
+
+#[derive(Clone, Debug)]
 pub struct Transaction {
     pub chain_id: u64,
     pub nonce: u64,
@@ -25,7 +33,7 @@ pub struct Transaction {
     pub input_data: Vec<u8>,
     // EIP-2930
     // list of addresses and storage keys transaction intends to access
-    // access_list: TBD
+    pub access_list: AccessList,
     pub signature_parity: bool,
     pub signature: [u8; 64],
 }
@@ -49,33 +57,54 @@ impl Transaction {
             to,
             value,
             input_data,
+            access_list: Vec::new(),
             signature_parity: false,
             signature: [0u8; 64],
         };
-        if private_key.is_some() {
-            tx.sign(&private_key.unwrap());
+        if let Some(private_key) = private_key {
+            tx.sign(private_key);
         }
         tx
     }
 
-    // Calculate the hash that will be signed
-    // This follows EIP-2718 and EIP-1559 transaction format
-    pub fn hash_for_signing(&self) -> Vec<u8> {
-        let mut hasher = Keccak256::new();
+    /// Signed transaction deploying a contract whose init code is `init_code`, endowed with
+    /// `value`.
+    pub fn deployment(
+        init_code: Vec<u8>,
+        value: u64,
+        gas_limit: u64,
+        max_priority_fee_per_gas: u64,
+        max_fee_per_gas: u64,
+        private_key: &SigningKey,
+    ) -> Self {
+        Self::new(
+            Address::ZERO,
+            value,
+            gas_limit,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            init_code,
+            Some(private_key),
+        )
+    }
 
-        // We use RLP encoding in practice, but for simplicity, we'll just concatenate fields
-        // In a real implementation, you'd want to use proper RLP encoding here
-        hasher.update([0x02]); // transaction type 2 (EIP-1559)
-        hasher.update(self.chain_id.to_be_bytes());
-        hasher.update(self.nonce.to_be_bytes());
-        hasher.update(self.max_priority_fee_per_gas.to_be_bytes());
-        hasher.update(self.max_fee_per_gas.to_be_bytes());
-        hasher.update(self.gas_limit.to_be_bytes());
-        hasher.update(self.to.into_word().as_slice());
-        hasher.update(self.value.to_be_bytes());
-        // In practice, we'd also include access_list and data field here
+    /// Whether the transaction deploys a contract, which it does when it has no recipient.
+    pub fn is_creation(&self) -> bool {
+        self.to.is_zero()
+    }
 
-        hasher.finalize().to_vec()
+    /// Hash signed by the sender: `keccak256(0x02 || rlp([chain_id, nonce,
+    /// max_priority_fee_per_gas, max_fee_per_gas, gas_limit, to, value, data, access_list]))`
+    /// (EIP-1559).
+    pub fn hash_for_signing(&self) -> Vec<u8> {
+        let mut out = vec![EIP1559_TRANSACTION_TYPE];
+        rlp_list(&self.unsigned_payload(), &mut out);
+        keccak256(out).to_vec()
+    }
+
+    /// Hash identifying the signed transaction, the hash of its network encoding.
+    pub fn hash(&self) -> B256 {
+        keccak256(self.to_rlp())
     }
 
     pub fn sign(&mut self, private_key: &SigningKey) {
@@ -89,49 +118,352 @@ impl Transaction {
         self.signature_parity = recovery_id.to_byte() == 1;
     }
 
-    pub fn verify_signature(&self) -> bool {
-        let signature = Signature::from_slice(&self.signature.as_slice()).unwrap();
-        if let Ok(verifying_key) = self.recover_verifying_key() {
-            verifying_key
-                .verify_prehash(self.hash_for_signing().as_slice(), &signature)
-                .unwrap();
-            true
-        } else {
-            false
-        }
-    }
+    /// Recovers the address of the sender from the signature.
+    ///
+    /// A successful recovery also proves that the signature is valid, so validation should call
+    /// this once and keep the address instead of verifying and recovering separately.
+    pub fn recover_sender(&self) -> Result<Address, TransactionError> {
+        #[cfg(test)]
+        RECOVERIES.with(|count| count.set(count.get() + 1));
 
-    fn recover_verifying_key(&self) -> Result<VerifyingKey, Box<dyn std::error::Error>> {
-        let recovery_id = RecoveryId::try_from(self.signature_parity as u8).unwrap();
-        let signature = Signature::from_slice(&self.signature.as_slice()).unwrap();
-        if let Ok(key) = VerifyingKey::recover_from_prehash(
+        let recovery_id = RecoveryId::try_from(self.signature_parity as u8)
+            .map_err(|_| TransactionError::InvalidSignature)?;
+        let signature = Signature::from_slice(self.signature.as_slice())
+            .map_err(|_| TransactionError::InvalidSignature)?;
+        let verifying_key = VerifyingKey::recover_from_prehash(
             self.hash_for_signing().as_slice(),
             &signature,
             recovery_id,
-        ) {
-            Ok(key)
+        )
+        .map_err(|_| TransactionError::InvalidSignature)?;
+
+        let public_key_bytes = verifying_key.to_encoded_point(false).as_bytes().to_vec();
+        let mut hasher = Keccak256::new();
+        hasher.update(&public_key_bytes[1..]); // Skip first byte of public key (0x04 prefix)
+        let hash = hasher.finalize();
+        Ok(Address::from_slice(&hash[12..]))
+    }
+
+    /// Serializes the transaction into the Ethereum JSON shape, with hex quantities and
+    /// `0x`-prefixed data.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&TransactionJson::from(self))
+            .expect("transaction serialization cannot fail")
+    }
+
+    /// Parses a transaction from the Ethereum JSON shape. Fields other than the signed payload
+    /// and the signature, such as `hash` or `blockNumber`, are ignored.
+    pub fn from_json(json: &str) -> Result<Self, TransactionError> {
+        let json: TransactionJson = serde_json::from_str(json)?;
+        Self::try_from(json)
+    }
+
+    /// Encodes the signed transaction as it is sent over the network:
+    /// `0x02 || rlp([chain_id, nonce, max_priority_fee_per_gas, max_fee_per_gas, gas_limit, to,
+    /// value, data, access_list, y_parity, r, s])` (EIP-2718, EIP-1559).
+    pub fn to_rlp(&self) -> Vec<u8> {
+        let mut payload = self.unsigned_payload();
+
+        self.signature_parity.encode(&mut payload);
+        // r and s are integers, encoded without their leading zeros
+        for word in self.signature.chunks(32) {
+            let leading_zeros = word.iter().take_while(|byte| **byte == 0).count();
+            word[leading_zeros..].encode(&mut payload);
+        }
+
+        let mut out = vec![EIP1559_TRANSACTION_TYPE];
+        rlp_list(&payload, &mut out);
+        out
+    }
+
+    /// RLP encoding of the fields covered by the signature, without the list header.
+    fn unsigned_payload(&self) -> Vec<u8> {
+        let mut payload = vec![];
+        self.chain_id.encode(&mut payload);
+        self.nonce.encode(&mut payload);
+        self.max_priority_fee_per_gas.encode(&mut payload);
+        self.max_fee_per_gas.encode(&mut payload);
+        self.gas_limit.encode(&mut payload);
+        // a creation has an empty recipient
+        if self.is_creation() {
+            [0u8; 0].encode(&mut payload);
         } else {
-            Err(Box::new(TransactionError::InvalidSignature))
+            self.to.into_array().encode(&mut payload);
+        }
+        self.value.encode(&mut payload);
+        self.input_data.as_slice().encode(&mut payload);
+
+        let mut access_list = vec![];
+        for (address, storage_keys) in &self.access_list {
+            let mut item = vec![];
+            address.into_array().encode(&mut item);
+            storage_keys
+                .iter()
+                .map(|key| key.0)
+                .collect::<Vec<_>>()
+                .encode(&mut item);
+            rlp_list(&item, &mut access_list);
         }
+        rlp_list(&access_list, &mut payload);
+        payload
+    }
+
+    /// Decodes a signed transaction encoded by `to_rlp`. The signature is not verified.
+    pub fn from_rlp(bytes: &[u8]) -> Result<Self, TransactionError> {
+        let Some((&EIP1559_TRANSACTION_TYPE, mut buf)) = bytes.split_first() else {
+            return Err(TransactionError::InvalidTransaction);
+        };
+        let mut payload = Header::decode_bytes(&mut buf, true)?;
+        if !buf.is_empty() {
+            return Err(alloy_rlp::Error::UnexpectedLength.into());
+        }
+        let payload = &mut payload;
+
+        let chain_id = u64::decode(payload)?;
+        let nonce = u64::decode(payload)?;
+        let max_priority_fee_per_gas = u64::decode(payload)?;
+        let max_fee_per_gas = u64::decode(payload)?;
+        let gas_limit = u64::decode(payload)?;
+        let to = match Header::decode_bytes(payload, false)? {
+            [] => Address::ZERO,
+            to if to.len() == 20 => Address::from_slice(to),
+            _ => return Err(alloy_rlp::Error::UnexpectedLength.into()),
+        };
+        let value = u64::decode(payload)?;
+        let input_data = Header::decode_bytes(payload, false)?.to_vec();
+
+        let mut access_list = vec![];
+        let mut items = Header::decode_bytes(payload, true)?;
+        while !items.is_empty() {
+            let mut item = Header::decode_bytes(&mut items, true)?;
+            let address = Address::from(<[u8; 20]>::decode(&mut item)?);
+            let storage_keys = Vec::<[u8; 32]>::decode(&mut item)?;
+            if !item.is_empty() {
+                return Err(alloy_rlp::Error::UnexpectedLength.into());
+            }
+            access_list.push((address, storage_keys.into_iter().map(B256::from).collect()));
+        }
+
+        let signature_parity = bool::decode(payload)?;
+        let mut signature = [0u8; 64];
+        for word in signature.chunks_mut(32) {
+            let value = Header::decode_bytes(payload, false)?;
+            if value.len() > 32 {
+                return Err(alloy_rlp::Error::Overflow.into());
+            }
+            word[32 - value.len()..].copy_from_slice(value);
+        }
+        if !payload.is_empty() {
+            return Err(alloy_rlp::Error::UnexpectedLength.into());
+        }
+
+        Ok(Self {
+            chain_id,
+            nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            to,
+            value,
+            input_data,
+            access_list,
+            signature_parity,
+            signature,
+        })
+    }
+
+    pub fn verify_signature(&self) -> bool {
+        self.recover_sender().is_ok()
     }
 
     pub fn get_sender_address(&self) -> Option<Address> {
-        if let Ok(verifying_key) = self.recover_verifying_key() {
-            let public_key_bytes = verifying_key.to_encoded_point(false).as_bytes().to_vec();
-            let mut hasher = Keccak256::new();
-            hasher.update(&public_key_bytes[1..]); // Skip first byte of public key (0x04 prefix)
-            let hash = hasher.finalize();
-            Some(Address::from_slice(&hash[12..]))
-        } else {
-            None
+        self.recover_sender().ok()
+    }
+
+    /// Price paid per unit of gas under the given base fee: the base fee plus the priority fee,
+    /// capped by the maximum fee.
+    pub fn effective_gas_price(&self, base_fee: u64) -> u64 {
+        self.max_fee_per_gas
+            .min(base_fee.saturating_add(self.max_priority_fee_per_gas))
+    }
+
+    /// Checks that the transaction can be executed next by the sender `account` under the
+    /// given base fee. The signature isn't checked, recovering the sender does that.
+    pub fn validate(&self, account: &Account, base_fee: u64) -> Result<(), TransactionError> {
+        if self.nonce < account.nonce {
+            return Err(TransactionError::NonceTooLow);
+        }
+        if self.nonce > account.nonce {
+            return Err(TransactionError::NonceTooHigh);
+        }
+        self.validate_pending(account, base_fee)
+    }
+
+    /// Like `validate`, but accepts a nonce that is still to be reached, for transactions
+    /// waiting behind others of the same sender.
+    pub fn validate_pending(
+        &self,
+        account: &Account,
+        base_fee: u64,
+    ) -> Result<(), TransactionError> {
+        // EIP-2681: the nonce can't be bumped past its maximum
+        if self.nonce == u64::MAX {
+            return Err(TransactionError::NonceMax);
+        }
+        if self.max_priority_fee_per_gas > self.max_fee_per_gas {
+            return Err(TransactionError::PriorityFeeExceedsMaxFee);
+        }
+        if base_fee > self.max_fee_per_gas {
+            return Err(TransactionError::MaximumGasFeeBelowBaseFee);
+        }
+        if self.gas_limit < TRANSACTION_GAS_COST {
+            return Err(TransactionError::InsufficientGas);
         }
+        // a decoded transaction may carry fields no balance could ever cover
+        let total_cost = self
+            .gas_limit
+            .checked_mul(self.effective_gas_price(base_fee))
+            .and_then(|upfront_fee| upfront_fee.checked_add(self.value));
+        match total_cost {
+            Some(total_cost) if account.balance >= total_cost => Ok(()),
+            _ => Err(TransactionError::InsufficientBalance),
+        }
+    }
+}
+
+/// Two transactions are equal when they carry the same payload from the same sender. The
+/// signatures themselves aren't compared, as the sender is what they attest to.
+impl PartialEq for Transaction {
+    fn eq(&self, other: &Self) -> bool {
+        self.chain_id == other.chain_id
+            && self.nonce == other.nonce
+            && self.max_priority_fee_per_gas == other.max_priority_fee_per_gas
+            && self.max_fee_per_gas == other.max_fee_per_gas
+            && self.gas_limit == other.gas_limit
+            && self.to == other.to
+            && self.value == other.value
+            && self.input_data == other.input_data
+            && self.access_list == other.access_list
+            && self.get_sender_address() == other.get_sender_address()
     }
 }
 
+/// Appends `payload` to `out` as an RLP list.
+fn rlp_list(payload: &[u8], out: &mut Vec<u8>) {
+    Header {
+        list: true,
+        payload_length: payload.len(),
+    }
+    .encode(out);
+    out.extend_from_slice(payload);
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessListItemJson {
+    address: Address,
+    storage_keys: Vec<B256>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionJson {
+    #[serde(rename = "type")]
+    transaction_type: U64,
+    chain_id: U64,
+    nonce: U64,
+    max_priority_fee_per_gas: U64,
+    max_fee_per_gas: U64,
+    gas: U64,
+    // null for contract creations
+    to: Option<Address>,
+    value: U256,
+    input: Bytes,
+    #[serde(default)]
+    access_list: Vec<AccessListItemJson>,
+    y_parity: U64,
+    r: U256,
+    s: U256,
+}
+
+impl From<&Transaction> for TransactionJson {
+    fn from(transaction: &Transaction) -> Self {
+        Self {
+            transaction_type: U64::from(EIP1559_TRANSACTION_TYPE),
+            chain_id: U64::from(transaction.chain_id),
+            nonce: U64::from(transaction.nonce),
+            max_priority_fee_per_gas: U64::from(transaction.max_priority_fee_per_gas),
+            max_fee_per_gas: U64::from(transaction.max_fee_per_gas),
+            gas: U64::from(transaction.gas_limit),
+            to: (!transaction.is_creation()).then_some(transaction.to),
+            value: U256::from(transaction.value),
+            input: Bytes::copy_from_slice(&transaction.input_data),
+            access_list: transaction
+                .access_list
+                .iter()
+                .map(|(address, storage_keys)| AccessListItemJson {
+                    address: *address,
+                    storage_keys: storage_keys.clone(),
+                })
+                .collect(),
+            y_parity: U64::from(transaction.signature_parity as u8),
+            r: U256::from_be_slice(&transaction.signature[..32]),
+            s: U256::from_be_slice(&transaction.signature[32..]),
+        }
+    }
+}
+
+impl TryFrom<TransactionJson> for Transaction {
+    type Error = TransactionError;
+
+    fn try_from(json: TransactionJson) -> Result<Self, Self::Error> {
+        if json.transaction_type != U64::from(EIP1559_TRANSACTION_TYPE)
+            || json.y_parity > U64::from(1)
+        {
+            return Err(TransactionError::InvalidTransaction);
+        }
+        let quantity = |value: U64| value.to::<u64>();
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&json.r.to_be_bytes::<32>());
+        signature[32..].copy_from_slice(&json.s.to_be_bytes::<32>());
+
+        Ok(Self {
+            chain_id: quantity(json.chain_id),
+            nonce: quantity(json.nonce),
+            max_priority_fee_per_gas: quantity(json.max_priority_fee_per_gas),
+            max_fee_per_gas: quantity(json.max_fee_per_gas),
+            gas_limit: quantity(json.gas),
+            to: json.to.unwrap_or(Address::ZERO),
+            value: json
+                .value
+                .try_into()
+                .map_err(|_| TransactionError::InvalidTransaction)?,
+            input_data: json.input.to_vec(),
+            access_list: json
+                .access_list
+                .into_iter()
+                .map(|item| (item.address, item.storage_keys))
+                .collect(),
+            signature_parity: json.y_parity == U64::from(1),
+            signature,
+        })
+    }
+}
+
+// Number of sender recoveries performed on the current thread
+#[cfg(test)]
+thread_local! {
+    pub(crate) static RECOVERIES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::block::account::EMPTY_CODE_HASH;
     use crate::crypto::wallet::Wallet;
+    use crate::transaction::builder::TransactionBuilder;
+    use alloy_primitives::address;
 
     #[test]
     fn test_sign_verify() {
@@ -147,4 +479,234 @@ mod tests {
         );
         assert!(tx.verify_signature());
     }
+
+    #[test]
+    fn test_signature_covers_input_data() {
+        let wallet = Wallet::generate();
+        let tx = Transaction::new(
+            Wallet::generate().address,
+            100,
+            50_000,
+            100,
+            100,
+            vec![0xa9, 0x05, 0x9c, 0xbb],
+            Some(&wallet.private_key),
+        );
+        assert_eq!(tx.recover_sender().unwrap(), wallet.address);
+
+        let mut tampered = tx.clone();
+        tampered.input_data = vec![0xde, 0xad, 0xbe, 0xef];
+        assert_ne!(tampered.recover_sender().ok(), Some(wallet.address));
+        assert_ne!(tampered.hash(), tx.hash());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let eth_wallet = Wallet::generate();
+        let mut tx = Transaction::new(Address::ZERO, 100, 21000, 100, 200, vec![0x60, 0x00], None);
+        tx.nonce = 3;
+        tx.access_list = vec![(eth_wallet.address, vec![B256::with_last_byte(1)])];
+        tx.sign(&eth_wallet.private_key);
+
+        let json = tx.to_json();
+        assert!(json.contains("\"to\":null"));
+        assert!(json.contains("\"input\":\"0x6000\""));
+
+        let decoded = Transaction::from_json(&json).unwrap();
+        assert_eq!(decoded.hash(), tx.hash());
+        assert_eq!(decoded.input_data, tx.input_data);
+        assert_eq!(decoded.recover_sender().unwrap(), eth_wallet.address);
+    }
+
+    #[test]
+    fn test_rlp_round_trip() {
+        let eth_wallet = Wallet::generate();
+        let mut tx = Transaction::new(
+            eth_wallet.address,
+            100,
+            21000,
+            100,
+            200,
+            vec![0x60, 0x00],
+            None,
+        );
+        tx.chain_id = 1;
+        tx.nonce = 1024;
+        tx.access_list = vec![
+            (
+                Address::repeat_byte(0xaa),
+                vec![B256::with_last_byte(1), B256::ZERO],
+            ),
+            (Address::repeat_byte(0xbb), vec![]),
+        ];
+        tx.sign(&eth_wallet.private_key);
+
+        let creation =
+            Transaction::deployment(vec![0x00], 0, 100_000, 0, 10, &eth_wallet.private_key);
+        for tx in [tx, creation] {
+            let encoded = tx.to_rlp();
+            assert_eq!(encoded[0], EIP1559_TRANSACTION_TYPE);
+
+            let decoded = Transaction::from_rlp(&encoded).unwrap();
+            assert_eq!(decoded.hash(), tx.hash());
+            assert_eq!(decoded.to, tx.to);
+            assert_eq!(decoded.input_data, tx.input_data);
+            assert_eq!(decoded.access_list, tx.access_list);
+            assert_eq!(decoded.recover_sender().unwrap(), eth_wallet.address);
+            assert_eq!(decoded.to_rlp(), encoded);
+
+            // a truncated or padded encoding is rejected
+            assert!(matches!(
+                Transaction::from_rlp(&encoded[..encoded.len() - 1]),
+                Err(TransactionError::InvalidRlp(_))
+            ));
+            let mut padded = encoded.clone();
+            padded.push(0x00);
+            assert!(Transaction::from_rlp(&padded).is_err());
+        }
+        assert!(matches!(
+            Transaction::from_rlp(&[0x01, 0xc0]),
+            Err(TransactionError::InvalidTransaction)
+        ));
+    }
+
+    #[test]
+    fn test_equality_compares_senders() {
+        let sender = Wallet::generate();
+        let tx = Transaction::new(
+            Address::repeat_byte(0xaa),
+            100,
+            21000,
+            1,
+            10,
+            vec![0x60, 0x00],
+            Some(&sender.private_key),
+        );
+        assert_eq!(tx.clone(), tx);
+        assert_eq!(Transaction::from_rlp(&tx.to_rlp()).unwrap(), tx);
+
+        let mut other_value = tx.clone();
+        other_value.value += 1;
+        other_value.sign(&sender.private_key);
+        assert_ne!(other_value, tx);
+
+        let mut other_sender = tx.clone();
+        other_sender.sign(&Wallet::generate().private_key);
+        assert_ne!(other_sender, tx);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut account = Account::new(1_000_000, EMPTY_CODE_HASH, B256::ZERO);
+        account.nonce = 5;
+        let base_fee = 10;
+        let transaction = || {
+            TransactionBuilder::new()
+                .nonce(5)
+                .value(1000)
+                .gas_limit(TRANSACTION_GAS_COST)
+                .max_priority_fee_per_gas(2)
+                .max_fee_per_gas(20)
+        };
+        let validate =
+            |builder: TransactionBuilder| builder.build().unwrap().validate(&account, base_fee);
+
+        // 21000 gas at 12 wei plus the value
+        assert!(validate(transaction()).is_ok());
+        let mut poor = account.clone();
+        poor.balance = TRANSACTION_GAS_COST * 12 + 999;
+        assert!(matches!(
+            transaction().build().unwrap().validate(&poor, base_fee),
+            Err(TransactionError::InsufficientBalance)
+        ));
+
+        assert!(matches!(
+            validate(transaction().nonce(4)),
+            Err(TransactionError::NonceTooLow)
+        ));
+        assert!(matches!(
+            validate(transaction().nonce(6)),
+            Err(TransactionError::NonceTooHigh)
+        ));
+        let mut exhausted = account.clone();
+        exhausted.nonce = u64::MAX;
+        assert!(matches!(
+            transaction()
+                .nonce(u64::MAX)
+                .build()
+                .unwrap()
+                .validate(&exhausted, base_fee),
+            Err(TransactionError::NonceMax)
+        ));
+        assert!(matches!(
+            validate(transaction().max_fee_per_gas(9).max_priority_fee_per_gas(0)),
+            Err(TransactionError::MaximumGasFeeBelowBaseFee)
+        ));
+        assert!(matches!(
+            validate(transaction().gas_limit(TRANSACTION_GAS_COST - 1)),
+            Err(TransactionError::InsufficientGas)
+        ));
+        // the builder refuses such a transaction, but a decoded one may still carry it
+        let mut overpriced = transaction().build().unwrap();
+        overpriced.max_priority_fee_per_gas = 21;
+        assert!(matches!(
+            overpriced.validate(&account, base_fee),
+            Err(TransactionError::PriorityFeeExceedsMaxFee)
+        ));
+
+        // the upfront cost of a raw transaction can exceed any balance
+        let mut greedy = transaction().build().unwrap();
+        greedy.gas_limit = u64::MAX;
+        greedy.sign(&Wallet::generate().private_key);
+        let greedy = Transaction::from_rlp(&greedy.to_rlp()).unwrap();
+        let mut rich = account.clone();
+        rich.balance = u64::MAX;
+        assert!(matches!(
+            greedy.validate(&rich, base_fee),
+            Err(TransactionError::InsufficientBalance)
+        ));
+
+        // a transaction waiting behind others of the sender only fails on the other checks
+        assert!(transaction()
+            .nonce(6)
+            .build()
+            .unwrap()
+            .validate_pending(&account, base_fee)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_json_decode_recovers_sender() {
+        // signed with the private key 0x1111...11, with explorer-only fields mixed in
+        let json = r#"{
+            "blockNumber": "0x10",
+            "from": "0x19e7e376e7c213b7e7e7e46cc70a5dd086daff2a",
+            "type": "0x2",
+            "chainId": "0x1",
+            "nonce": "0x7",
+            "maxPriorityFeePerGas": "0x3b9aca00",
+            "maxFeePerGas": "0x6fc23ac00",
+            "gas": "0x5208",
+            "to": "0xd8da6bf26964af9d7eed9e03e53415d37aa96045",
+            "value": "0x38d7ea4c68000",
+            "input": "0x",
+            "accessList": [],
+            "yParity": "0x0",
+            "r": "0xaa1692addfc39b4d7be58478ea8b2c2f771c7ed316e70c0876672811a5ed3a77",
+            "s": "0x12f16229da71432503758c3e44db9d4143b1ca4a45d781fdc83967472a5b624e"
+        }"#;
+
+        let tx = Transaction::from_json(json).unwrap();
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.value, 1_000_000_000_000_000);
+        assert_eq!(
+            tx.recover_sender().unwrap(),
+            address!("19e7e376e7c213b7e7e7e46cc70a5dd086daff2a")
+        );
+
+        assert!(matches!(
+            Transaction::from_json("{}"),
+            Err(TransactionError::InvalidJson(_))
+        ));
+    }
 }
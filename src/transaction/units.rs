@@ -0,0 +1,93 @@
+// Human readable ether amounts, e.g. `1.5 ether` or `20 gwei`.
+
+use alloy_primitives::U256;
+
+use crate::transaction::errors::TransactionError;
+
+/// Parses an amount like `1.5 ether`, `20 gwei` or `100 wei` into wei. An amount without a
+/// unit is in wei.
+pub fn parse_ether(input: &str) -> Result<U256, TransactionError> {
+    let invalid = || TransactionError::InvalidAmount(input.to_string());
+
+    let mut parts = input.split_whitespace();
+    let amount = parts.next().ok_or_else(invalid)?;
+    let decimals = match parts.next() {
+        None | Some("wei") => 0,
+        Some("gwei") => 9,
+        Some("ether") | Some("eth") => 18,
+        Some(_) => return Err(invalid()),
+    };
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+
+    let (integer, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_number = |digits: &str| digits.bytes().all(|byte| byte.is_ascii_digit());
+    if integer.is_empty()
+        || !is_number(integer)
+        || !is_number(fraction)
+        || (amount.contains('.') && fraction.is_empty())
+        || fraction.len() > decimals
+    {
+        return Err(invalid());
+    }
+
+    // shift the decimal point out of the amount, e.g. 1.5 ether is 15 * 10^17 wei
+    let digits = format!("{}{}", integer, fraction);
+    let scale = U256::from(10).pow(U256::from(decimals - fraction.len()));
+    U256::from_str_radix(&digits, 10)
+        .ok()
+        .and_then(|value| value.checked_mul(scale))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::transaction::{ETH_TO_WEI, GWEI_TO_WEI};
+
+    #[test]
+    fn test_parse_ether() {
+        assert_eq!(parse_ether("1 ether").unwrap(), U256::from(ETH_TO_WEI));
+        assert_eq!(
+            parse_ether("20 gwei").unwrap(),
+            U256::from(20 * GWEI_TO_WEI)
+        );
+        assert_eq!(
+            parse_ether("1.5 ether").unwrap(),
+            U256::from(3 * ETH_TO_WEI / 2)
+        );
+        assert_eq!(
+            parse_ether("0.000000001 ether").unwrap(),
+            U256::from(GWEI_TO_WEI)
+        );
+        assert_eq!(
+            parse_ether("2.25 gwei").unwrap(),
+            U256::from(2_250_000_000u64)
+        );
+        assert_eq!(parse_ether("42").unwrap(), U256::from(42));
+
+        for malformed in [
+            "",
+            "ether",
+            "1.5 wei",
+            "1. ether",
+            ".5 ether",
+            "1.2.3 ether",
+            "-1 ether",
+            "1e18",
+            "1 finney",
+            "1 ether extra",
+            "0.0000000000000000001 ether",
+        ] {
+            assert!(
+                matches!(
+                    parse_ether(malformed),
+                    Err(TransactionError::InvalidAmount(_))
+                ),
+                "{:?} should be rejected",
+                malformed
+            );
+        }
+    }
+}